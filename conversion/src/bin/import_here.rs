@@ -3,7 +3,7 @@
 use std::{env, error::Error, path::Path, str::FromStr};
 
 use conversion::here::{csv_source::CSVSource, read_graph};
-use rust_road_router::{cli::CliErr, io::*};
+use rust_road_router::cli::CliErr;
 
 fn main() -> Result<(), Box<dyn Error>> {
     let mut args = env::args().skip(1);
@@ -18,22 +18,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let source = CSVSource::new(Path::new(in_dir));
     let data = read_graph(&source, (min_lat, min_lon), (max_lat, max_lon));
-    data.graph.deconstruct_to(out_dir)?;
-    let out_dir = Path::new(out_dir);
-
-    data.graph.weight().write_to(&out_dir.join("travel_time"))?;
-    data.functional_road_classes.write_to(&out_dir.join("functional_road_classes"))?;
-    data.lat.write_to(&out_dir.join("latitude"))?;
-    data.lng.write_to(&out_dir.join("longitude"))?;
-    data.link_id_mapping.write_to(&out_dir.join("link_id_mapping"))?;
-    data.here_rank_to_link_id.write_to(&out_dir.join("here_rank_to_link_id"))?;
-    data.link_lengths
-        .into_iter()
-        .map(|d| d as u32)
-        .collect::<Box<[_]>>()
-        .write_to(&out_dir.join("geo_distance"))?;
-    [1000].write_to(&out_dir.join("tt_units_per_s"))?;
-    [1].write_to(&out_dir.join("dist_units_per_m"))?;
+    data.write_to_dir(Path::new(out_dir))?;
 
     Ok(())
 }
@@ -0,0 +1,30 @@
+// Program to convert OpenStreetMap data from a `.osm.pbf` file into RoutingKit data structures.
+// Takes the input file, the output directory and optionally a car profile file (see `CarProfile::from_file`).
+
+use std::{env, error::Error, path::Path};
+
+use conversion::osm::{read_pbf, CarProfile};
+use rust_road_router::cli::CliErr;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args().skip(1);
+
+    let in_file = &args.next().ok_or(CliErr("No input file arg given"))?;
+    let out_dir = &args.next().ok_or(CliErr("No output directory arg given"))?;
+    let profile = match args.next() {
+        Some(profile_file) => CarProfile::from_file(Path::new(&profile_file))?,
+        None => CarProfile::default(),
+    };
+
+    let data = read_pbf(Path::new(in_file), &profile)?;
+    data.write_to_dir(Path::new(out_dir))?;
+
+    eprintln!(
+        "{} nodes, {} arcs, {} forbidden turns",
+        data.latitude.len(),
+        data.head.len(),
+        data.forbidden_turn_from_arc.len()
+    );
+
+    Ok(())
+}
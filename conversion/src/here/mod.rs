@@ -1,9 +1,11 @@
 use rust_road_router::datastr::graph::*;
 use rust_road_router::datastr::rank_select_map::{BitVec, RankSelectMap};
+use rust_road_router::io::*;
 use rust_road_router::util::in_range_option::*;
 use std::error::Error;
 use std::fmt;
 use std::iter;
+use std::path::Path;
 use std::str::FromStr;
 
 use nav_types::WGS84;
@@ -146,6 +148,27 @@ pub struct HereData {
     pub here_rank_to_link_id: Vec<(InRangeOption<EdgeId>, InRangeOption<EdgeId>)>,
 }
 
+impl HereData {
+    /// Write the graph and all additional data in the RoutingKit format to `out_dir`.
+    pub fn write_to_dir(&self, out_dir: &Path) -> std::io::Result<()> {
+        self.graph.deconstruct_to(&out_dir)?;
+        self.graph.weight().write_to(&out_dir.join("travel_time"))?;
+        self.functional_road_classes.write_to(&out_dir.join("functional_road_classes"))?;
+        self.lat.write_to(&out_dir.join("latitude"))?;
+        self.lng.write_to(&out_dir.join("longitude"))?;
+        self.link_id_mapping.write_to(&out_dir.join("link_id_mapping"))?;
+        self.here_rank_to_link_id.write_to(&out_dir.join("here_rank_to_link_id"))?;
+        self.link_lengths
+            .iter()
+            .map(|&d| d as u32)
+            .collect::<Box<[_]>>()
+            .write_to(&out_dir.join("geo_distance"))?;
+        [1000].write_to(&out_dir.join("tt_units_per_s"))?;
+        [1].write_to(&out_dir.join("dist_units_per_m"))?;
+        Ok(())
+    }
+}
+
 pub trait RdfDataSource {
     fn links(&self) -> Vec<RdfLink>;
     fn nav_links(&self) -> Vec<RdfNavLink>;
@@ -0,0 +1,579 @@
+//! Import of OpenStreetMap data into RoutingKit style graphs.
+//!
+//! Ways are filtered and weighted by a `CarProfile`.
+//! Only nodes where ways intersect or end become nodes of the graph, the nodes in between only contribute to the arc lengths.
+//! Turn restrictions from `restriction` relations with a node as `via` member become forbidden turns.
+//! Height, weight, dangerous goods and vehicle class restrictions of ways become `LinkAttributes` for vehicle profiles.
+
+use rust_road_router::{
+    datastr::{
+        graph::*,
+        vehicle_restrictions::{hazmat, vehicle_class, LinkAttributes, NO_LIMIT},
+    },
+    io::*,
+};
+use std::{collections::HashMap, error::Error, fs, io, path::Path};
+
+use nav_types::WGS84;
+
+pub mod pbf;
+use pbf::{MemberType, Relation, Tags, Visitor, Way};
+
+/// Which ways are routable for cars and how fast they are.
+#[derive(Debug, Clone)]
+pub struct CarProfile {
+    /// Speed in km/h for each routable `highway` value, ways with other values are ignored
+    pub highway_speeds: Vec<(String, u32)>,
+    /// Use the `maxspeed` tag instead of the highway speed where it can be parsed
+    pub use_maxspeed: bool,
+    /// Values of `access`, `vehicle`, `motor_vehicle` and `motorcar` which exclude a way
+    pub blocked_access: Vec<String>,
+}
+
+impl Default for CarProfile {
+    fn default() -> Self {
+        let highway_speeds = [
+            ("motorway", 90),
+            ("motorway_link", 45),
+            ("trunk", 85),
+            ("trunk_link", 40),
+            ("primary", 65),
+            ("primary_link", 30),
+            ("secondary", 55),
+            ("secondary_link", 25),
+            ("tertiary", 40),
+            ("tertiary_link", 20),
+            ("unclassified", 25),
+            ("residential", 25),
+            ("living_street", 10),
+            ("service", 8),
+            ("road", 20),
+        ];
+        CarProfile {
+            highway_speeds: highway_speeds.iter().map(|&(highway, speed)| (highway.to_string(), speed)).collect(),
+            use_maxspeed: true,
+            blocked_access: ["no", "private", "agricultural", "forestry", "delivery", "emergency", "psv"]
+                .iter()
+                .map(|value| value.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl CarProfile {
+    /// Read a profile from a text file.
+    /// Each line contains a highway value and its speed in km/h, for example `primary 65`.
+    /// Additionally, `maxspeed yes|no` switches the use of `maxspeed` tags and `blocked_access VALUE` adds a blocking access value.
+    /// Empty lines and lines starting with `#` are ignored.
+    /// The highway speeds of the default profile are replaced by those in the file.
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut profile = CarProfile {
+            highway_speeds: Vec::new(),
+            ..CarProfile::default()
+        };
+        for line in fs::read_to_string(path)?.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some("maxspeed"), Some(value), None) => profile.use_maxspeed = value == "yes",
+                (Some("blocked_access"), Some(value), None) => profile.blocked_access.push(value.to_string()),
+                (Some(highway), Some(speed), None) => profile.highway_speeds.push((highway.to_string(), speed.parse()?)),
+                _ => return Err(format!("Invalid profile line: {}", line).into()),
+            }
+        }
+        Ok(profile)
+    }
+
+    /// Speed in km/h and whether the way can be driven forward and backward, `None` if cars may not use it.
+    pub fn way_properties(&self, way: &Way) -> Option<(u32, bool, bool)> {
+        let tags = &way.tags;
+        let highway = tags.get("highway")?;
+        let highway_speed = self.highway_speeds.iter().find(|(h, _)| h == highway)?.1;
+        if tags.get("area") == Some("yes") {
+            return None;
+        }
+        // the most specific access tag decides
+        let access = ["motorcar", "motor_vehicle", "vehicle", "access"].iter().find_map(|&key| tags.get(key));
+        if access.is_some_and(|access| self.blocked_access.iter().any(|blocked| blocked == access)) {
+            return None;
+        }
+
+        let speed = if self.use_maxspeed {
+            tags.get("maxspeed").and_then(parse_maxspeed).unwrap_or(highway_speed)
+        } else {
+            highway_speed
+        };
+        if speed == 0 {
+            return None;
+        }
+
+        let implied_oneway = highway == "motorway" || tags.get("junction") == Some("roundabout");
+        let (forward, backward) = match tags.get("oneway") {
+            Some("yes") | Some("true") | Some("1") => (true, false),
+            Some("-1") | Some("reverse") => (false, true),
+            Some("no") | Some("false") | Some("0") => (true, true),
+            _ => (true, !implied_oneway),
+        };
+
+        Some((speed, forward, backward))
+    }
+}
+
+/// Speed in km/h of a `maxspeed` value, `None` if it can not be interpreted, for example `none` or `signals`.
+fn parse_maxspeed(value: &str) -> Option<u32> {
+    let value = value.trim();
+    if let Some(mph) = value.strip_suffix("mph") {
+        return mph.trim().parse::<f64>().ok().map(|mph| (mph * 1.609).round() as u32);
+    }
+    if let Ok(kmh) = value.parse::<f64>() {
+        return Some(kmh.round() as u32);
+    }
+    // implicit limits like `DE:urban`
+    match value.split(':').nth(1)? {
+        "living_street" => Some(7),
+        "walk" => Some(5),
+        "urban" => Some(50),
+        "rural" => Some(90),
+        _ => None,
+    }
+}
+
+// Restrictions of a way for vehicles other than cars, in the units of `LinkAttributes`.
+#[derive(Debug, Clone, Copy)]
+struct WayRestrictions {
+    max_height: u32,
+    max_weight: u32,
+    forbidden_hazmat: u8,
+    allowed_vehicle_classes: u32,
+}
+
+impl WayRestrictions {
+    fn from_tags(tags: &Tags) -> Self {
+        let mut forbidden_hazmat = 0;
+        if tags.get("hazmat") == Some("no") {
+            forbidden_hazmat |= hazmat::ALL;
+        }
+        if tags.get("hazmat:water") == Some("no") {
+            forbidden_hazmat |= hazmat::WATER_POLLUTING;
+        }
+        let mut allowed_vehicle_classes = vehicle_class::ALL;
+        if tags.get("hgv") == Some("no") {
+            allowed_vehicle_classes &= !vehicle_class::TRUCK;
+        }
+        if tags.get("bus") == Some("no") {
+            allowed_vehicle_classes &= !vehicle_class::BUS;
+        }
+
+        WayRestrictions {
+            max_height: tags.get("maxheight").and_then(parse_maxheight).unwrap_or(NO_LIMIT),
+            max_weight: tags.get("maxweight").and_then(parse_maxweight).unwrap_or(NO_LIMIT),
+            forbidden_hazmat,
+            allowed_vehicle_classes,
+        }
+    }
+}
+
+/// Height in cm of a `maxheight` value in meters or in feet and inches like `12'6"`, `None` for `none`, `default` and unknown values.
+fn parse_maxheight(value: &str) -> Option<u32> {
+    let value = value.trim();
+    if let Some((feet, inches)) = value.split_once('\'') {
+        let feet: f64 = feet.trim().parse().ok()?;
+        let inches: f64 = match inches.trim().trim_end_matches('"') {
+            "" => 0.0,
+            inches => inches.parse().ok()?,
+        };
+        return Some(((feet * 12.0 + inches) * 2.54).round() as u32);
+    }
+    let meters: f64 = value.trim_end_matches('m').trim().parse().ok()?;
+    Some((meters * 100.0).round() as u32)
+}
+
+/// Weight in kg of a `maxweight` value, in tons unless `kg` is given.
+fn parse_maxweight(value: &str) -> Option<u32> {
+    let value = value.trim();
+    if let Some(kg) = value.strip_suffix("kg") {
+        return kg.trim().parse::<f64>().ok().map(|kg| kg.round() as u32);
+    }
+    let tons: f64 = value.trim_end_matches('t').trim().parse().ok()?;
+    Some((tons * 1000.0).round() as u32)
+}
+
+/// Graph imported from OSM in the RoutingKit format.
+/// `travel_time` is in ms and `geo_distance` in meters.
+pub struct OsmData {
+    pub first_out: Vec<EdgeId>,
+    pub head: Vec<NodeId>,
+    pub travel_time: Vec<Weight>,
+    pub geo_distance: Vec<Weight>,
+    pub latitude: Vec<f32>,
+    pub longitude: Vec<f32>,
+    /// Sorted by from arc and then by to arc
+    pub forbidden_turn_from_arc: Vec<EdgeId>,
+    pub forbidden_turn_to_arc: Vec<EdgeId>,
+    pub attributes: LinkAttributes,
+}
+
+impl OsmData {
+    /// Write the graph and all additional data in the RoutingKit format to `out_dir`.
+    pub fn write_to_dir(&self, out_dir: &Path) -> io::Result<()> {
+        if !out_dir.exists() {
+            fs::create_dir(out_dir)?;
+        }
+
+        self.first_out.write_to(&out_dir.join("first_out"))?;
+        self.head.write_to(&out_dir.join("head"))?;
+        self.travel_time.write_to(&out_dir.join("travel_time"))?;
+        self.geo_distance.write_to(&out_dir.join("geo_distance"))?;
+        self.latitude.write_to(&out_dir.join("latitude"))?;
+        self.longitude.write_to(&out_dir.join("longitude"))?;
+        self.forbidden_turn_from_arc.write_to(&out_dir.join("forbidden_turn_from_arc"))?;
+        self.forbidden_turn_to_arc.write_to(&out_dir.join("forbidden_turn_to_arc"))?;
+        self.attributes.deconstruct_to(&out_dir)?;
+        [1000].write_to(&out_dir.join("tt_units_per_s"))?;
+        [1].write_to(&out_dir.join("dist_units_per_m"))?;
+        Ok(())
+    }
+}
+
+struct RoutableWay {
+    id: i64,
+    refs: Vec<i64>,
+    speed: u32,
+    forward: bool,
+    backward: bool,
+    restrictions: WayRestrictions,
+}
+
+enum RestrictionKind {
+    No,
+    Only,
+}
+
+struct Restriction {
+    kind: RestrictionKind,
+    from_way: i64,
+    via_node: i64,
+    to_way: i64,
+}
+
+struct WayCollector<'p> {
+    profile: &'p CarProfile,
+    ways: Vec<RoutableWay>,
+    restrictions: Vec<Restriction>,
+}
+
+impl Visitor for WayCollector<'_> {
+    fn wants_nodes(&self) -> bool {
+        false
+    }
+
+    fn way(&mut self, way: &Way) {
+        if way.refs.len() < 2 {
+            return;
+        }
+        if let Some((speed, forward, backward)) = self.profile.way_properties(way) {
+            self.ways.push(RoutableWay {
+                id: way.id,
+                refs: way.refs.clone(),
+                speed,
+                forward,
+                backward,
+                restrictions: WayRestrictions::from_tags(&way.tags),
+            });
+        }
+    }
+
+    fn relation(&mut self, relation: &Relation) {
+        if relation.tags.get("type") != Some("restriction") {
+            return;
+        }
+        if relation
+            .tags
+            .get("except")
+            .is_some_and(|except| except.split(';').any(|vehicle| vehicle.trim() == "motorcar"))
+        {
+            return;
+        }
+        let restriction = match relation.tags.get("restriction:motorcar").or_else(|| relation.tags.get("restriction")) {
+            Some(restriction) => restriction,
+            None => return,
+        };
+        let kind = if restriction.starts_with("no_") {
+            RestrictionKind::No
+        } else if restriction.starts_with("only_") {
+            RestrictionKind::Only
+        } else {
+            return;
+        };
+
+        let member = |role: &str, member_type: MemberType| {
+            let mut members = relation.members.iter().filter(|member| member.role == role);
+            match (members.next(), members.next()) {
+                (Some(member), None) if member.member_type == member_type => Some(member.id),
+                _ => None,
+            }
+        };
+        // restrictions with a way as via member are not supported
+        if let (Some(from_way), Some(via_node), Some(to_way)) =
+            (member("from", MemberType::Way), member("via", MemberType::Node), member("to", MemberType::Way))
+        {
+            self.restrictions.push(Restriction {
+                kind,
+                from_way,
+                via_node,
+                to_way,
+            });
+        }
+    }
+}
+
+// Coordinates of all nodes used by routable ways, indexed like the sorted node ids.
+struct NodeCollector<'a> {
+    ids: &'a [i64],
+    coords: Vec<Option<(f64, f64)>>,
+}
+
+impl Visitor for NodeCollector<'_> {
+    fn wants_ways(&self) -> bool {
+        false
+    }
+    fn wants_relations(&self) -> bool {
+        false
+    }
+    fn node(&mut self, node: pbf::Node) {
+        if let Ok(idx) = self.ids.binary_search(&node.id) {
+            self.coords[idx] = Some((node.lat, node.lng));
+        }
+    }
+}
+
+/// Import the road network for cars from an `.osm.pbf` file.
+/// Needs two passes over the file, one for ways and relations and one for nodes.
+pub fn read_pbf(path: &Path, profile: &CarProfile) -> io::Result<OsmData> {
+    eprintln!("read ways and relations");
+    let mut collector = WayCollector {
+        profile,
+        ways: Vec::new(),
+        restrictions: Vec::new(),
+    };
+    pbf::read(path, &mut collector)?;
+    let WayCollector { ways, restrictions, .. } = collector;
+
+    eprintln!("find routing nodes");
+    let mut node_ids: Vec<i64> = ways.iter().flat_map(|way| way.refs.iter().copied()).collect();
+    node_ids.sort_unstable();
+    node_ids.dedup();
+
+    // Nodes used more than once, either by several ways or several times by the same way, and the ends of ways become routing nodes.
+    let mut num_uses = vec![0u8; node_ids.len()];
+    for way in &ways {
+        for (i, node) in way.refs.iter().enumerate() {
+            let uses = &mut num_uses[node_ids.binary_search(node).unwrap()];
+            *uses = uses.saturating_add(if i == 0 || i == way.refs.len() - 1 { 2 } else { 1 });
+        }
+    }
+
+    eprintln!("read nodes");
+    let mut nodes = NodeCollector {
+        ids: &node_ids,
+        coords: vec![None; node_ids.len()],
+    };
+    pbf::read(path, &mut nodes)?;
+    let coords = nodes.coords;
+
+    let mut routing_node = vec![None; node_ids.len()];
+    let mut latitude = Vec::new();
+    let mut longitude = Vec::new();
+    for (idx, &uses) in num_uses.iter().enumerate() {
+        if uses > 1 {
+            if let Some((lat, lng)) = coords[idx] {
+                routing_node[idx] = Some(latitude.len() as NodeId);
+                latitude.push(lat as f32);
+                longitude.push(lng as f32);
+            }
+        }
+    }
+    let num_nodes = latitude.len();
+
+    eprintln!("build arcs");
+    // (tail, head, travel_time, geo_distance, way index)
+    let mut arcs: Vec<(NodeId, NodeId, Weight, Weight, usize)> = Vec::new();
+    for (way_idx, way) in ways.iter().enumerate() {
+        let mut segment_start: Option<NodeId> = None;
+        let mut length = 0.0;
+        let mut prev: Option<(f64, f64)> = None;
+        for node in &way.refs {
+            let idx = node_ids.binary_search(node).unwrap();
+            // ways referencing nodes missing from the extract are cut at these nodes
+            let pos = match coords[idx] {
+                Some(pos) => pos,
+                None => {
+                    segment_start = None;
+                    prev = None;
+                    continue;
+                }
+            };
+            if let Some(prev) = prev {
+                length += distance(prev, pos);
+            }
+            prev = Some(pos);
+
+            if let Some(node) = routing_node[idx] {
+                if let Some(start) = segment_start {
+                    if start != node {
+                        let geo_distance = length.round() as Weight;
+                        let travel_time = (length * 3600.0 / f64::from(way.speed)).round() as Weight;
+                        if way.forward {
+                            arcs.push((start, node, travel_time, geo_distance, way_idx));
+                        }
+                        if way.backward {
+                            arcs.push((node, start, travel_time, geo_distance, way_idx));
+                        }
+                    }
+                }
+                segment_start = Some(node);
+                length = 0.0;
+            }
+        }
+    }
+    arcs.sort_by_key(|&(tail, head, ..)| (tail, head));
+
+    let mut first_out = vec![0 as EdgeId; num_nodes + 1];
+    for &(tail, ..) in &arcs {
+        first_out[tail as usize + 1] += 1;
+    }
+    for node in 0..num_nodes {
+        first_out[node + 1] += first_out[node];
+    }
+
+    eprintln!("build forbidden turns");
+    let mut incoming: Vec<EdgeId> = (0..arcs.len() as EdgeId).collect();
+    incoming.sort_by_key(|&arc| arcs[arc as usize].1);
+    let way_idx: HashMap<i64, usize> = ways.iter().enumerate().map(|(idx, way)| (way.id, idx)).collect();
+    let mut forbidden_turns = Vec::new();
+    for restriction in &restrictions {
+        let (from_way, to_way) = match (way_idx.get(&restriction.from_way), way_idx.get(&restriction.to_way)) {
+            (Some(&from_way), Some(&to_way)) => (from_way, to_way),
+            _ => continue,
+        };
+        let via = match node_ids.binary_search(&restriction.via_node).ok().and_then(|idx| routing_node[idx]) {
+            Some(via) => via,
+            None => continue,
+        };
+
+        let incoming_start = incoming.partition_point(|&arc| arcs[arc as usize].1 < via);
+        let incoming_end = incoming.partition_point(|&arc| arcs[arc as usize].1 <= via);
+        let from_arcs = incoming[incoming_start..incoming_end].iter().filter(|&&arc| arcs[arc as usize].4 == from_way);
+        let outgoing = (first_out[via as usize]..first_out[via as usize + 1]).map(|arc| (arc, arcs[arc as usize].4));
+        for &from_arc in from_arcs {
+            for (to_arc, way) in outgoing.clone() {
+                let forbidden = match restriction.kind {
+                    RestrictionKind::No => way == to_way,
+                    RestrictionKind::Only => way != to_way,
+                };
+                if forbidden {
+                    forbidden_turns.push((from_arc, to_arc));
+                }
+            }
+        }
+    }
+    forbidden_turns.sort_unstable();
+    forbidden_turns.dedup();
+
+    Ok(OsmData {
+        first_out,
+        head: arcs.iter().map(|&(_, head, ..)| head).collect(),
+        travel_time: arcs.iter().map(|&(_, _, travel_time, ..)| travel_time).collect(),
+        geo_distance: arcs.iter().map(|&(_, _, _, geo_distance, _)| geo_distance).collect(),
+        latitude,
+        longitude,
+        forbidden_turn_from_arc: forbidden_turns.iter().map(|&(from, _)| from).collect(),
+        forbidden_turn_to_arc: forbidden_turns.iter().map(|&(_, to)| to).collect(),
+        attributes: LinkAttributes {
+            max_height: arcs.iter().map(|arc| ways[arc.4].restrictions.max_height).collect(),
+            max_weight: arcs.iter().map(|arc| ways[arc.4].restrictions.max_weight).collect(),
+            forbidden_hazmat: arcs.iter().map(|arc| ways[arc.4].restrictions.forbidden_hazmat).collect(),
+            allowed_vehicle_classes: arcs.iter().map(|arc| ways[arc.4].restrictions.allowed_vehicle_classes).collect(),
+        },
+    })
+}
+
+fn distance((from_lat, from_lng): (f64, f64), (to_lat, to_lng): (f64, f64)) -> f64 {
+    WGS84::from_degrees_and_meters(from_lat, from_lng, 0.0).distance(&WGS84::from_degrees_and_meters(to_lat, to_lng, 0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pbf::tests::{write_pbf, BlockBuilder, TempFile};
+    use super::*;
+
+    #[test]
+    fn import_network_with_turn_restrictions() {
+        let residential = [("highway", "residential")];
+        let block = BlockBuilder::default()
+            .dense_nodes(&[
+                (1, 0.0, 0.0),
+                (2, 0.0, 0.001),
+                (3, 0.0, 0.002),
+                (4, 0.001, 0.002),
+                (5, 0.0, 0.003),
+                (6, -0.001, 0.002),
+                (7, 0.001, 0.003),
+            ])
+            .way(10, &residential, &[1, 2, 3])
+            .way(11, &residential, &[3, 4])
+            .way(12, &residential, &[3, 5])
+            .way(13, &[("highway", "residential"), ("oneway", "yes")], &[3, 6])
+            .way(14, &[("highway", "footway")], &[4, 7])
+            .way(15, &[("highway", "residential"), ("access", "private")], &[5, 7])
+            .relation(
+                20,
+                &[("type", "restriction"), ("restriction", "no_left_turn")],
+                &[("from", 10, 1), ("via", 3, 0), ("to", 11, 1)],
+            )
+            .relation(
+                21,
+                &[("type", "restriction"), ("restriction", "only_straight_on")],
+                &[("from", 11, 1), ("via", 3, 0), ("to", 12, 1)],
+            )
+            // ignored: not for cars, and with a way as via member
+            .relation(
+                22,
+                &[("type", "restriction"), ("restriction", "no_right_turn"), ("except", "bicycle;motorcar")],
+                &[("from", 10, 1), ("via", 3, 0), ("to", 13, 1)],
+            )
+            .relation(
+                23,
+                &[("type", "restriction"), ("restriction", "no_u_turn")],
+                &[("from", 10, 1), ("via", 12, 1), ("to", 10, 1)],
+            )
+            .build();
+        let file = TempFile::new("osm_import");
+        write_pbf(&file, &[block], true);
+
+        let data = read_pbf(&file.0, &CarProfile::default()).unwrap();
+
+        // nodes 1, 3, 4, 5 and 6 become routing nodes 0 to 4, node 2 only shapes the first way
+        assert_eq!(data.latitude.len(), 5);
+        assert_eq!(data.first_out, vec![0, 1, 5, 6, 7, 7]);
+        assert_eq!(data.head, vec![1, 0, 2, 3, 4, 1, 1]);
+        // the travel time is computed from the unrounded length of the way, not from `geo_distance`
+        let length = distance((0.0, 0.0), (0.0, 0.001)) + distance((0.0, 0.001), (0.0, 0.002));
+        assert_eq!(data.geo_distance[0], length.round() as Weight);
+        assert!((220..225).contains(&data.geo_distance[0]));
+        assert_eq!(data.travel_time[0], (length * 3600.0 / 25.0).round() as Weight);
+
+        // no left turn from arc 0->1 into 1->2, only straight on from 2->1 into 1->3
+        let forbidden: Vec<_> = data
+            .forbidden_turn_from_arc
+            .iter()
+            .copied()
+            .zip(data.forbidden_turn_to_arc.iter().copied())
+            .collect();
+        assert_eq!(forbidden, vec![(0, 2), (5, 1), (5, 2), (5, 4)]);
+        assert_eq!(data.attributes.max_height.len(), data.head.len());
+    }
+}
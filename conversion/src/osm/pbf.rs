@@ -0,0 +1,851 @@
+//! Reader for the OpenStreetMap PBF format.
+//!
+//! Only supports what is needed to build routing graphs: zlib compressed or raw blobs,
+//! plain and dense nodes without tags, ways and relations with their tags.
+//! Metadata like versions and timestamps is skipped.
+//! The protobuf messages are decoded by hand, the format only needs a tiny subset of protobuf.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, ErrorKind, Read},
+    path::Path,
+    str,
+};
+
+use flate2::read::ZlibDecoder;
+
+pub struct Node {
+    pub id: i64,
+    pub lat: f64,
+    pub lng: f64,
+}
+
+/// Key value pairs of a way or relation, borrowed from the string table of the current block.
+pub struct Tags<'a>(Vec<(&'a str, &'a str)>);
+
+impl<'a> Tags<'a> {
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        self.0.iter().find(|&&(k, _)| k == key).map(|&(_, v)| v)
+    }
+}
+
+pub struct Way<'a> {
+    pub id: i64,
+    pub tags: Tags<'a>,
+    pub refs: Vec<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemberType {
+    Node,
+    Way,
+    Relation,
+}
+
+pub struct Member<'a> {
+    pub id: i64,
+    pub member_type: MemberType,
+    pub role: &'a str,
+}
+
+pub struct Relation<'a> {
+    pub id: i64,
+    pub tags: Tags<'a>,
+    pub members: Vec<Member<'a>>,
+}
+
+/// Callbacks for the elements of a PBF file.
+/// Decoding elements of a kind which is not wanted is skipped, which makes separate passes over the file cheap.
+pub trait Visitor {
+    fn wants_nodes(&self) -> bool {
+        true
+    }
+    fn wants_ways(&self) -> bool {
+        true
+    }
+    fn wants_relations(&self) -> bool {
+        true
+    }
+    fn node(&mut self, _node: Node) {}
+    fn way(&mut self, _way: &Way) {}
+    fn relation(&mut self, _relation: &Relation) {}
+}
+
+const SUPPORTED_FEATURES: [&str; 2] = ["OsmSchema-V0.6", "DenseNodes"];
+// Size limits from the format specification, larger sizes come from corrupt files and would only exhaust the memory
+const MAX_BLOB_HEADER_SIZE: usize = 64 * 1024;
+const MAX_BLOB_SIZE: usize = 32 * 1024 * 1024;
+
+/// Read the PBF file at `path` and pass all elements to `visitor` in file order.
+pub fn read(path: &Path, visitor: &mut dyn Visitor) -> io::Result<()> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut buffer = Vec::new();
+
+    loop {
+        let mut header_len = [0u8; 4];
+        match file.read_exact(&mut header_len) {
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            result => result?,
+        }
+        let header_len = u32::from_be_bytes(header_len) as usize;
+        if header_len > MAX_BLOB_HEADER_SIZE {
+            return Err(invalid("blob header too large"));
+        }
+        buffer.resize(header_len, 0);
+        file.read_exact(&mut buffer)?;
+
+        // BlobHeader
+        let mut blob_type = "";
+        let mut blob_len = 0;
+        let mut header = ProtoReader::new(&buffer);
+        while let Some((field, value)) = header.next_field()? {
+            match (field, value) {
+                (1, Value::Bytes(bytes)) => blob_type = utf8(bytes),
+                (3, Value::Varint(len)) => blob_len = len as usize,
+                _ => (),
+            }
+        }
+        let blob_type = blob_type.to_string();
+        if blob_len > MAX_BLOB_SIZE {
+            return Err(invalid("blob too large"));
+        }
+
+        buffer.resize(blob_len, 0);
+        file.read_exact(&mut buffer)?;
+
+        match &blob_type[..] {
+            "OSMHeader" => check_header(&blob_data(&buffer)?)?,
+            "OSMData" => read_primitive_block(&blob_data(&buffer)?, visitor)?,
+            // unknown blobs may be skipped according to the spec
+            _ => (),
+        }
+    }
+}
+
+fn blob_data(blob: &[u8]) -> io::Result<Vec<u8>> {
+    let mut raw_size = 0;
+    let mut reader = ProtoReader::new(blob);
+    while let Some((field, value)) = reader.next_field()? {
+        match (field, value) {
+            (1, Value::Bytes(raw)) => return Ok(raw.to_vec()),
+            (2, Value::Varint(size)) => raw_size = size as usize,
+            (3, Value::Bytes(compressed)) => {
+                let mut data = Vec::with_capacity(raw_size);
+                ZlibDecoder::new(compressed).read_to_end(&mut data)?;
+                return Ok(data);
+            }
+            (4..=7, _) => return Err(invalid("only zlib compressed blobs are supported")),
+            _ => (),
+        }
+    }
+    Err(invalid("blob without data"))
+}
+
+fn check_header(data: &[u8]) -> io::Result<()> {
+    let mut reader = ProtoReader::new(data);
+    while let Some((field, value)) = reader.next_field()? {
+        if let (4, Value::Bytes(feature)) = (field, value) {
+            let feature = utf8(feature);
+            if !SUPPORTED_FEATURES.contains(&feature) {
+                return Err(invalid(&format!("unsupported required feature {}", feature)));
+            }
+        }
+    }
+    Ok(())
+}
+
+struct Block<'a> {
+    strings: Vec<&'a str>,
+    granularity: i64,
+    lat_offset: i64,
+    lon_offset: i64,
+}
+
+impl<'a> Block<'a> {
+    fn string(&self, idx: u64) -> io::Result<&'a str> {
+        self.strings.get(idx as usize).copied().ok_or_else(|| invalid("string index out of range"))
+    }
+
+    fn tags(&self, keys: &[u64], vals: &[u64]) -> io::Result<Tags<'a>> {
+        if keys.len() != vals.len() {
+            return Err(invalid("different number of keys and values"));
+        }
+        Ok(Tags(
+            keys.iter()
+                .zip(vals)
+                .map(|(&k, &v)| Ok((self.string(k)?, self.string(v)?)))
+                .collect::<io::Result<_>>()?,
+        ))
+    }
+
+    // computed in floating point, so corrupt offsets or granularities can not overflow
+    fn coords(&self, lat: i64, lon: i64) -> (f64, f64) {
+        (
+            1e-9 * (self.lat_offset as f64 + self.granularity as f64 * lat as f64),
+            1e-9 * (self.lon_offset as f64 + self.granularity as f64 * lon as f64),
+        )
+    }
+}
+
+fn read_primitive_block(data: &[u8], visitor: &mut dyn Visitor) -> io::Result<()> {
+    let mut block = Block {
+        strings: Vec::new(),
+        granularity: 100,
+        lat_offset: 0,
+        lon_offset: 0,
+    };
+    let mut groups = Vec::new();
+
+    let mut reader = ProtoReader::new(data);
+    while let Some((field, value)) = reader.next_field()? {
+        match (field, value) {
+            (1, Value::Bytes(string_table)) => {
+                let mut strings = ProtoReader::new(string_table);
+                while let Some((field, value)) = strings.next_field()? {
+                    if let (1, Value::Bytes(string)) = (field, value) {
+                        block.strings.push(utf8(string));
+                    }
+                }
+            }
+            (2, Value::Bytes(group)) => groups.push(group),
+            (17, Value::Varint(granularity)) => block.granularity = granularity as i64,
+            (19, Value::Varint(offset)) => block.lat_offset = offset as i64,
+            (20, Value::Varint(offset)) => block.lon_offset = offset as i64,
+            _ => (),
+        }
+    }
+
+    for group in groups {
+        let mut reader = ProtoReader::new(group);
+        while let Some((field, value)) = reader.next_field()? {
+            match (field, value) {
+                (1, Value::Bytes(node)) if visitor.wants_nodes() => visitor.node(read_node(node, &block)?),
+                (2, Value::Bytes(dense)) if visitor.wants_nodes() => read_dense_nodes(dense, &block, visitor)?,
+                (3, Value::Bytes(way)) if visitor.wants_ways() => visitor.way(&read_way(way, &block)?),
+                (4, Value::Bytes(relation)) if visitor.wants_relations() => visitor.relation(&read_relation(relation, &block)?),
+                _ => (),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn read_node(data: &[u8], block: &Block) -> io::Result<Node> {
+    let (mut id, mut lat, mut lon) = (0, 0, 0);
+    let mut reader = ProtoReader::new(data);
+    while let Some((field, value)) = reader.next_field()? {
+        match (field, value) {
+            (1, Value::Varint(value)) => id = zigzag(value),
+            (8, Value::Varint(value)) => lat = zigzag(value),
+            (9, Value::Varint(value)) => lon = zigzag(value),
+            _ => (),
+        }
+    }
+    let (lat, lng) = block.coords(lat, lon);
+    Ok(Node { id, lat, lng })
+}
+
+fn read_dense_nodes(data: &[u8], block: &Block, visitor: &mut dyn Visitor) -> io::Result<()> {
+    let (mut ids, mut lats, mut lons) = (Vec::new(), Vec::new(), Vec::new());
+    let mut reader = ProtoReader::new(data);
+    while let Some((field, value)) = reader.next_field()? {
+        match field {
+            1 => repeated(value, &mut ids)?,
+            8 => repeated(value, &mut lats)?,
+            9 => repeated(value, &mut lons)?,
+            _ => (),
+        }
+    }
+    if ids.len() != lats.len() || ids.len() != lons.len() {
+        return Err(invalid("inconsistent dense nodes"));
+    }
+
+    // all three are delta coded, wrapping so corrupt deltas can not overflow
+    let (mut id, mut lat, mut lon) = (0i64, 0i64, 0i64);
+    for ((&id_delta, &lat_delta), &lon_delta) in ids.iter().zip(&lats).zip(&lons) {
+        id = id.wrapping_add(zigzag(id_delta));
+        lat = lat.wrapping_add(zigzag(lat_delta));
+        lon = lon.wrapping_add(zigzag(lon_delta));
+        let (lat, lng) = block.coords(lat, lon);
+        visitor.node(Node { id, lat, lng });
+    }
+    Ok(())
+}
+
+fn read_way<'a>(data: &[u8], block: &Block<'a>) -> io::Result<Way<'a>> {
+    let (mut id, mut keys, mut vals, mut refs) = (0, Vec::new(), Vec::new(), Vec::new());
+    let mut reader = ProtoReader::new(data);
+    while let Some((field, value)) = reader.next_field()? {
+        match field {
+            1 => id = varint(value)? as i64,
+            2 => repeated(value, &mut keys)?,
+            3 => repeated(value, &mut vals)?,
+            8 => repeated(value, &mut refs)?,
+            _ => (),
+        }
+    }
+
+    let mut node = 0i64;
+    let refs = refs
+        .into_iter()
+        .map(|delta| {
+            node = node.wrapping_add(zigzag(delta));
+            node
+        })
+        .collect();
+    Ok(Way {
+        id,
+        tags: block.tags(&keys, &vals)?,
+        refs,
+    })
+}
+
+fn read_relation<'a>(data: &[u8], block: &Block<'a>) -> io::Result<Relation<'a>> {
+    let (mut id, mut keys, mut vals, mut roles, mut member_ids, mut types) = (0, Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    let mut reader = ProtoReader::new(data);
+    while let Some((field, value)) = reader.next_field()? {
+        match field {
+            1 => id = varint(value)? as i64,
+            2 => repeated(value, &mut keys)?,
+            3 => repeated(value, &mut vals)?,
+            8 => repeated(value, &mut roles)?,
+            9 => repeated(value, &mut member_ids)?,
+            10 => repeated(value, &mut types)?,
+            _ => (),
+        }
+    }
+    if roles.len() != member_ids.len() || roles.len() != types.len() {
+        return Err(invalid("inconsistent relation members"));
+    }
+
+    let mut member_id = 0i64;
+    let members = roles
+        .iter()
+        .zip(&member_ids)
+        .zip(&types)
+        .map(|((&role, &id_delta), &member_type)| {
+            member_id = member_id.wrapping_add(zigzag(id_delta));
+            Ok(Member {
+                id: member_id,
+                member_type: match member_type {
+                    0 => MemberType::Node,
+                    1 => MemberType::Way,
+                    2 => MemberType::Relation,
+                    _ => return Err(invalid("unknown member type")),
+                },
+                role: block.string(role)?,
+            })
+        })
+        .collect::<io::Result<_>>()?;
+
+    Ok(Relation {
+        id,
+        tags: block.tags(&keys, &vals)?,
+        members,
+    })
+}
+
+// Fixed size values are not used by the PBF messages we read, so they are only skipped.
+enum Value<'a> {
+    Varint(u64),
+    Fixed64,
+    Bytes(&'a [u8]),
+    Fixed32,
+}
+
+struct ProtoReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ProtoReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        ProtoReader { data, pos: 0 }
+    }
+
+    fn next_field(&mut self) -> io::Result<Option<(u32, Value<'a>)>> {
+        if self.pos >= self.data.len() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let value = match key & 0x7 {
+            0 => Value::Varint(self.varint()?),
+            1 => {
+                self.bytes(8)?;
+                Value::Fixed64
+            }
+            2 => {
+                let len = self.varint()? as usize;
+                Value::Bytes(self.bytes(len)?)
+            }
+            5 => {
+                self.bytes(4)?;
+                Value::Fixed32
+            }
+            _ => return Err(invalid("unsupported protobuf wire type")),
+        };
+        Ok(Some(((key >> 3) as u32, value)))
+    }
+
+    fn varint(&mut self) -> io::Result<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.data.get(self.pos).ok_or_else(|| invalid("truncated varint"))?;
+            self.pos += 1;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(invalid("varint too long"))
+    }
+
+    fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        // the length comes straight from the file, so it may be arbitrarily large
+        let bytes = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.data.get(self.pos..end))
+            .ok_or_else(|| invalid("truncated message"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+}
+
+fn varint(value: Value) -> io::Result<u64> {
+    match value {
+        Value::Varint(value) => Ok(value),
+        _ => Err(invalid("expected varint")),
+    }
+}
+
+// Repeated numbers are usually packed, but a single value per field is also valid protobuf.
+fn repeated(value: Value, values: &mut Vec<u64>) -> io::Result<()> {
+    match value {
+        Value::Varint(value) => values.push(value),
+        Value::Bytes(packed) => {
+            let mut reader = ProtoReader::new(packed);
+            while reader.pos < packed.len() {
+                values.push(reader.varint()?);
+            }
+        }
+        Value::Fixed64 | Value::Fixed32 => return Err(invalid("expected varints")),
+    }
+    Ok(())
+}
+
+fn zigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+fn utf8(bytes: &[u8]) -> &str {
+    str::from_utf8(bytes).unwrap_or("")
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use flate2::{write::ZlibEncoder, Compression};
+    use std::{io::Write, path::PathBuf};
+
+    // Just enough of a protobuf encoder to build test files
+    #[derive(Default, Clone)]
+    pub struct Message(Vec<u8>);
+
+    fn encode_varint(out: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            out.push((value as u8 & 0x7f) | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    pub fn zigzag_encode(value: i64) -> u64 {
+        ((value << 1) ^ (value >> 63)) as u64
+    }
+
+    impl Message {
+        pub fn varint(mut self, field: u32, value: u64) -> Self {
+            encode_varint(&mut self.0, u64::from(field) << 3);
+            encode_varint(&mut self.0, value);
+            self
+        }
+
+        pub fn bytes(mut self, field: u32, bytes: &[u8]) -> Self {
+            encode_varint(&mut self.0, u64::from(field) << 3 | 2);
+            encode_varint(&mut self.0, bytes.len() as u64);
+            self.0.extend_from_slice(bytes);
+            self
+        }
+
+        pub fn message(self, field: u32, message: Message) -> Self {
+            self.bytes(field, &message.0)
+        }
+
+        pub fn packed(self, field: u32, values: &[u64]) -> Self {
+            let mut packed = Vec::new();
+            for &value in values {
+                encode_varint(&mut packed, value);
+            }
+            self.bytes(field, &packed)
+        }
+
+        // delta and zigzag coded, like ids and coordinates
+        pub fn deltas(self, field: u32, values: &[i64]) -> Self {
+            let mut prev = 0;
+            let deltas: Vec<u64> = values
+                .iter()
+                .map(|&value| {
+                    let delta = zigzag_encode(value - prev);
+                    prev = value;
+                    delta
+                })
+                .collect();
+            self.packed(field, &deltas)
+        }
+    }
+
+    // A primitive block with a string table, built up element by element
+    #[derive(Default)]
+    pub struct BlockBuilder {
+        strings: Vec<String>,
+        groups: Vec<Message>,
+    }
+
+    impl BlockBuilder {
+        fn string(&mut self, string: &str) -> u64 {
+            if self.strings.is_empty() {
+                // index 0 is reserved as delimiter
+                self.strings.push(String::new());
+            }
+            match self.strings.iter().position(|s| s == string) {
+                Some(idx) => idx as u64,
+                None => {
+                    self.strings.push(string.to_string());
+                    self.strings.len() as u64 - 1
+                }
+            }
+        }
+
+        fn tags(&mut self, message: Message, tags: &[(&str, &str)]) -> Message {
+            let keys: Vec<u64> = tags.iter().map(|&(key, _)| self.string(key)).collect();
+            let vals: Vec<u64> = tags.iter().map(|&(_, val)| self.string(val)).collect();
+            message.packed(2, &keys).packed(3, &vals)
+        }
+
+        /// Dense nodes with coordinates in degrees, with the default granularity of 100 nanodegrees
+        pub fn dense_nodes(mut self, nodes: &[(i64, f64, f64)]) -> Self {
+            let ids: Vec<i64> = nodes.iter().map(|&(id, _, _)| id).collect();
+            let lats: Vec<i64> = nodes.iter().map(|&(_, lat, _)| (lat * 1e7).round() as i64).collect();
+            let lons: Vec<i64> = nodes.iter().map(|&(_, _, lng)| (lng * 1e7).round() as i64).collect();
+            let dense = Message::default().deltas(1, &ids).deltas(8, &lats).deltas(9, &lons);
+            self.groups.push(Message::default().message(2, dense));
+            self
+        }
+
+        pub fn way(mut self, id: i64, tags: &[(&str, &str)], refs: &[i64]) -> Self {
+            let way = Message::default().varint(1, id as u64);
+            let way = self.tags(way, tags).deltas(8, refs);
+            self.groups.push(Message::default().message(3, way));
+            self
+        }
+
+        /// Members are given as role, id and type (0 node, 1 way, 2 relation)
+        pub fn relation(mut self, id: i64, tags: &[(&str, &str)], members: &[(&str, i64, u64)]) -> Self {
+            let relation = Message::default().varint(1, id as u64);
+            let relation = self.tags(relation, tags);
+            let roles: Vec<u64> = members.iter().map(|&(role, _, _)| self.string(role)).collect();
+            let ids: Vec<i64> = members.iter().map(|&(_, id, _)| id).collect();
+            let types: Vec<u64> = members.iter().map(|&(_, _, member_type)| member_type).collect();
+            let relation = relation.packed(8, &roles).deltas(9, &ids).packed(10, &types);
+            self.groups.push(Message::default().message(4, relation));
+            self
+        }
+
+        pub fn build(mut self) -> Message {
+            // make sure the empty string is at index 0 even without any tags
+            self.string("");
+            let strings = self.strings.iter().fold(Message::default(), |table, string| table.bytes(1, string.as_bytes()));
+            self.groups
+                .into_iter()
+                .fold(Message::default().message(1, strings), |block, group| block.message(2, group))
+        }
+    }
+
+    // Removes the file when the test ends, also when an assertion fails
+    pub struct TempFile(pub PathBuf);
+
+    impl TempFile {
+        pub fn new(name: &str) -> Self {
+            TempFile(std::env::temp_dir().join(format!("rrr_{}_{}.osm.pbf", name, std::process::id())))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn blob(blob_type: &str, data: &Message, compress: bool) -> Vec<u8> {
+        let blob = if compress {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&data.0).unwrap();
+            Message::default().varint(2, data.0.len() as u64).bytes(3, &encoder.finish().unwrap())
+        } else {
+            Message::default().bytes(1, &data.0)
+        };
+        let header = Message::default().bytes(1, blob_type.as_bytes()).varint(3, blob.0.len() as u64);
+
+        let mut bytes = (header.0.len() as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(&header.0);
+        bytes.extend_from_slice(&blob.0);
+        bytes
+    }
+
+    /// Write a PBF file with a header and one data blob per block
+    pub fn write_pbf(file: &TempFile, blocks: &[Message], compress: bool) {
+        let header = Message::default().bytes(4, b"OsmSchema-V0.6").bytes(4, b"DenseNodes");
+        let mut bytes = blob("OSMHeader", &header, compress);
+        for block in blocks {
+            bytes.extend(blob("OSMData", block, compress));
+        }
+        std::fs::write(&file.0, bytes).unwrap();
+    }
+
+    type OwnedTags = Vec<(String, String)>;
+    type OwnedMembers = Vec<(String, i64, MemberType)>;
+
+    #[derive(Default)]
+    struct Recorder {
+        nodes: Vec<(i64, f64, f64)>,
+        ways: Vec<(i64, OwnedTags, Vec<i64>)>,
+        relations: Vec<(i64, OwnedTags, OwnedMembers)>,
+    }
+
+    fn owned_tags(tags: &Tags) -> OwnedTags {
+        tags.0.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    impl Visitor for Recorder {
+        fn node(&mut self, node: Node) {
+            self.nodes.push((node.id, node.lat, node.lng));
+        }
+        fn way(&mut self, way: &Way) {
+            self.ways.push((way.id, owned_tags(&way.tags), way.refs.clone()));
+        }
+        fn relation(&mut self, relation: &Relation) {
+            let members = relation.members.iter().map(|m| (m.role.to_string(), m.id, m.member_type)).collect();
+            self.relations.push((relation.id, owned_tags(&relation.tags), members));
+        }
+    }
+
+    fn record(block: &Message) -> io::Result<Recorder> {
+        let mut recorder = Recorder::default();
+        read_primitive_block(&block.0, &mut recorder)?;
+        Ok(recorder)
+    }
+
+    #[test]
+    fn varints_and_zigzag() {
+        for value in [0, 1, 127, 128, 300, 1 << 35, u64::MAX] {
+            let mut bytes = Vec::new();
+            encode_varint(&mut bytes, value);
+            assert_eq!(ProtoReader::new(&bytes).varint().unwrap(), value);
+        }
+        assert_eq!(ProtoReader::new(&[0xac, 0x02]).varint().unwrap(), 300);
+
+        for value in [0, -1, 1, -64, 64, i64::MAX, i64::MIN] {
+            assert_eq!(zigzag(zigzag_encode(value)), value);
+        }
+        assert_eq!(zigzag(1), -1);
+        assert_eq!(zigzag(4), 2);
+
+        assert!(ProtoReader::new(&[0x80, 0x80]).varint().is_err());
+        assert!(ProtoReader::new(&[0xff; 11]).varint().is_err());
+    }
+
+    #[test]
+    fn packed_and_single_repeated_values() {
+        let message = Message::default().packed(1, &[1, 300, 5]).varint(1, 7);
+        let mut reader = ProtoReader::new(&message.0);
+        let mut values = Vec::new();
+        while let Some((field, value)) = reader.next_field().unwrap() {
+            assert_eq!(field, 1);
+            repeated(value, &mut values).unwrap();
+        }
+        assert_eq!(values, vec![1, 300, 5, 7]);
+
+        // fixed size values are skipped, but are not valid repeated numbers
+        let fixed = [1 << 3 | 5, 0, 0, 0, 0];
+        let (_, value) = ProtoReader::new(&fixed).next_field().unwrap().unwrap();
+        assert!(repeated(value, &mut values).is_err());
+    }
+
+    #[test]
+    fn dense_nodes_are_delta_decoded() {
+        let block = BlockBuilder::default()
+            .dense_nodes(&[(10, 49.0, 8.4), (12, 49.001, 8.399), (7, -33.5, 151.2)])
+            .build();
+        let nodes = record(&block).unwrap().nodes;
+
+        assert_eq!(nodes.iter().map(|&(id, _, _)| id).collect::<Vec<_>>(), vec![10, 12, 7]);
+        for (&(_, lat, lng), &(expected_lat, expected_lng)) in nodes.iter().zip(&[(49.0, 8.4), (49.001, 8.399), (-33.5, 151.2)]) {
+            assert!((lat - expected_lat).abs() < 1e-7 && (lng - expected_lng).abs() < 1e-7);
+        }
+
+        // granularity and offsets of the block are applied
+        let dense = Message::default().deltas(1, &[1]).deltas(8, &[5]).deltas(9, &[-5]);
+        let block = Message::default()
+            .message(2, Message::default().message(2, dense))
+            .varint(17, 1000)
+            .varint(19, 2_000_000_000)
+            .varint(20, 1_000_000_000);
+        let (_, lat, lng) = record(&block).unwrap().nodes[0];
+        assert!((lat - 2.000005).abs() < 1e-9 && (lng - 0.999995).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ways_and_relations() {
+        let block = BlockBuilder::default()
+            .way(5, &[("highway", "primary"), ("oneway", "yes")], &[100, 90, 110])
+            .relation(
+                9,
+                &[("type", "restriction"), ("restriction", "no_left_turn")],
+                &[("from", 5, 1), ("via", 90, 0), ("to", 6, 1), ("", 3, 2)],
+            )
+            .build();
+        let recorder = record(&block).unwrap();
+
+        let (id, tags, refs) = &recorder.ways[0];
+        assert_eq!(*id, 5);
+        assert_eq!(refs, &vec![100, 90, 110]);
+        assert_eq!(
+            tags,
+            &vec![("highway".to_string(), "primary".to_string()), ("oneway".to_string(), "yes".to_string())]
+        );
+
+        let (id, tags, members) = &recorder.relations[0];
+        assert_eq!(*id, 9);
+        assert_eq!(tags[1], ("restriction".to_string(), "no_left_turn".to_string()));
+        assert_eq!(
+            members,
+            &vec![
+                ("from".to_string(), 5, MemberType::Way),
+                ("via".to_string(), 90, MemberType::Node),
+                ("to".to_string(), 6, MemberType::Way),
+                (String::new(), 3, MemberType::Relation),
+            ]
+        );
+    }
+
+    #[test]
+    fn unwanted_elements_are_skipped() {
+        struct WaysOnly(usize);
+        impl Visitor for WaysOnly {
+            fn wants_nodes(&self) -> bool {
+                false
+            }
+            fn node(&mut self, _node: Node) {
+                panic!("nodes are not wanted");
+            }
+            fn way(&mut self, _way: &Way) {
+                self.0 += 1;
+            }
+        }
+
+        let block = BlockBuilder::default().dense_nodes(&[(1, 0.0, 0.0)]).way(2, &[], &[1, 3]).build();
+        let mut visitor = WaysOnly(0);
+        read_primitive_block(&block.0, &mut visitor).unwrap();
+        assert_eq!(visitor.0, 1);
+    }
+
+    #[test]
+    fn corrupt_blocks_are_errors() {
+        // a length far beyond the data, including one which overflows the position
+        for len in [100, u64::MAX] {
+            let mut bytes = vec![1 << 3 | 2];
+            encode_varint(&mut bytes, len);
+            let err = ProtoReader::new(&bytes).next_field().err().unwrap();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+        }
+        // wire types 3 and 4 are deprecated groups
+        assert!(ProtoReader::new(&[1 << 3 | 3]).next_field().is_err());
+
+        let dense = Message::default().deltas(1, &[1, 2]).deltas(8, &[0]).deltas(9, &[0, 0]);
+        assert!(record(&Message::default().message(2, Message::default().message(2, dense))).is_err());
+
+        let way = Message::default().varint(1, 1).packed(2, &[42]).packed(3, &[42]);
+        assert!(record(&Message::default().message(2, Message::default().message(3, way))).is_err());
+
+        let way = Message::default().varint(1, 1).packed(2, &[0, 0]).packed(3, &[0]);
+        assert!(record(&Message::default().message(2, Message::default().message(3, way))).is_err());
+
+        let relation = BlockBuilder::default().relation(1, &[], &[("via", 1, 7)]).build();
+        assert!(record(&relation).is_err());
+
+        // huge deltas wrap instead of overflowing
+        let dense = Message::default().packed(1, &[u64::MAX, u64::MAX - 1]).packed(8, &[0, 0]).packed(9, &[0, 0]);
+        assert_eq!(
+            record(&Message::default().message(2, Message::default().message(2, dense)))
+                .unwrap()
+                .nodes
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn read_raw_and_compressed_files() {
+        let block = BlockBuilder::default()
+            .dense_nodes(&[(1, 49.0, 8.4), (2, 49.1, 8.5)])
+            .way(3, &[("highway", "residential")], &[1, 2]);
+        let blocks = [block.build(), BlockBuilder::default().way(4, &[], &[2, 1]).build()];
+
+        for compress in [false, true] {
+            let file = TempFile::new(if compress { "pbf_zlib" } else { "pbf_raw" });
+            write_pbf(&file, &blocks, compress);
+            let mut recorder = Recorder::default();
+            read(&file.0, &mut recorder).unwrap();
+            assert_eq!(recorder.nodes.len(), 2);
+            assert_eq!(
+                recorder.ways.iter().map(|(id, _, refs)| (*id, refs.clone())).collect::<Vec<_>>(),
+                vec![(3, vec![1, 2]), (4, vec![2, 1])]
+            );
+        }
+    }
+
+    #[test]
+    fn corrupt_files_are_errors() {
+        let file = TempFile::new("pbf_corrupt");
+        write_pbf(&file, &[BlockBuilder::default().way(1, &[], &[1, 2]).build()], true);
+        let bytes = std::fs::read(&file.0).unwrap();
+
+        // cut off in the middle of the last blob
+        std::fs::write(&file.0, &bytes[..bytes.len() - 3]).unwrap();
+        assert!(read(&file.0, &mut Recorder::default()).is_err());
+
+        // absurd blob header size
+        std::fs::write(&file.0, [0xff, 0xff, 0xff, 0xff]).unwrap();
+        assert_eq!(read(&file.0, &mut Recorder::default()).unwrap_err().kind(), ErrorKind::InvalidData);
+
+        // unsupported required feature
+        let header = Message::default().bytes(4, b"HistoricalInformation");
+        std::fs::write(&file.0, blob("OSMHeader", &header, false)).unwrap();
+        assert_eq!(read(&file.0, &mut Recorder::default()).unwrap_err().kind(), ErrorKind::InvalidData);
+
+        // compression other than zlib
+        let data = Message::default().varint(2, 10).bytes(4, b"lzma data");
+        let header = Message::default().bytes(1, b"OSMData").varint(3, data.0.len() as u64);
+        let mut bytes = (header.0.len() as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(&header.0);
+        bytes.extend_from_slice(&data.0);
+        std::fs::write(&file.0, bytes).unwrap();
+        assert_eq!(read(&file.0, &mut Recorder::default()).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+}
@@ -1,15 +1,33 @@
+//! Landmark based A* potentials (ALT).
+//!
+//! The potentials only need exact distances from and to a few landmarks and no other preprocessing.
+//! So they are a fallback when the metric changes too often for a CCH customization.
+//! Weight increases keep the potentials feasible, they only get weaker.
+//! `LandmarkUpdater` brings them back to exact landmark distances by only repairing the parts of the landmark shortest path trees below the changed arcs.
+
 use super::*;
 use crate::report::*;
 use crate::{
     algo::{
         a_star::*,
-        dijkstra::{query::dijkstra::ServerWrapper, *},
+        dijkstra::{
+            query::{
+                bidirectional_dijkstra::{self, PathServerWrapper},
+                dijkstra::ServerWrapper,
+            },
+            *,
+        },
     },
-    datastr::rank_select_map::BitVec,
+    datastr::{index_heap::IndexdMinHeap, rank_select_map::BitVec},
+    io::*,
 };
 use rand::prelude::*;
+use std::collections::HashMap;
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ALTPotData {
+    landmarks: Vec<NodeId>,
+    // distances from (forward) and to (backward) each landmark, all landmarks of a node next to each other
     landmark_forward_distances: Vec<Weight>,
     landmark_backward_distances: Vec<Weight>,
     num_landmarks: usize,
@@ -41,10 +59,10 @@ impl ALTPotData {
             })
             .unzip();
 
-        Self::new_with_landmark_distances(landmark_forward_distances, landmark_backward_distances)
+        Self::new_with_landmark_distances(landmarks, landmark_forward_distances, landmark_backward_distances)
     }
 
-    fn new_with_landmark_distances(forward: Vec<Vec<Weight>>, backward: Vec<Vec<Weight>>) -> Self {
+    fn new_with_landmark_distances(landmarks: Vec<NodeId>, forward: Vec<Vec<Weight>>, backward: Vec<Vec<Weight>>) -> Self {
         let n = forward[0].len();
         let k = forward.len();
 
@@ -59,6 +77,7 @@ impl ALTPotData {
         }
 
         Self {
+            landmarks,
             landmark_forward_distances,
             landmark_backward_distances,
             num_landmarks: k,
@@ -140,7 +159,7 @@ impl ALTPotData {
             landmark_backward_distances.push(backward_distances);
         }
 
-        Self::new_with_landmark_distances(landmark_forward_distances, landmark_backward_distances)
+        Self::new_with_landmark_distances(landmarks, landmark_forward_distances, landmark_backward_distances)
     }
 
     fn dfs_set_sizes<G>(
@@ -225,6 +244,264 @@ impl ALTPotData {
             landmark_backward_distances: &self.landmark_forward_distances,
         }
     }
+
+    pub fn landmarks(&self) -> &[NodeId] {
+        &self.landmarks
+    }
+}
+
+impl Deconstruct for ALTPotData {
+    fn save_each(&self, store: &dyn Fn(&str, &dyn Save) -> std::io::Result<()>) -> std::io::Result<()> {
+        store("landmarks", &self.landmarks)?;
+        store("landmark_forward_distances", &self.landmark_forward_distances)?;
+        store("landmark_backward_distances", &self.landmark_backward_distances)?;
+        Ok(())
+    }
+}
+
+impl Reconstruct for ALTPotData {
+    fn reconstruct_with(loader: Loader) -> std::io::Result<Self> {
+        let landmarks: Vec<NodeId> = loader.load("landmarks")?;
+        let landmark_forward_distances: Vec<Weight> = loader.load("landmark_forward_distances")?;
+        let landmark_backward_distances: Vec<Weight> = loader.load("landmark_backward_distances")?;
+        let num_landmarks = landmarks.len();
+        if num_landmarks == 0
+            || !landmark_forward_distances.len().is_multiple_of(num_landmarks)
+            || landmark_forward_distances.len() != landmark_backward_distances.len()
+        {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "inconsistent landmark files"));
+        }
+        Ok(Self {
+            landmarks,
+            landmark_forward_distances,
+            landmark_backward_distances,
+            num_landmarks,
+        })
+    }
+}
+
+/// Updates landmark distances after weight increases.
+/// The adjacency arrays in both directions are built once for the topology of the graph,
+/// so each update only costs the repair searches, which are restricted to the nodes below the changed arcs.
+pub struct LandmarkUpdater {
+    outgoing: Adjacency,
+    incoming: Adjacency,
+    tail: Vec<NodeId>,
+    affected: BitVec,
+    queue: IndexdMinHeap<State<Weight>>,
+}
+
+impl LandmarkUpdater {
+    pub fn new<G: LinkIterable<(NodeIdT, EdgeIdT)>>(graph: &G) -> Self {
+        let n = graph.num_nodes();
+        let mut arcs = Vec::with_capacity(graph.num_arcs());
+        for node in 0..n as NodeId {
+            for (NodeIdT(head), EdgeIdT(edge)) in LinkIterable::<(NodeIdT, EdgeIdT)>::link_iter(graph, node) {
+                arcs.push((node, head, edge));
+            }
+        }
+        let mut tail = vec![0; graph.num_arcs()];
+        for &(node, _, edge) in &arcs {
+            tail[edge as usize] = node;
+        }
+
+        Self {
+            outgoing: Adjacency::new(n, arcs.iter().map(|&(tail, head, edge)| (tail, head, edge))),
+            incoming: Adjacency::new(n, arcs.iter().map(|&(tail, head, edge)| (head, tail, edge))),
+            tail,
+            affected: BitVec::new(n),
+            queue: IndexdMinHeap::new(n),
+        }
+    }
+
+    /// Update the landmark distances in `pot_data` after the weights of some arcs were increased.
+    /// `graph` has to have the topology this updater was built for and contain the new weights,
+    /// `increased` the ids and the previous weights of the changed arcs.
+    /// Only distances of nodes below a changed arc in the shortest path tree of a landmark are recomputed.
+    pub fn update_increased<G: EdgeRandomAccessGraph<Link>>(&mut self, pot_data: &mut ALTPotData, graph: &G, increased: &[(EdgeId, Weight)]) {
+        debug_assert!(
+            increased.iter().all(|&(edge, old)| graph.link(edge).weight >= old),
+            "landmark updates only support weight increases"
+        );
+        report!("num_increased_arcs", increased.len());
+        let previous_weight: HashMap<EdgeId, Weight> = increased.iter().copied().collect();
+        let forward_increased: Vec<_> = increased
+            .iter()
+            .map(|&(edge, old)| ((self.tail[edge as usize], graph.link(edge).node), edge, old))
+            .collect();
+        let backward_increased: Vec<_> = forward_increased.iter().map(|&((tail, head), edge, old)| ((head, tail), edge, old)).collect();
+
+        let mut repair = LandmarkRepair {
+            graph,
+            previous_weight: &previous_weight,
+            affected: &mut self.affected,
+            queue: &mut self.queue,
+        };
+        let k = pot_data.num_landmarks;
+        for (idx, &landmark) in pot_data.landmarks.iter().enumerate() {
+            let mut forward = StridedDistances(&mut pot_data.landmark_forward_distances, k, idx);
+            repair.run(landmark, &mut forward, &self.outgoing, &self.incoming, &forward_increased);
+            let mut backward = StridedDistances(&mut pot_data.landmark_backward_distances, k, idx);
+            repair.run(landmark, &mut backward, &self.incoming, &self.outgoing, &backward_increased);
+        }
+    }
+}
+
+// Arcs grouped by their first node, with the other node and the arc id.
+struct Adjacency {
+    first_out: Vec<usize>,
+    arcs: Vec<(NodeId, EdgeId)>,
+}
+
+impl Adjacency {
+    fn new(n: usize, arcs: impl Iterator<Item = (NodeId, NodeId, EdgeId)> + Clone) -> Self {
+        let mut first_out = vec![0; n + 1];
+        for (from, _, _) in arcs.clone() {
+            first_out[from as usize + 1] += 1;
+        }
+        for node in 0..n {
+            first_out[node + 1] += first_out[node];
+        }
+        let mut next = first_out.clone();
+        let mut adjacent = vec![(0, 0); first_out[n]];
+        for (from, to, edge) in arcs {
+            adjacent[next[from as usize]] = (to, edge);
+            next[from as usize] += 1;
+        }
+        Self { first_out, arcs: adjacent }
+    }
+
+    fn arcs(&self, node: NodeId) -> &[(NodeId, EdgeId)] {
+        &self.arcs[self.first_out[node as usize]..self.first_out[node as usize + 1]]
+    }
+}
+
+// The distances of one landmark in the interleaved distance array.
+struct StridedDistances<'a>(&'a mut [Weight], usize, usize);
+
+impl std::ops::Index<NodeId> for StridedDistances<'_> {
+    type Output = Weight;
+
+    fn index(&self, node: NodeId) -> &Weight {
+        &self.0[node as usize * self.1 + self.2]
+    }
+}
+
+impl std::ops::IndexMut<NodeId> for StridedDistances<'_> {
+    fn index_mut(&mut self, node: NodeId) -> &mut Weight {
+        &mut self.0[node as usize * self.1 + self.2]
+    }
+}
+
+struct LandmarkRepair<'a, G> {
+    graph: &'a G,
+    previous_weight: &'a HashMap<EdgeId, Weight>,
+    affected: &'a mut BitVec,
+    queue: &'a mut IndexdMinHeap<State<Weight>>,
+}
+
+impl<G: EdgeRandomAccessGraph<Link>> LandmarkRepair<'_, G> {
+    // Repair the distances from `landmark` along `outgoing` arcs.
+    // `increased` contains the changed arcs as pairs of first and second node in search direction.
+    fn run(
+        &mut self,
+        landmark: NodeId,
+        distances: &mut StridedDistances,
+        outgoing: &Adjacency,
+        incoming: &Adjacency,
+        increased: &[((NodeId, NodeId), EdgeId, Weight)],
+    ) {
+        let graph = self.graph;
+        let weight = |edge: EdgeId| graph.link(edge).weight;
+        let previous_weight = |edge: EdgeId| self.previous_weight.get(&edge).copied().unwrap_or_else(|| weight(edge));
+
+        // All nodes reachable over arcs which were tight before, starting from the changed arcs which were tight.
+        // Distances of all other nodes are still valid, since all their shortest paths avoid the changed arcs.
+        let mut affected = Vec::new();
+        for &((from, to), _, old) in increased {
+            if distances[from] < INFINITY && distances[from] + old == distances[to] && to != landmark && !self.affected.get(to as usize) {
+                self.affected.set(to as usize);
+                affected.push(to);
+            }
+        }
+        let mut idx = 0;
+        while idx < affected.len() {
+            let node = affected[idx];
+            idx += 1;
+            for &(head, edge) in outgoing.arcs(node) {
+                if distances[node] + previous_weight(edge) == distances[head] && head != landmark && !self.affected.get(head as usize) {
+                    self.affected.set(head as usize);
+                    affected.push(head);
+                }
+            }
+        }
+        report!("num_affected_nodes", affected.len());
+
+        for &node in &affected {
+            distances[node] = INFINITY;
+        }
+        for &node in &affected {
+            let dist = incoming
+                .arcs(node)
+                .iter()
+                .filter(|&&(tail, _)| !self.affected.get(tail as usize) && distances[tail] < INFINITY)
+                .map(|&(tail, edge)| distances[tail] + weight(edge))
+                .min()
+                .unwrap_or(INFINITY);
+            if dist < INFINITY {
+                distances[node] = dist;
+                self.queue.push(State { key: dist, node });
+            }
+        }
+
+        // Dijkstra restricted to the affected nodes, the others can not improve
+        while let Some(State { key: dist, node }) = self.queue.pop() {
+            for &(head, edge) in outgoing.arcs(node) {
+                let new_dist = dist + weight(edge);
+                if self.affected.get(head as usize) && new_dist < distances[head] {
+                    distances[head] = new_dist;
+                    if self.queue.contains_index(head as usize) {
+                        self.queue.decrease_key(State { key: new_dist, node: head });
+                    } else {
+                        self.queue.push(State { key: new_dist, node: head });
+                    }
+                }
+            }
+        }
+
+        for &node in &affected {
+            self.affected.unset(node as usize);
+        }
+    }
+}
+
+/// Bidirectional A* with ALT potentials.
+/// Forward and backward potentials are averaged, so both searches can meet like in a bidirectional Dijkstra.
+pub struct BiDirServer<'a, G> {
+    server: bidirectional_dijkstra::Server<G, OwnedGraph, AveragePotential<ALTPotential<'a>, ALTPotential<'a>>>,
+}
+
+impl<'a, G: LinkIterGraph> BiDirServer<'a, G> {
+    /// `pot_data` has to be computed on `graph` or on a graph with lower weights.
+    pub fn new(graph: G, pot_data: &'a ALTPotData) -> Self {
+        Self {
+            server: bidirectional_dijkstra::Server::new_with_potentials(
+                graph,
+                AveragePotential::new(pot_data.forward_potential(), pot_data.backward_potential()),
+            ),
+        }
+    }
+}
+
+impl<'a, G: LinkIterGraph> QueryServer for BiDirServer<'a, G> {
+    type P<'s>
+        = PathServerWrapper<'s, G, OwnedGraph, AveragePotential<ALTPotential<'a>, ALTPotential<'a>>, ChooseMinKeyDir>
+    where
+        Self: 's;
+
+    fn query(&mut self, query: Query) -> QueryResult<Self::P<'_>, Weight> {
+        self.server.query(query)
+    }
 }
 
 pub struct ALTPotential<'a> {
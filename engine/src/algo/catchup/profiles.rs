@@ -14,6 +14,33 @@ use crate::datastr::rank_select_map::{BitVec, FastClearBitVec};
 use crate::report::benchmark::Timer;
 
 use std::convert::TryInto;
+use std::ops::Range;
+
+/// Result of a profile query: the travel time for every departure time within the period
+/// together with the paths which are optimal for some departure time.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    breakpoints: Box<[TTFPoint]>,
+    pub paths: Vec<ProfilePath>,
+}
+
+/// A path (as original edge ids) and the departure time intervals in which it is optimal.
+#[derive(Debug, Clone)]
+pub struct ProfilePath {
+    pub edges: Vec<EdgeId>,
+    pub valid: Vec<Range<Timestamp>>,
+}
+
+impl Profile {
+    pub fn travel_time_function(&self) -> PeriodicPiecewiseLinearFunction<'_> {
+        PeriodicPiecewiseLinearFunction::new(&self.breakpoints)
+    }
+
+    /// Breakpoints of the travel time function, starting at zero and ending at the period.
+    pub fn breakpoints(&self) -> &[TTFPoint] {
+        &self.breakpoints
+    }
+}
 
 /// Query server struct for CATCHUp.
 /// Implements the common query trait.
@@ -94,6 +121,54 @@ impl<'a> Server<'a> {
         }
     }
 
+    /// Exact travel time profile from `from_node` to `to_node` and all distinct optimal paths.
+    /// `None` if `to_node` is not reachable.
+    pub fn profile(&mut self, from_node: NodeId, to_node: NodeId) -> Option<Profile> {
+        if from_node == to_node {
+            return Some(Profile {
+                breakpoints: vec![
+                    TTFPoint {
+                        at: Timestamp::ZERO,
+                        val: FlWeight::ZERO,
+                    },
+                    TTFPoint {
+                        at: period(),
+                        val: FlWeight::ZERO,
+                    },
+                ]
+                .into(),
+                paths: vec![ProfilePath {
+                    edges: Vec::new(),
+                    valid: vec![Timestamp::ZERO..period()],
+                }],
+            });
+        }
+
+        let (_, breakpoints, switchpoints) = self.distance(from_node, to_node);
+        if breakpoints.is_empty() {
+            return None;
+        }
+
+        let mut paths: Vec<ProfilePath> = Vec::new();
+        for (idx, (valid_from, edges)) in switchpoints.iter().enumerate() {
+            let valid_until = switchpoints.get(idx + 1).map(|&(t, _)| t).unwrap_or_else(period);
+            match paths.iter_mut().find(|path| &path.edges == edges) {
+                // consecutive intervals of the same path are merged
+                Some(path) if path.valid.last().unwrap().end == *valid_from => path.valid.last_mut().unwrap().end = valid_until,
+                Some(path) => path.valid.push(*valid_from..valid_until),
+                None => paths.push(ProfilePath {
+                    edges: edges.clone(),
+                    valid: vec![*valid_from..valid_until],
+                }),
+            }
+        }
+
+        Some(Profile {
+            breakpoints: breakpoints.into(),
+            paths,
+        })
+    }
+
     #[allow(clippy::collapsible_if)]
     #[allow(clippy::cognitive_complexity)]
     pub fn distance(&mut self, from_node: NodeId, to_node: NodeId) -> (Shortcut, Vec<TTFPoint>, Vec<(Timestamp, Vec<EdgeId>)>) {
@@ -101,6 +101,25 @@ impl<'a> CCHPotData<'a> {
     pub fn customized(&self) -> &CustomizedPerfect<'a, CCH> {
         &self.customized
     }
+
+    /// Forward and backward potentials on a shared copy of the customized graphs.
+    /// They only borrow the CCH, so they can outlive this and be cloned cheaply.
+    pub fn shared_potentials(&self) -> (SharedCCHPot<'a>, SharedCCHPot<'a>) {
+        let n = self.num_nodes();
+        let share = |graph: BorrowedGraph| -> SharedGraph { FirstOutGraph::new(graph.first_out().into(), graph.head().into(), graph.weight().into()) };
+        let forward = share(self.customized.forward_graph());
+        let backward = share(self.customized.backward_graph());
+        let potential = |forward_cch_graph: &SharedGraph, backward_cch_graph: &SharedGraph| CCHPotential {
+            cch: self.customized.cch,
+            stack: Vec::new(),
+            forward_cch_graph: forward_cch_graph.clone(),
+            backward_distances: TimestampedVector::new(n),
+            backward_cch_graph: backward_cch_graph.clone(),
+            potentials: TimestampedVector::new(n),
+            num_pot_computations: 0,
+        };
+        (potential(&forward, &backward), potential(&backward, &forward))
+    }
 }
 
 #[derive(Clone)]
@@ -116,6 +135,10 @@ pub struct CCHPotential<'a, GF, GB> {
 
 pub type BorrowedCCHPot<'a> = CCHPotential<'a, BorrowedGraph<'a>, BorrowedGraph<'a>>;
 
+type SharedGraph = FirstOutGraph<std::sync::Arc<[EdgeId]>, std::sync::Arc<[NodeId]>, std::sync::Arc<[Weight]>>;
+/// Potentials which do not borrow the customization they were created from, see `CCHPotData::shared_potentials`.
+pub type SharedCCHPot<'a> = CCHPotential<'a, SharedGraph, SharedGraph>;
+
 impl<'a> BorrowedCCHPot<'a> {
     pub fn new_from_customized<C: Customized<CCH = CCH>>(customized: &'a C) -> Self {
         let n = customized.forward_graph().num_nodes();
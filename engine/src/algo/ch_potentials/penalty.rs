@@ -14,6 +14,9 @@ pub struct Penalty<P> {
     // >,
     shortest_path_penalized: query::MultiThreadedBiDirSkipLowDegServer<BidirPenaltyPot<P>>,
     alternative_graph_dijkstra: query::SkipLowDegServer<AlternativeGraph<VirtualTopocoreGraph<OwnedGraph>>, DefaultOps, ZeroPotential, true, true>,
+    // the arcs incident to nodes outside of the core, to connect source and target to the core
+    comp_graph: OwnedGraph,
+    comp_dijkstra_data: DijkstraData<Weight>,
     reversed: ReversedGraphWithEdgeIds,
     tail: Vec<NodeId>,
     times_penalized: Vec<u8>,
@@ -21,6 +24,18 @@ pub struct Penalty<P> {
     reverse_dijkstra_data: DijkstraData<Weight>,
 }
 
+/// A route found by the penalty method together with its quality measures relative to the shortest route.
+#[derive(Debug, Clone)]
+pub struct AlternativeRoute {
+    pub path: Vec<NodeId>,
+    /// Length of the route with the unpenalized weights
+    pub distance: Weight,
+    /// Ratio of `distance` and the length of the shortest route
+    pub detour: f64,
+    /// Fraction of `distance` on arcs which are also part of the shortest route
+    pub sharing: f64,
+}
+
 type PenaltyPot<P> = RecyclingPotential<PotentialForPermutated<P>>;
 type BidirPenaltyPot<P> = SymmetricBiDirPotential<PenaltyPot<P>, PenaltyPot<P>>;
 
@@ -30,7 +45,7 @@ impl<P: Potential + Send + Clone> Penalty<P> {
         G: LinkIterable<NodeIdT>,
         OwnedGraph: BuildPermutated<G>,
     {
-        let (main_graph, _, virtual_topocore) = VirtualTopocoreGraph::new_topo_dijkstra_graphs(graph);
+        let (main_graph, comp_graph, virtual_topocore) = VirtualTopocoreGraph::<OwnedGraph>::new_topo_dijkstra_graphs(graph);
         let n = main_graph.num_nodes();
         let m = main_graph.num_arcs();
 
@@ -48,16 +63,7 @@ impl<P: Potential + Send + Clone> Penalty<P> {
             // shortest_path_penalized: query::BiDirCoreServer::new(
             shortest_path_penalized: query::MultiThreadedBiDirSkipLowDegServer::new(
                 main_graph.clone(),
-                SymmetricBiDirPotential::new(
-                    RecyclingPotential::new(PotentialForPermutated {
-                        order: virtual_topocore.order.clone(),
-                        potential: forward_potential,
-                    }),
-                    RecyclingPotential::new(PotentialForPermutated {
-                        order: virtual_topocore.order.clone(),
-                        potential: backward_potential,
-                    }),
-                ),
+                penalty_potential(&virtual_topocore.order, forward_potential, backward_potential),
                 // DefaultOpsWithLinkPath::default(),
             ),
             alternative_graph_dijkstra: query::SkipLowDegServer::new(
@@ -68,6 +74,8 @@ impl<P: Potential + Send + Clone> Penalty<P> {
                 ZeroPotential(),
                 DefaultOps::default(),
             ),
+            comp_dijkstra_data: DijkstraData::new(n),
+            comp_graph: comp_graph.graph,
             virtual_topocore,
             reversed,
             times_penalized: vec![0; n],
@@ -297,7 +305,112 @@ impl<P: Potential + Send + Clone> Penalty<P> {
         vec![pots.0.forward(), pots.0.backward(), pots.1.forward(), pots.1.backward()].into_iter()
     }
 
-    pub fn alternatives_iterative(&mut self, mut query: Query) -> Option<Vec<Vec<NodeId>>> {
+    pub fn alternatives_iterative(&mut self, query: Query) -> Option<Vec<Vec<NodeId>>> {
+        self.penalty_paths(query, 5).map(|paths| paths.into_iter().skip(1).map(|(path, _)| path).collect())
+    }
+
+    /// Switch to new weights and potentials for them, without repeating the preprocessing.
+    /// `graph` has to have the same arcs as the graph this was built with, only the weights may change.
+    pub fn set_metric<G: LinkIterable<Link>>(&mut self, graph: &G, forward_potential: P, backward_potential: P) {
+        debug_assert!(self.nodes_to_reset.is_empty());
+        let virtual_topocore = &self.virtual_topocore;
+        let in_core = |tail: NodeId, head: NodeId| virtual_topocore.node_type(tail).in_core() && virtual_topocore.node_type(head).in_core();
+
+        let core_weights = permutated_weights(graph, &virtual_topocore.order, in_core);
+        for (edge, &weight) in core_weights.iter().enumerate() {
+            self.shortest_path_penalized.set_edge_weight(edge as EdgeId, weight);
+        }
+        self.alternative_graph_dijkstra
+            .graph_mut()
+            .graph
+            .graph
+            .weights_mut()
+            .copy_from_slice(&core_weights);
+        let comp_weights = permutated_weights(graph, &virtual_topocore.order, |tail, head| !in_core(tail, head));
+        self.comp_graph.weights_mut().copy_from_slice(&comp_weights);
+
+        self.shortest_path_penalized
+            .set_potential(penalty_potential(&virtual_topocore.order, forward_potential, backward_potential));
+    }
+
+    /// Up to `k` alternatives for `query` with their quality measures.
+    /// The first route is always the shortest one, so the result contains at most `k + 1` routes.
+    /// Alternatives are only searched within the core of the graph.
+    /// Sources and targets outside of the core are connected to it through their shortest path to the node where they attach to the core,
+    /// which all routes share.
+    pub fn alternative_routes(&mut self, query: Query, k: usize) -> Option<Vec<AlternativeRoute>> {
+        let from = self.virtual_topocore.order.rank(query.from);
+        let to = self.virtual_topocore.order.rank(query.to);
+        let core_from = self.virtual_topocore.bridge_node(from).unwrap_or(from);
+        let core_to = self.virtual_topocore.bridge_node(to).unwrap_or(to);
+
+        if core_from == core_to {
+            // the route does not enter the core, so there are no alternatives
+            let (distance, path) = self.comp_path(from, to)?;
+            return Some(vec![AlternativeRoute {
+                path: path.into_iter().map(|node| self.virtual_topocore.order.node(node)).collect(),
+                distance,
+                detour: 1.0,
+                sharing: 1.0,
+            }]);
+        }
+
+        let (prefix_dist, prefix) = self.comp_path(from, core_from)?;
+        let (suffix_dist, suffix) = self.comp_path(core_to, to)?;
+        let paths = self.penalty_paths(query, k)?;
+        let weights = self.alternative_graph_dijkstra.graph().graph.graph.weight();
+        let mut main_edges: Vec<EdgeId> = paths[0].1.iter().map(|&EdgeIdT(edge)| edge).collect();
+        main_edges.sort_unstable();
+        let base_dist: Weight = prefix_dist + paths[0].1.iter().map(|&EdgeIdT(edge)| weights[edge as usize]).sum::<Weight>() + suffix_dist;
+
+        Some(
+            paths
+                .into_iter()
+                .map(|(path, path_edges)| {
+                    let core_dist: Weight = path_edges.iter().map(|&EdgeIdT(edge)| weights[edge as usize]).sum();
+                    let distance = prefix_dist + core_dist + suffix_dist;
+                    let shared: Weight = prefix_dist
+                        + suffix_dist
+                        + path_edges
+                            .iter()
+                            .filter(|&&EdgeIdT(edge)| main_edges.binary_search(&edge).is_ok())
+                            .map(|&EdgeIdT(edge)| weights[edge as usize])
+                            .sum::<Weight>();
+                    AlternativeRoute {
+                        path: prefix
+                            .iter()
+                            .chain(&path[1..])
+                            .chain(&suffix[1..])
+                            .map(|&node| self.virtual_topocore.order.node(node))
+                            .collect(),
+                        distance,
+                        detour: if base_dist == 0 { 1.0 } else { distance as f64 / base_dist as f64 },
+                        sharing: if distance == 0 { 1.0 } else { shared as f64 / distance as f64 },
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    // Shortest path outside of the core, between permutated node ids.
+    // Nodes outside of the core are only connected to the rest of the graph through the node where they attach to the core,
+    // so paths from or to that node never use core arcs.
+    fn comp_path(&mut self, from: NodeId, to: NodeId) -> Option<(Weight, Vec<NodeId>)> {
+        let mut ops = DefaultOps();
+        for node in DijkstraRun::query(&self.comp_graph, &mut self.comp_dijkstra_data, &mut ops, DijkstraInit::from(from)) {
+            if node == to {
+                break;
+            }
+        }
+        let distance = self.comp_dijkstra_data.distances[to as usize];
+        if distance >= INFINITY {
+            return None;
+        }
+        Some((distance, self.comp_dijkstra_data.node_path(from, to)))
+    }
+
+    // The shortest path followed by up to `max_alternatives` alternatives, as node and edge paths in the permutated core graph.
+    fn penalty_paths(&mut self, mut query: Query, max_alternatives: usize) -> Option<Vec<(Vec<NodeId>, Vec<EdgeIdT>)>> {
         query.permutate(&self.virtual_topocore.order);
         let core_from = self.virtual_topocore.bridge_node(query.from()).unwrap_or(query.from);
         let core_to = self.virtual_topocore.bridge_node(query.to()).unwrap_or(query.to);
@@ -306,7 +419,7 @@ impl<P: Potential + Send + Clone> Penalty<P> {
         let alt_time = Timer::new();
 
         if let Some(mut result) = silent_report_time_with_key("initial_query_running_time_ms", || self.shortest_path_penalized.query(query)).found() {
-            let mut alternatives = Vec::new();
+            let mut alternatives = vec![(result.node_path(), result.edge_path())];
             let base_dist = result.distance();
             report!("base_dist", base_dist);
 
@@ -316,8 +429,8 @@ impl<P: Potential + Send + Clone> Penalty<P> {
             let max_penalized_dist = (base_dist as f64 * 1.25 * 1.1) as Weight + 2 * rejoin_penalty;
             let max_num_penalizations = 5;
 
-            let mut path = result.node_path();
-            let mut path_edges = result.edge_path();
+            let mut path = alternatives[0].0.clone();
+            let mut path_edges = alternatives[0].1.clone();
             let shortest_path_penalized = &mut self.shortest_path_penalized;
             let alternative_graph_dijkstra = &mut self.alternative_graph_dijkstra;
 
@@ -332,6 +445,9 @@ impl<P: Potential + Send + Clone> Penalty<P> {
 
             let mut i: usize = 0;
             loop {
+                if alternatives.len() > max_alternatives {
+                    break;
+                }
                 i += 1;
 
                 for &EdgeIdT(edge) in &path_edges {
@@ -418,15 +534,11 @@ impl<P: Potential + Send + Clone> Penalty<P> {
 
                 if nonshared_length > path_orig_len / 5 {
                     let _alt_ctxt = alternatives_ctxt.push_collection_item();
-                    alternatives.push(path.clone());
+                    alternatives.push((path.clone(), path_edges.clone()));
                     alternative_graph_dijkstra.graph_mut().add_edges(&path_edges);
                     report!("sharing_percent", (path_orig_len - nonshared_length) * 100 / base_dist);
                     report!("iteration", i);
                     report!("running_time_ms", alt_time.get_passed().as_secs_f64() * 1000.0);
-
-                    if alternatives.len() > 4 {
-                        break;
-                    }
                 }
 
                 if path_orig_len > max_orig_dist || penalty_dist > max_penalized_dist {
@@ -459,6 +571,35 @@ impl<P: Potential + Send + Clone> Penalty<P> {
     }
 }
 
+fn penalty_potential<P: Potential>(order: &NodeOrder, forward_potential: P, backward_potential: P) -> BidirPenaltyPot<P> {
+    SymmetricBiDirPotential::new(
+        RecyclingPotential::new(PotentialForPermutated {
+            order: order.clone(),
+            potential: forward_potential,
+        }),
+        RecyclingPotential::new(PotentialForPermutated {
+            order: order.clone(),
+            potential: backward_potential,
+        }),
+    )
+}
+
+// The weights of the arcs accepted by `predicate` in the order `OwnedGraph::permutated_filtered` puts them.
+// Parallel arcs may end up in a different order, which does not matter as they only differ in their weights.
+fn permutated_weights<G: LinkIterable<Link>>(graph: &G, order: &NodeOrder, predicate: impl Fn(NodeId, NodeId) -> bool) -> Vec<Weight> {
+    let mut weights = Vec::new();
+    for (rank, &node) in order.order().iter().enumerate() {
+        let mut links: Vec<(NodeId, Weight)> = graph
+            .link_iter(node)
+            .map(|link| (order.rank(link.node), link.weight))
+            .filter(|&(head, _)| predicate(rank as NodeId, head))
+            .collect();
+        links.sort_unstable();
+        weights.extend(links.into_iter().map(|(_, weight)| weight));
+    }
+    weights
+}
+
 struct AlternativeGraph<G> {
     graph: G,
     contained_edges: FastClearBitVec,
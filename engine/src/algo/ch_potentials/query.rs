@@ -338,6 +338,46 @@ where
     }
 }
 
+// The topocore query enters and leaves the core through the bridge nodes of the source and target components,
+// so there is no combined search for several sources and targets.
+// Instead, all pairs are queried and the best one is queried again for its path.
+impl<G, O, P, const BCC_CORE: bool, const SKIP_DEG_2: bool, const SKIP_DEG_3: bool> MultiQueryServer for Server<G, O, P, BCC_CORE, SKIP_DEG_2, SKIP_DEG_3>
+where
+    P: Potential,
+    O: DijkstraOps<G, Label = Timestamp>,
+    G: LinkIterable<NodeIdT> + LinkIterable<O::Arc>,
+{
+    type P<'s> = PathServerWrapper<'s, G, O, P, Query, BCC_CORE, SKIP_DEG_2, SKIP_DEG_3> where Self: 's;
+
+    fn multi_query(&mut self, query: &MultiQuery) -> QueryResult<Self::P<'_>, Weight> {
+        let mut best: Option<(Weight, Query)> = None;
+        let mut pairs_ctxt = push_collection_context("pairs");
+        for &(from, source_offset) in query.sources() {
+            for &(to, target_offset) in query.targets() {
+                let _item = pairs_ctxt.push_collection_item();
+                let pair = Query { from, to };
+                if let Some(dist) = self.distance(pair, |_, _, _, _| ()) {
+                    let dist = source_offset + dist + target_offset;
+                    if best.map(|(best_dist, _)| dist < best_dist).unwrap_or(true) {
+                        best = Some((dist, pair));
+                    }
+                }
+            }
+        }
+        drop(pairs_ctxt);
+
+        match best {
+            Some((dist, pair)) => {
+                let _ctxt = push_context("path_query");
+                self.distance(pair, |_, _, _, _| ());
+                QueryResult::new(Some(dist), PathServerWrapper(self, pair))
+            }
+            // without a distance, the path will never be requested, so the pair does not matter
+            None => QueryResult::new(None, PathServerWrapper(self, Query { from: 0, to: 0 })),
+        }
+    }
+}
+
 pub struct VirtualTopocoreOps<O>(pub O);
 
 impl<G, O> DijkstraOps<VirtualTopocoreGraph<G>> for VirtualTopocoreOps<O>
@@ -1296,6 +1336,11 @@ impl<P: BiDirPotential + Clone + Send> MultiThreadedBiDirSkipLowDegServer<P> {
     pub fn potentials(&self) -> (&P, &P) {
         (&self.fw_potential, &self.bw_potential)
     }
+
+    pub(super) fn set_potential(&mut self, potential: P) {
+        self.fw_potential = potential.clone();
+        self.bw_potential = potential;
+    }
 }
 
 pub struct MultiThreadedBiDirCorePathServerWrapper<'s, P, Q>(&'s mut MultiThreadedBiDirSkipLowDegServer<P>, Q);
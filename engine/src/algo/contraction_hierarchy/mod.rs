@@ -1,14 +1,17 @@
 //! Experimental prototype implementation of Contraction Hierarchies in rust.
 //!
-//! Not tuned for performance yet.
-//! No node ordering implemented yet, depends on getting a precalculated order.
+//! `contract` and `overlay` depend on getting a precalculated order and are not tuned for performance yet.
+//! `ordering::contract_with_ordering` computes an order and the hierarchy at the same time.
 
 use std::marker::PhantomData;
 
 use super::*;
 use crate::algo::{a_star::*, dijkstra::*};
 use crate::datastr::node_order::NodeOrder;
+use crate::io::*;
+use crate::report::*;
 
+pub mod ordering;
 pub mod query;
 
 /// Struct for a Contraction Hierarchy, that is the completely preprocessed
@@ -32,6 +35,43 @@ impl ContractionHierarchy {
     }
 }
 
+impl Deconstruct for ContractionHierarchy {
+    fn save_each(&self, store: &dyn Fn(&str, &dyn Save) -> std::io::Result<()>) -> std::io::Result<()> {
+        store("forward", &Sub(&self.forward))?;
+        store("backward", &Sub(&self.backward))?;
+        if let Some((forward_middle_nodes, backward_middle_nodes)) = &self.middle_nodes {
+            store("forward_middle_nodes", forward_middle_nodes)?;
+            store("backward_middle_nodes", backward_middle_nodes)?;
+        }
+        Ok(())
+    }
+}
+
+impl Reconstruct for ContractionHierarchy {
+    fn reconstruct_with(loader: Loader) -> std::io::Result<Self> {
+        let forward: OwnedGraph = loader.reconstruct("forward")?;
+        let backward: OwnedGraph = loader.reconstruct("backward")?;
+        // hierarchies from `from_contracted_graph` have no unpacking info
+        let middle_nodes = if loader.path().join("forward_middle_nodes").exists() {
+            let middle_nodes: (Vec<NodeId>, Vec<NodeId>) = (loader.load("forward_middle_nodes")?, loader.load("backward_middle_nodes")?);
+            if middle_nodes.0.len() != forward.num_arcs() || middle_nodes.1.len() != backward.num_arcs() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "middle nodes do not match the number of CH arcs",
+                ));
+            }
+            Some(middle_nodes)
+        } else {
+            None
+        };
+        Ok(ContractionHierarchy {
+            forward,
+            backward,
+            middle_nodes,
+        })
+    }
+}
+
 #[derive(Debug, PartialEq)]
 enum ShortcutResult {
     NewShortcut,
@@ -0,0 +1,260 @@
+//! Node ordering and contraction in one go.
+//!
+//! Nodes are contracted bottom-up in rounds.
+//! Each round contracts an independent set of nodes whose priority is smaller than that of all their neighbors, in parallel.
+//! The priority of a node is a linear combination of its edge difference (shortcuts needed minus arcs removed),
+//! the number of already contracted neighbors and the depth of the search space below it.
+//! Witness searches are Dijkstras limited to a number of settled nodes.
+//! When the limit is hit, the shortcut is inserted even though it might not be necessary,
+//! so the hierarchy is always correct, just possibly a bit larger.
+
+use super::*;
+use crate::datastr::{index_heap::IndexdMinHeap, timestamped_vector::TimestampedVector};
+use rayon::prelude::*;
+
+/// Tuning parameters for the node ordering.
+#[derive(Debug, Clone, Copy)]
+pub struct OrderingParams {
+    /// Maximum number of nodes settled in a witness search
+    pub witness_search_limit: usize,
+    pub edge_difference_factor: i64,
+    pub deleted_neighbors_factor: i64,
+    pub level_factor: i64,
+}
+
+impl Default for OrderingParams {
+    fn default() -> Self {
+        Self {
+            witness_search_limit: 500,
+            edge_difference_factor: 2,
+            deleted_neighbors_factor: 1,
+            level_factor: 1,
+        }
+    }
+}
+
+// Arcs to remaining nodes during the contraction, with the middle node for shortcuts (`n` for original arcs).
+#[derive(Debug, Clone, Default)]
+struct DynamicNode {
+    outgoing: Vec<(NodeId, Weight, NodeId)>,
+    incoming: Vec<(NodeId, Weight, NodeId)>,
+}
+
+fn insert_or_decrease(links: &mut Vec<(NodeId, Weight, NodeId)>, node: NodeId, weight: Weight, middle: NodeId) {
+    match links.iter_mut().find(|(other, _, _)| *other == node) {
+        Some(link) => {
+            if weight < link.1 {
+                *link = (node, weight, middle);
+            }
+        }
+        None => links.push((node, weight, middle)),
+    }
+}
+
+struct WitnessSearch {
+    distances: TimestampedVector<Weight>,
+    queue: IndexdMinHeap<State<Weight>>,
+}
+
+impl WitnessSearch {
+    fn new(n: usize) -> Self {
+        Self {
+            distances: TimestampedVector::new(n),
+            queue: IndexdMinHeap::new(n),
+        }
+    }
+
+    // Shortcuts required when contracting `node`, as triples of tail, head and weight.
+    // Witness paths may not pass through nodes marked in `excluded`.
+    fn shortcuts(&mut self, nodes: &[DynamicNode], excluded: &[bool], node: NodeId, limit: usize) -> Vec<(NodeId, NodeId, Weight)> {
+        let mut shortcuts = Vec::new();
+        let outgoing = &nodes[node as usize].outgoing;
+
+        for &(from, in_weight, _) in &nodes[node as usize].incoming {
+            let max_weight = outgoing
+                .iter()
+                .filter(|&&(to, _, _)| to != from)
+                .map(|&(_, out_weight, _)| in_weight + out_weight)
+                .max();
+            let max_weight = match max_weight {
+                Some(max_weight) => max_weight,
+                None => continue,
+            };
+
+            self.distances.reset();
+            self.queue.clear();
+            self.distances[from as usize] = 0;
+            self.queue.push(State { key: 0, node: from });
+            let mut settled = 0;
+            while let Some(State { key: dist, node: tail }) = self.queue.pop() {
+                settled += 1;
+                if dist > max_weight || settled > limit {
+                    break;
+                }
+                for &(head, weight, _) in &nodes[tail as usize].outgoing {
+                    if head == node || excluded[head as usize] {
+                        continue;
+                    }
+                    let new_dist = dist + weight;
+                    if new_dist < self.distances[head as usize] {
+                        self.distances[head as usize] = new_dist;
+                        if self.queue.contains_index(head as usize) {
+                            self.queue.decrease_key(State { key: new_dist, node: head });
+                        } else {
+                            self.queue.push(State { key: new_dist, node: head });
+                        }
+                    }
+                }
+            }
+
+            for &(to, out_weight, _) in outgoing {
+                let weight = in_weight + out_weight;
+                if to != from && weight < INFINITY && self.distances[to as usize] > weight {
+                    shortcuts.push((from, to, weight));
+                }
+            }
+        }
+
+        shortcuts
+    }
+}
+
+/// Compute a node order and contract the graph along it.
+/// The returned hierarchy uses ranks as node ids, so the order has to be passed on to the query server.
+pub fn contract_with_ordering<Graph: LinkIterGraph>(graph: &Graph, params: OrderingParams) -> (ContractionHierarchy, NodeOrder) {
+    report!("algo", "CH Node Ordering");
+    let n = graph.num_nodes();
+    let no_middle = n as NodeId;
+
+    let mut nodes = vec![DynamicNode::default(); n];
+    for tail in 0..n as NodeId {
+        for Link { node: head, weight } in graph.link_iter(tail) {
+            if head != tail && weight < INFINITY {
+                insert_or_decrease(&mut nodes[tail as usize].outgoing, head, weight, no_middle);
+                insert_or_decrease(&mut nodes[head as usize].incoming, tail, weight, no_middle);
+            }
+        }
+    }
+
+    // Nodes of the current independent set must not serve as witnesses for each other,
+    // because they are all removed at once.
+    let mut in_set = vec![false; n];
+    let mut deleted_neighbors = vec![0i64; n];
+    let mut level = vec![0i64; n];
+    let priority = |nodes: &[DynamicNode], excluded: &[bool], witness: &mut WitnessSearch, node: NodeId, deleted_neighbors: i64, level: i64| {
+        let removed = (nodes[node as usize].outgoing.len() + nodes[node as usize].incoming.len()) as i64;
+        let edge_difference = witness.shortcuts(nodes, excluded, node, params.witness_search_limit).len() as i64 - removed;
+        params.edge_difference_factor * edge_difference + params.deleted_neighbors_factor * deleted_neighbors + params.level_factor * level
+    };
+
+    let mut priorities: Vec<i64> = report_time_with_key("initial priorities", "initial_priorities_running_time_ms", || {
+        (0..n as NodeId)
+            .into_par_iter()
+            .map_init(|| WitnessSearch::new(n), |witness, node| priority(&nodes, &in_set, witness, node, 0, 0))
+            .collect()
+    });
+
+    // upward and downward arcs of each node in the final hierarchy, with original ids
+    let mut upward: Vec<Vec<(NodeId, Weight, NodeId)>> = vec![Vec::new(); n];
+    let mut downward: Vec<Vec<(NodeId, Weight, NodeId)>> = vec![Vec::new(); n];
+    let mut order = Vec::with_capacity(n);
+    let mut remaining: Vec<NodeId> = (0..n as NodeId).collect();
+    let mut contracted = vec![false; n];
+    let mut num_rounds = 0;
+
+    report_time_with_key("contraction", "contraction_running_time_ms", || {
+        while !remaining.is_empty() {
+            num_rounds += 1;
+            // ties are broken by id, so the set is never empty
+            let key = |node: NodeId| (priorities[node as usize], node);
+            let independent: Vec<NodeId> = remaining
+                .par_iter()
+                .copied()
+                .filter(|&node| {
+                    let links = &nodes[node as usize];
+                    links.outgoing.iter().chain(&links.incoming).all(|&(other, _, _)| key(node) < key(other))
+                })
+                .collect();
+
+            for &node in &independent {
+                in_set[node as usize] = true;
+            }
+            let shortcuts: Vec<_> = independent
+                .par_iter()
+                .map_init(
+                    || WitnessSearch::new(n),
+                    |witness, &node| witness.shortcuts(&nodes, &in_set, node, params.witness_search_limit),
+                )
+                .collect();
+            for &node in &independent {
+                in_set[node as usize] = false;
+            }
+
+            let mut touched = Vec::new();
+            for (&node, shortcuts) in independent.iter().zip(shortcuts) {
+                order.push(node);
+                contracted[node as usize] = true;
+                let DynamicNode { outgoing, incoming } = std::mem::take(&mut nodes[node as usize]);
+                for &(head, _, _) in &outgoing {
+                    nodes[head as usize].incoming.retain(|&(other, _, _)| other != node);
+                }
+                for &(tail, _, _) in &incoming {
+                    nodes[tail as usize].outgoing.retain(|&(other, _, _)| other != node);
+                }
+                for &(neighbor, _, _) in outgoing.iter().chain(&incoming) {
+                    deleted_neighbors[neighbor as usize] += 1;
+                    level[neighbor as usize] = std::cmp::max(level[neighbor as usize], level[node as usize] + 1);
+                    touched.push(neighbor);
+                }
+                for (from, to, weight) in shortcuts {
+                    insert_or_decrease(&mut nodes[from as usize].outgoing, to, weight, node);
+                    insert_or_decrease(&mut nodes[to as usize].incoming, from, weight, node);
+                }
+                upward[node as usize] = outgoing;
+                downward[node as usize] = incoming;
+            }
+
+            touched.sort_unstable();
+            touched.dedup();
+            let updated: Vec<i64> = touched
+                .par_iter()
+                .map_init(
+                    || WitnessSearch::new(n),
+                    |witness, &node| priority(&nodes, &in_set, witness, node, deleted_neighbors[node as usize], level[node as usize]),
+                )
+                .collect();
+            for (&node, priority) in touched.iter().zip(updated) {
+                priorities[node as usize] = priority;
+            }
+
+            remaining.retain(|&node| !contracted[node as usize]);
+        }
+    });
+    report!("num_rounds", num_rounds);
+
+    let order = NodeOrder::from_node_order(order);
+    let to_ranks = |arcs: &Vec<(NodeId, Weight, NodeId)>| -> (Vec<Link>, Vec<NodeId>) {
+        arcs.iter()
+            .map(|&(node, weight, middle)| {
+                let middle = if middle == no_middle { no_middle } else { order.rank(middle) };
+                (
+                    Link {
+                        node: order.rank(node),
+                        weight,
+                    },
+                    middle,
+                )
+            })
+            .unzip()
+    };
+    let (forward, forward_middle_nodes): (Vec<_>, Vec<_>) = order.order().iter().map(|&node| to_ranks(&upward[node as usize])).unzip();
+    let (backward, backward_middle_nodes): (Vec<_>, Vec<_>) = order.order().iter().map(|&node| to_ranks(&downward[node as usize])).unzip();
+
+    let ch = ContractionHierarchy {
+        forward: OwnedGraph::from_adjancecy_lists(forward),
+        backward: OwnedGraph::from_adjancecy_lists(backward),
+        middle_nodes: Some((forward_middle_nodes.concat(), backward_middle_nodes.concat())),
+    };
+    report!("num_ch_arcs", ch.forward.num_arcs() + ch.backward.num_arcs());
+    (ch, order)
+}
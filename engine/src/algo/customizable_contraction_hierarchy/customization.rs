@@ -1,13 +1,17 @@
 use super::*;
 use crate::report::*;
 use rayon::prelude::*;
-use std::{cell::RefCell, cmp::min};
+use std::{
+    cell::RefCell,
+    cmp::{max, min},
+};
 
 pub mod parallelization;
 use parallelization::*;
 pub mod directed;
 pub mod ftd;
 pub mod ftd_for_pot;
+pub mod incremental;
 pub mod validity;
 
 // One mapping of node id to weight for each thread during the scope of the customization.
@@ -51,6 +55,7 @@ where
 }
 
 pub use directed::customize_directed_perfect;
+pub use incremental::customize_incremental;
 
 /// Customize with zero metric.
 /// Edges that have weight infinity after customization will have this weight
@@ -0,0 +1,111 @@
+use super::*;
+use std::collections::BTreeSet;
+
+/// Update an existing customization after the weights of a few arcs changed.
+/// `metric` has to contain all weights, including the changed ones.
+/// `changed_arcs` contains the tail and the id of each arc whose weight changed, changed self loops are ignored.
+///
+/// Only CCH edges which may be affected are recomputed, starting from the edges of the changed arcs.
+/// The weight of an edge depends only on its input weight and its lower triangles.
+/// So when an edge `(low, a)` changes, all edges `(a, b)` of the upper triangles of it may change.
+/// Edge ids are ordered by the rank of the lower node, so processing them in increasing id order
+/// ensures that all lower triangles of an edge are final once it gets recomputed.
+/// Returns the number of recomputed edges.
+pub fn customize_incremental<Graph>(customized: &mut CustomizedBasic<CCH>, metric: &Graph, changed_arcs: &[(NodeIdT, EdgeIdT)]) -> usize
+where
+    Graph: LinkIterGraph + EdgeRandomAccessGraph<Link>,
+{
+    let cch = customized.cch;
+    let order = cch.node_order();
+
+    let mut queue = BTreeSet::new();
+    for &(NodeIdT(tail), EdgeIdT(arc)) in changed_arcs {
+        let tail_rank = order.rank(tail);
+        let head_rank = order.rank(metric.link(arc).node);
+        // self loops are not part of the CCH and never on a shortest path
+        if tail_rank == head_rank {
+            continue;
+        }
+        let (lower, upper) = if tail_rank < head_rank {
+            (tail_rank, head_rank)
+        } else {
+            (head_rank, tail_rank)
+        };
+        queue.insert(edge_id(cch, lower, upper));
+    }
+
+    let mut num_recomputed = 0;
+    report_time_with_key("CCH incremental customization", "incremental_customization_running_time_ms", || {
+        while let Some(edge) = queue.pop_first() {
+            num_recomputed += 1;
+            let lower = cch.edge_id_to_tail(edge);
+            let upper = cch.head()[edge as usize];
+
+            let mut upward = cch.forward_cch_edge_to_orig_arc()[edge as usize]
+                .iter()
+                .map(|&EdgeIdT(arc)| metric.link(arc).weight)
+                .fold(INFINITY, min);
+            let mut downward = cch.backward_cch_edge_to_orig_arc()[edge as usize]
+                .iter()
+                .map(|&EdgeIdT(arc)| metric.link(arc).weight)
+                .fold(INFINITY, min);
+            let mut upward_unpack = (InRangeOption::NONE, InRangeOption::NONE);
+            let mut downward_unpack = (InRangeOption::NONE, InRangeOption::NONE);
+
+            // `inverted` contains the downward neighbors sorted ascending.
+            // We do a coordinated linear sweep over both neighborhoods.
+            // Whenever we find a common neighbor, we have a lower triangle.
+            let mut lower_iter = cch.inverted.link_iter(lower).peekable();
+            let mut upper_iter = cch.inverted.link_iter(upper).peekable();
+            while let (Some(&(NodeIdT(from_lower), Reversed(EdgeIdT(first_edge)))), Some(&(NodeIdT(from_upper), Reversed(EdgeIdT(second_edge))))) =
+                (lower_iter.peek(), upper_iter.peek())
+            {
+                match from_lower.cmp(&from_upper) {
+                    Ordering::Less => lower_iter.next(),
+                    Ordering::Greater => upper_iter.next(),
+                    Ordering::Equal => {
+                        let triang_weight = customized.downward[first_edge as usize] + customized.upward[second_edge as usize];
+                        if triang_weight < upward {
+                            upward = triang_weight;
+                            upward_unpack = (InRangeOption::some(first_edge), InRangeOption::some(second_edge));
+                        }
+                        let triang_weight = customized.downward[second_edge as usize] + customized.upward[first_edge as usize];
+                        if triang_weight < downward {
+                            downward = triang_weight;
+                            downward_unpack = (InRangeOption::some(second_edge), InRangeOption::some(first_edge));
+                        }
+
+                        lower_iter.next();
+                        upper_iter.next()
+                    }
+                };
+            }
+
+            customized.up_unpacking[edge as usize] = upward_unpack;
+            customized.down_unpacking[edge as usize] = downward_unpack;
+
+            if customized.upward[edge as usize] == upward && customized.downward[edge as usize] == downward {
+                continue;
+            }
+            customized.upward[edge as usize] = upward;
+            customized.downward[edge as usize] = downward;
+
+            // this edge is part of a lower triangle of all edges between `upper` and the other upward neighbors of `lower`
+            for other in cch.neighbor_iter(lower) {
+                if other != upper {
+                    queue.insert(edge_id(cch, min(upper, other), max(upper, other)));
+                }
+            }
+        }
+    });
+    report!("num_recustomized_edges", num_recomputed);
+
+    num_recomputed
+}
+
+// Id of the edge between two nodes (by rank) which are known to be adjacent in the chordal supergraph.
+fn edge_id(cch: &CCH, lower: NodeId, upper: NodeId) -> EdgeId {
+    let edges = cch.neighbor_edge_indices_usize(lower);
+    let idx = cch.head()[edges.clone()].binary_search(&upper).expect("nodes not adjacent in CCH");
+    (edges.start + idx) as EdgeId
+}
@@ -13,11 +13,12 @@ mod contraction;
 use contraction::*;
 pub mod customization;
 pub use customization::ftd as ftd_cch;
-pub use customization::{customize, customize_directed, customize_directed_perfect, customize_perfect};
+pub use customization::{customize, customize_directed, customize_directed_perfect, customize_incremental, customize_perfect};
 pub mod separator_decomposition;
 use separator_decomposition::*;
 mod reorder;
 pub use reorder::*;
+pub mod nested_dissection;
 pub mod query;
 
 /// Execute first phase, that is metric independent preprocessing.
@@ -440,6 +441,19 @@ pub struct CustomizedBasic<'a, CCH> {
     down_unpacking: Vec<(InRangeOption<EdgeId>, InRangeOption<EdgeId>)>,
 }
 
+// Manual impl, deriving would require `C: Clone`, but we only clone the reference.
+impl<'a, C> Clone for CustomizedBasic<'a, C> {
+    fn clone(&self) -> Self {
+        Self {
+            cch: self.cch,
+            upward: self.upward.clone(),
+            downward: self.downward.clone(),
+            up_unpacking: self.up_unpacking.clone(),
+            down_unpacking: self.down_unpacking.clone(),
+        }
+    }
+}
+
 impl<'a, C: CCHT> CustomizedBasic<'a, C> {
     fn new(
         cch: &'a C,
@@ -461,6 +475,45 @@ impl<'a, C: CCHT> CustomizedBasic<'a, C> {
         (self.upward, self.downward)
     }
 }
+
+impl<C> crate::io::Deconstruct for CustomizedBasic<'_, C> {
+    fn save_each(&self, store: &dyn Fn(&str, &dyn crate::io::Save) -> std::io::Result<()>) -> std::io::Result<()> {
+        store("upward", &self.upward)?;
+        store("downward", &self.downward)?;
+        store("up_unpacking", &self.up_unpacking)?;
+        store("down_unpacking", &self.down_unpacking)?;
+        Ok(())
+    }
+}
+
+impl<'a, C: CCHT> crate::io::ReconstructPrepared<CustomizedBasic<'a, C>> for &'a C {
+    fn reconstruct_with(self, loader: Loader) -> std::io::Result<CustomizedBasic<'a, C>> {
+        let customized = CustomizedBasic::new(
+            self,
+            loader.load("upward")?,
+            loader.load("downward")?,
+            loader.load("up_unpacking")?,
+            loader.load("down_unpacking")?,
+        );
+        // files of a different CCH could still be loaded successfully, so check at least the sizes
+        let m = self.forward_head().len();
+        if [
+            customized.upward.len(),
+            customized.downward.len(),
+            customized.up_unpacking.len(),
+            customized.down_unpacking.len(),
+        ]
+        .iter()
+        .any(|&len| len != m)
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "customization does not match the number of CCH edges",
+            ));
+        }
+        Ok(customized)
+    }
+}
 impl<'a, C: CCHT> Customized for CustomizedBasic<'a, C> {
     type CCH = C;
     fn forward_graph(&self) -> BorrowedGraph {
@@ -562,6 +615,70 @@ impl<'a, C: CCHT> Customized for CustomizedPerfect<'a, C> {
     }
 }
 
+/// Shared customization, so multiple query servers (e.g. one per thread) can use the same metric.
+impl<T: Customized> Customized for std::sync::Arc<T> {
+    type CCH = T::CCH;
+    fn forward_graph(&self) -> BorrowedGraph {
+        (**self).forward_graph()
+    }
+    fn backward_graph(&self) -> BorrowedGraph {
+        (**self).backward_graph()
+    }
+    fn cch(&self) -> &Self::CCH {
+        (**self).cch()
+    }
+    fn forward_tail(&self) -> &[NodeId] {
+        (**self).forward_tail()
+    }
+    fn backward_tail(&self) -> &[NodeId] {
+        (**self).backward_tail()
+    }
+    fn unpack_outgoing(&self, edge: EdgeIdT) -> Option<(EdgeIdT, EdgeIdT, NodeIdT)> {
+        (**self).unpack_outgoing(edge)
+    }
+    fn unpack_incoming(&self, edge: EdgeIdT) -> Option<(EdgeIdT, EdgeIdT, NodeIdT)> {
+        (**self).unpack_incoming(edge)
+    }
+    fn forward_unpacking(&self) -> &[(InRangeOption<EdgeId>, InRangeOption<EdgeId>)] {
+        (**self).forward_unpacking()
+    }
+    fn backward_unpacking(&self) -> &[(InRangeOption<EdgeId>, InRangeOption<EdgeId>)] {
+        (**self).backward_unpacking()
+    }
+}
+
+/// Borrowed customization, so a query server can be used next to other algorithms working on the same customization.
+impl<T: Customized> Customized for &T {
+    type CCH = T::CCH;
+    fn forward_graph(&self) -> BorrowedGraph {
+        (**self).forward_graph()
+    }
+    fn backward_graph(&self) -> BorrowedGraph {
+        (**self).backward_graph()
+    }
+    fn cch(&self) -> &Self::CCH {
+        (**self).cch()
+    }
+    fn forward_tail(&self) -> &[NodeId] {
+        (**self).forward_tail()
+    }
+    fn backward_tail(&self) -> &[NodeId] {
+        (**self).backward_tail()
+    }
+    fn unpack_outgoing(&self, edge: EdgeIdT) -> Option<(EdgeIdT, EdgeIdT, NodeIdT)> {
+        (**self).unpack_outgoing(edge)
+    }
+    fn unpack_incoming(&self, edge: EdgeIdT) -> Option<(EdgeIdT, EdgeIdT, NodeIdT)> {
+        (**self).unpack_incoming(edge)
+    }
+    fn forward_unpacking(&self) -> &[(InRangeOption<EdgeId>, InRangeOption<EdgeId>)] {
+        (**self).forward_unpacking()
+    }
+    fn backward_unpacking(&self) -> &[(InRangeOption<EdgeId>, InRangeOption<EdgeId>)] {
+        (**self).backward_unpacking()
+    }
+}
+
 impl<C> crate::io::Deconstruct for CustomizedPerfect<'_, C> {
     fn save_each(&self, store: &dyn Fn(&str, &dyn crate::io::Save) -> std::io::Result<()>) -> std::io::Result<()> {
         store("fw_graph", &Sub(&self.upward))?;
@@ -0,0 +1,292 @@
+//! Nested dissection orders for CCHs, computed with inertial flow.
+//!
+//! The graph is recursively split by small node separators.
+//! To find a separator, the nodes of a cell are projected onto several lines through their geographic coordinates.
+//! The first and the last nodes along each line become sources and sinks of a max flow problem with unit node capacities.
+//! The smallest min cut over all lines is the separator of the cell.
+//! See Schild and Sommer, "On Balanced Separators in Road Networks".
+//!
+//! Separators get the highest ranks, so the order can be passed directly to `CCH::fix_order_and_build`,
+//! which takes care of the reordering for the separator based customization.
+
+use super::*;
+use rayon::prelude::*;
+use std::collections::VecDeque;
+
+/// Fraction of the nodes at each end of a projection which become sources and sinks.
+const TERMINAL_FRACTION: f64 = 0.25;
+/// Cells with at most this many nodes are not split any further.
+const MAX_LEAF_CELL_SIZE: usize = 16;
+/// Directions of the lines the nodes get projected onto, as factors for latitude and longitude.
+const DIRECTIONS: [(f32, f32); 4] = [(1.0, 0.0), (0.0, 1.0), (1.0, 1.0), (1.0, -1.0)];
+
+/// Compute a nested dissection order for `graph` based on the node coordinates.
+/// Arc directions are ignored.
+pub fn nested_dissection_order<G: LinkIterable<NodeIdT>>(graph: &G, latitude: &[f32], longitude: &[f32]) -> NodeOrder {
+    let coords: Vec<(f32, f32)> = latitude.iter().copied().zip(longitude.iter().copied()).collect();
+    assert_eq!(coords.len(), graph.num_nodes());
+    order_for_coords(graph, &coords)
+}
+
+/// Compute a nested dissection order for the turn expanded graph `exp_graph` of `graph`,
+/// where each node of `exp_graph` is an arc of `graph`, as built by `line_graph`.
+/// Each arc is placed at the middle between its tail and head.
+pub fn turn_expanded_nested_dissection_order<G, E>(graph: &G, exp_graph: &E, latitude: &[f32], longitude: &[f32]) -> NodeOrder
+where
+    G: LinkIterable<NodeIdT>,
+    E: LinkIterable<NodeIdT>,
+{
+    assert_eq!(exp_graph.num_nodes(), graph.num_arcs());
+    let mut coords = Vec::with_capacity(graph.num_arcs());
+    for tail in 0..graph.num_nodes() {
+        for NodeIdT(head) in graph.link_iter(tail as NodeId) {
+            coords.push((
+                (latitude[tail] + latitude[head as usize]) / 2.0,
+                (longitude[tail] + longitude[head as usize]) / 2.0,
+            ));
+        }
+    }
+    order_for_coords(exp_graph, &coords)
+}
+
+fn order_for_coords<G: LinkIterable<NodeIdT>>(graph: &G, coords: &[(f32, f32)]) -> NodeOrder {
+    report_time_with_key("nested dissection order", "nested_dissection_running_time_ms", || {
+        let graph = UndirectedGraph::new(graph);
+        let order = dissect(&graph, coords, (0..graph.num_nodes() as NodeId).collect());
+        NodeOrder::from_node_order(order)
+    })
+}
+
+// Symmetric graph without loops and multi arcs.
+struct UndirectedGraph {
+    first_out: Vec<usize>,
+    head: Vec<NodeId>,
+}
+
+impl UndirectedGraph {
+    fn new<G: LinkIterable<NodeIdT>>(graph: &G) -> Self {
+        let mut edges = Vec::with_capacity(2 * graph.num_arcs());
+        for node in 0..graph.num_nodes() as NodeId {
+            for NodeIdT(head) in graph.link_iter(node) {
+                if head != node {
+                    edges.push((node, head));
+                    edges.push((head, node));
+                }
+            }
+        }
+        edges.sort_unstable();
+        edges.dedup();
+
+        let mut first_out = vec![0; graph.num_nodes() + 1];
+        for &(tail, _) in &edges {
+            first_out[tail as usize + 1] += 1;
+        }
+        for node in 0..graph.num_nodes() {
+            first_out[node + 1] += first_out[node];
+        }
+
+        UndirectedGraph {
+            first_out,
+            head: edges.into_iter().map(|(_, head)| head).collect(),
+        }
+    }
+
+    fn num_nodes(&self) -> usize {
+        self.first_out.len() - 1
+    }
+
+    fn neighbors(&self, node: NodeId) -> &[NodeId] {
+        &self.head[self.first_out[node as usize]..self.first_out[node as usize + 1]]
+    }
+}
+
+// The subgraph induced by the nodes of a cell with local ids, that is the positions in the sorted node list.
+struct Cell {
+    nodes: Vec<NodeId>,
+    first_out: Vec<usize>,
+    head: Vec<usize>,
+}
+
+impl Cell {
+    fn new(graph: &UndirectedGraph, nodes: Vec<NodeId>) -> Self {
+        debug_assert!(nodes.windows(2).all(|pair| pair[0] < pair[1]));
+        let mut first_out = Vec::with_capacity(nodes.len() + 1);
+        first_out.push(0);
+        let mut head = Vec::new();
+        for &node in &nodes {
+            head.extend(graph.neighbors(node).iter().filter_map(|neighbor| nodes.binary_search(neighbor).ok()));
+            first_out.push(head.len());
+        }
+        Cell { nodes, first_out, head }
+    }
+
+    fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn neighbors(&self, node: usize) -> &[usize] {
+        &self.head[self.first_out[node]..self.first_out[node + 1]]
+    }
+
+    // Connected components of the cell without the `removed` nodes, as sorted lists of global node ids.
+    fn components(&self, removed: &[bool]) -> Vec<Vec<NodeId>> {
+        let mut visited = removed.to_vec();
+        let mut components = Vec::new();
+        let mut queue = VecDeque::new();
+        for start in 0..self.len() {
+            if visited[start] {
+                continue;
+            }
+            visited[start] = true;
+            queue.push_back(start);
+            let mut component = Vec::new();
+            while let Some(node) = queue.pop_front() {
+                component.push(self.nodes[node]);
+                for &neighbor in self.neighbors(node) {
+                    if !visited[neighbor] {
+                        visited[neighbor] = true;
+                        queue.push_back(neighbor);
+                    }
+                }
+            }
+            component.sort_unstable();
+            components.push(component);
+        }
+        components
+    }
+}
+
+// Order the given nodes (sorted), lowest rank first.
+fn dissect(graph: &UndirectedGraph, coords: &[(f32, f32)], nodes: Vec<NodeId>) -> Vec<NodeId> {
+    if nodes.len() <= MAX_LEAF_CELL_SIZE {
+        return nodes;
+    }
+
+    let cell = Cell::new(graph, nodes);
+    let mut removed = vec![false; cell.len()];
+    let mut components = cell.components(&removed);
+    let mut separator = Vec::new();
+
+    // only connected cells need a separator
+    if components.len() == 1 {
+        for node in min_separator(&cell, coords) {
+            removed[node] = true;
+            separator.push(cell.nodes[node]);
+        }
+        components = cell.components(&removed);
+    }
+    drop(cell);
+
+    let mut order: Vec<NodeId> = components
+        .into_par_iter()
+        .map(|component| dissect(graph, coords, component))
+        .collect::<Vec<_>>()
+        .concat();
+    order.extend(separator);
+    order
+}
+
+// The smallest of the inertial flow cuts for all directions, as local node ids.
+fn min_separator(cell: &Cell, coords: &[(f32, f32)]) -> Vec<usize> {
+    let num_terminals = std::cmp::max(1, (cell.len() as f64 * TERMINAL_FRACTION) as usize);
+
+    DIRECTIONS
+        .iter()
+        .map(|&(lat_factor, lng_factor)| {
+            let projection = |node: usize| {
+                let (lat, lng) = coords[cell.nodes[node] as usize];
+                lat_factor * lat + lng_factor * lng
+            };
+            let mut sorted: Vec<usize> = (0..cell.len()).collect();
+            sorted.sort_by(|&a, &b| projection(a).total_cmp(&projection(b)));
+            min_vertex_cut(cell, &sorted[..num_terminals], &sorted[cell.len() - num_terminals..])
+        })
+        .min_by_key(Vec::len)
+        .unwrap()
+}
+
+// Min node cut between `sources` and `sinks` with Edmonds-Karp on the split graph.
+// Each node `v` becomes `2v -> 2v + 1` with capacity one, and each edge `{u, v}` becomes
+// `2u + 1 -> 2v` and `2v + 1 -> 2u` with infinite capacity.
+// Terminals may also be cut, so the flow is bounded by the number of terminals.
+fn min_vertex_cut(cell: &Cell, sources: &[usize], sinks: &[usize]) -> Vec<usize> {
+    const INF: u32 = u32::MAX;
+    let n = 2 * cell.len() + 2;
+    let (source, sink) = (n - 2, n - 1);
+
+    // arcs are stored in pairs, `arc ^ 1` is the reverse arc
+    let mut arcs: Vec<(usize, usize, u32)> = Vec::with_capacity(2 * (cell.len() + cell.head.len() + sources.len() + sinks.len()));
+    let mut add_arc = |from: usize, to: usize, capacity: u32| {
+        arcs.push((from, to, capacity));
+        arcs.push((to, from, 0));
+    };
+    for node in 0..cell.len() {
+        add_arc(2 * node, 2 * node + 1, 1);
+        for &neighbor in cell.neighbors(node) {
+            add_arc(2 * node + 1, 2 * neighbor, INF);
+        }
+    }
+    for &node in sources {
+        add_arc(source, 2 * node, INF);
+    }
+    for &node in sinks {
+        add_arc(2 * node + 1, sink, INF);
+    }
+
+    let mut first_out = vec![0; n + 1];
+    for &(from, _, _) in &arcs {
+        first_out[from + 1] += 1;
+    }
+    for node in 0..n {
+        first_out[node + 1] += first_out[node];
+    }
+    let mut next = first_out.clone();
+    let mut out_arcs = vec![0; arcs.len()];
+    for (arc, &(from, _, _)) in arcs.iter().enumerate() {
+        out_arcs[next[from]] = arc;
+        next[from] += 1;
+    }
+    let mut residual: Vec<u32> = arcs.iter().map(|&(_, _, capacity)| capacity).collect();
+
+    let mut parent_arc = vec![usize::MAX; n];
+    let mut visited = vec![false; n];
+    let mut queue = VecDeque::new();
+    loop {
+        visited.iter_mut().for_each(|visited| *visited = false);
+        visited[source] = true;
+        queue.clear();
+        queue.push_back(source);
+        while let Some(node) = queue.pop_front() {
+            if node == sink {
+                break;
+            }
+            for &arc in &out_arcs[first_out[node]..first_out[node + 1]] {
+                let head = arcs[arc].1;
+                if residual[arc] > 0 && !visited[head] {
+                    visited[head] = true;
+                    parent_arc[head] = arc;
+                    queue.push_back(head);
+                }
+            }
+        }
+        if !visited[sink] {
+            break;
+        }
+
+        // every path contains a node arc, so the bottleneck is always one
+        let mut node = sink;
+        while node != source {
+            let arc = parent_arc[node];
+            if residual[arc] != INF {
+                residual[arc] -= 1;
+            }
+            if residual[arc ^ 1] != INF {
+                residual[arc ^ 1] += 1;
+            }
+            node = arcs[arc].0;
+        }
+    }
+
+    // `visited` now marks the source side of the min cut
+    (0..cell.len()).filter(|&node| visited[2 * node] && !visited[2 * node + 1]).collect()
+}
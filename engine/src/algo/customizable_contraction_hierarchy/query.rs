@@ -1,6 +1,7 @@
 //! CCH query based on elimination tree
 
 use super::*;
+pub mod isochrone;
 pub mod nearest_neighbor;
 pub mod stepped_elimination_tree;
 use stepped_elimination_tree::EliminationTreeWalk;
@@ -128,6 +129,106 @@ impl<C: Customized> Server<C> {
         }
     }
 
+    // Like `distance`, but the searches start at several nodes with offsets.
+    // Each search relaxes the union of the elimination tree paths of its origins in ascending rank order.
+    fn multi_distance(&mut self, query: &MultiQuery) -> Option<(Weight, Query)> {
+        let order = self.customized.cch().node_order();
+        let elimination_tree = self.customized.cch().elimination_tree();
+        let fw_graph = self.customized.forward_graph();
+        let bw_graph = self.customized.backward_graph();
+
+        let fw_nodes = Self::init_multi_search(query.sources(), order, elimination_tree, &mut self.fw_distances, &mut self.fw_parents);
+        let bw_nodes = Self::init_multi_search(query.targets(), order, elimination_tree, &mut self.bw_distances, &mut self.bw_parents);
+        self.walked_nodes = fw_nodes.len() + bw_nodes.len();
+        self.relaxed_edges = Self::relax_upward(&fw_graph, &fw_nodes, &mut self.fw_distances, &mut self.fw_parents)
+            + Self::relax_upward(&bw_graph, &bw_nodes, &mut self.bw_distances, &mut self.bw_parents);
+
+        // distances of nodes outside of the search spaces are infinity
+        let mut tentative_distance = INFINITY;
+        for &node in &fw_nodes {
+            let dist = self.fw_distances[node as usize] + self.bw_distances[node as usize];
+            if dist < tentative_distance {
+                tentative_distance = dist;
+                self.meeting_node = node;
+            }
+        }
+
+        for &node in &fw_nodes {
+            self.fw_distances[node as usize] = INFINITY;
+        }
+        for &node in &bw_nodes {
+            self.bw_distances[node as usize] = INFINITY;
+        }
+
+        if tentative_distance >= INFINITY {
+            return None;
+        }
+
+        // origins are their own parents, unless a cheaper way to them from another origin was found
+        let origin = |parents: &[(NodeId, EdgeId)]| {
+            let mut node = self.meeting_node;
+            while parents[node as usize].0 != node {
+                node = parents[node as usize].0;
+            }
+            order.node(node)
+        };
+        let query = Query {
+            from: origin(&self.fw_parents),
+            to: origin(&self.bw_parents),
+        };
+        Some((tentative_distance, query))
+    }
+
+    // Set the offsets of the origins and collect the ranks of all their ancestors in the elimination tree in ascending order.
+    fn init_multi_search(
+        origins: &[(NodeId, Weight)],
+        order: &NodeOrder,
+        elimination_tree: &[InRangeOption<NodeId>],
+        distances: &mut [Weight],
+        parents: &mut [(NodeId, EdgeId)],
+    ) -> Vec<NodeId> {
+        let mut nodes = Vec::new();
+        for &(node, offset) in origins {
+            let rank = order.rank(node);
+            distances[rank as usize] = offset;
+            parents[rank as usize] = (rank, 0);
+
+            let mut cur_node = Some(rank);
+            while let Some(node) = cur_node {
+                nodes.push(node);
+                cur_node = elimination_tree[node as usize].value();
+            }
+        }
+        nodes.sort_unstable();
+        nodes.dedup();
+        nodes
+    }
+
+    // All upward arcs of a node lead to its ancestors, so relaxing in ascending rank order settles every node before it is relaxed.
+    fn relax_upward(
+        graph: &impl LinkIterable<(NodeIdT, Weight, EdgeIdT)>,
+        nodes: &[NodeId],
+        distances: &mut [Weight],
+        parents: &mut [(NodeId, EdgeId)],
+    ) -> usize {
+        let mut relaxed_edges = 0;
+        for &node in nodes {
+            let distance = distances[node as usize];
+            if distance >= INFINITY {
+                continue;
+            }
+            for (NodeIdT(head), weight, EdgeIdT(edge_idx)) in LinkIterable::<(NodeIdT, Weight, EdgeIdT)>::link_iter(graph, node) {
+                relaxed_edges += 1;
+                let next_dist = distance + weight;
+                if next_dist < distances[head as usize] {
+                    distances[head as usize] = next_dist;
+                    parents[head as usize] = (node, edge_idx);
+                }
+            }
+        }
+        relaxed_edges
+    }
+
     fn path(&mut self, query: Query) -> Vec<NodeId> {
         let from = self.customized.cch().node_order().rank(query.from);
         let to = self.customized.cch().node_order().rank(query.to);
@@ -206,3 +307,13 @@ impl<C: Customized> QueryServer for Server<C> {
         QueryResult::new(self.distance(query.from, query.to), PathServerWrapper(self, query))
     }
 }
+
+impl<C: Customized> MultiQueryServer for Server<C> {
+    type P<'s> = PathServerWrapper<'s, C> where Self: 's;
+
+    fn multi_query(&mut self, query: &MultiQuery) -> QueryResult<Self::P<'_>, Weight> {
+        let (distance, pair) = self.multi_distance(query).unzip();
+        // without a distance, the path will never be requested, so the pair does not matter
+        QueryResult::new(distance, PathServerWrapper(self, pair.unwrap_or(Query { from: 0, to: 0 })))
+    }
+}
@@ -0,0 +1,117 @@
+//! Isochrones on a customized CCH.
+//!
+//! An upward elimination tree walk from the sources followed by a PHAST style downward sweep over all nodes.
+//! Nodes beyond the budget are pruned from the sweep, so the work is linear in the graph size
+//! but cheap for nodes outside of the isochrone.
+
+use super::*;
+
+pub struct Server<Customized> {
+    customized: Customized,
+    distances: Vec<Weight>,
+}
+
+impl<C: Customized> Server<C> {
+    pub fn new(customized: C) -> Self {
+        let n = customized.forward_graph().num_nodes();
+        Server {
+            customized,
+            distances: vec![INFINITY; n],
+        }
+    }
+
+    // Update the metric using a new customization result
+    pub fn update(&mut self, mut customized: C) {
+        std::mem::swap(&mut self.customized, &mut customized);
+    }
+
+    pub fn customized(&self) -> &C {
+        &self.customized
+    }
+
+    /// All nodes with a distance of at most `budget` from `source` together with their distances.
+    /// Sorted by distance.
+    pub fn isochrone(&mut self, source: NodeId, budget: Weight) -> Vec<(NodeId, Weight)> {
+        self.isochrone_from(&[(source, 0)], budget)
+    }
+
+    /// All nodes with a distance of at most `budget` from any of the `sources` together with their distances.
+    /// Each source starts with the given offset, for example the part of an edge driven to reach it from a point on the edge.
+    /// Sorted by distance.
+    pub fn isochrone_from(&mut self, sources: &[(NodeId, Weight)], budget: Weight) -> Vec<(NodeId, Weight)> {
+        let order = self.customized.cch().node_order();
+        let elimination_tree = self.customized.cch().elimination_tree();
+        let fw_graph = self.customized.forward_graph();
+        let bw_graph = self.customized.backward_graph();
+
+        // The union of the elimination tree paths of all sources, relaxed in ascending rank order,
+        // so each node is final before its upward arcs are relaxed.
+        let mut upward = Vec::new();
+        for &(source, offset) in sources {
+            let rank = order.rank(source);
+            self.distances[rank as usize] = std::cmp::min(self.distances[rank as usize], offset);
+
+            let mut cur_node = Some(rank);
+            while let Some(node) = cur_node {
+                upward.push(node);
+                cur_node = elimination_tree[node as usize].value();
+            }
+        }
+        upward.sort_unstable();
+        upward.dedup();
+        for node in upward {
+            let dist = self.distances[node as usize];
+            if dist >= INFINITY {
+                continue;
+            }
+            for Link { node: upper, weight } in LinkIterable::<Link>::link_iter(&fw_graph, node) {
+                self.distances[upper as usize] = std::cmp::min(self.distances[upper as usize], dist + weight);
+            }
+        }
+
+        // All upward neighbors of a node have higher ranks, so going from high to low
+        // they are final when we reach a node.
+        // Distances beyond the budget are reset right away, so they can't be used in the sweep.
+        let mut reached = Vec::new();
+        for rank in (0..self.distances.len() as NodeId).rev() {
+            let mut dist = self.distances[rank as usize];
+            for Link { node: upper, weight } in LinkIterable::<Link>::link_iter(&bw_graph, rank) {
+                dist = std::cmp::min(dist, self.distances[upper as usize] + weight);
+            }
+            if dist <= budget {
+                self.distances[rank as usize] = dist;
+                reached.push((rank, dist));
+            } else {
+                self.distances[rank as usize] = INFINITY;
+            }
+        }
+
+        for &(rank, _) in &reached {
+            self.distances[rank as usize] = INFINITY;
+        }
+        for (node, _) in &mut reached {
+            *node = order.node(*node);
+        }
+        reached.sort_unstable_by_key(|&(node, dist)| (dist, node));
+        reached
+    }
+}
+
+/// Arcs leaving an isochrone, that is arcs `(u, v)` where `u` was reached within the budget but `v` can not be reached through this arc.
+/// `v` may still be reached through some other arc.
+/// Returns the arc ids together with the fraction of the arc which can still be traversed within the budget.
+/// `reached` is the result of an isochrone query with this budget on the same metric as `graph`.
+pub fn boundary_arcs<G>(graph: &G, reached: &[(NodeId, Weight)], budget: Weight) -> Vec<(EdgeIdT, f64)>
+where
+    G: LinkIterable<(NodeIdT, Weight, EdgeIdT)>,
+{
+    let mut boundary = Vec::new();
+    for &(node, dist) in reached {
+        for (_, weight, edge) in graph.link_iter(node) {
+            if dist + weight > budget {
+                boundary.push((edge, f64::from(budget - dist) / f64::from(weight)));
+            }
+        }
+    }
+    boundary
+}
@@ -74,6 +74,15 @@ impl DijkstraInit<Weight> {
             initial_state: 0,
         }
     }
+
+    /// One start node for each source of a multi query, starting with its offset.
+    /// The first one initializes the search, the others are added with `add_start_node`.
+    pub fn from_multi_query(q: &MultiQuery) -> impl Iterator<Item = Self> + '_ {
+        q.sources().iter().map(|&(node, offset)| Self {
+            source: NodeIdT(node),
+            initial_state: offset,
+        })
+    }
 }
 
 #[derive(Clone)]
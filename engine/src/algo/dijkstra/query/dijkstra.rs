@@ -69,6 +69,44 @@ where
         result
     }
 
+    // The potential is bound to a single target, so multi queries run without it.
+    fn multi_distance(&mut self, query: &MultiQuery) -> Option<(Weight, Query)> {
+        report!("algo", "Dijkstra Multi Query");
+        let mut ops = Ops::default();
+        let mut sources = DijkstraInit::from_multi_query(query);
+        let mut dijkstra = DijkstraRun::query(self.graph.borrow(), &mut self.dijkstra, &mut ops, sources.next()?);
+        for init in sources {
+            dijkstra.add_start_node(init);
+        }
+
+        let mut result = None;
+        let mut tentative_distance = INFINITY;
+        let mut num_queue_pops = 0;
+        while dijkstra.queue().peek().map(|state| state.key < tentative_distance).unwrap_or(false) {
+            let node = dijkstra.next().unwrap();
+            num_queue_pops += 1;
+            if let Some(offset) = query.target_offset(node) {
+                let dist = *dijkstra.tentative_distance(node) + offset;
+                if dist < tentative_distance {
+                    tentative_distance = dist;
+                    result = Some(node);
+                }
+            }
+        }
+
+        report!("num_queue_pops", num_queue_pops);
+        report!("num_queue_pushs", dijkstra.num_queue_pushs());
+        report!("num_relaxed_arcs", dijkstra.num_relaxed_arcs());
+
+        let to = result?;
+        // sources are their own predecessors, unless a cheaper way to them from another source was found
+        let mut from = to;
+        while self.dijkstra.predecessors[from as usize].0 != from {
+            from = self.dijkstra.predecessors[from as usize].0;
+        }
+        Some((tentative_distance, Query { from, to }))
+    }
+
     fn node_path(&self, query: impl GenQuery<Weight>) -> Vec<NodeId> {
         self.dijkstra.node_path(query.from(), query.to())
     }
@@ -188,3 +226,13 @@ impl<G: LinkIterable<O::Arc>, O: DijkstraOps<G, Label = Weight> + Default, P: Po
         QueryResult::new(self.distance(query), PathServerWrapper(self, query))
     }
 }
+
+impl<G: LinkIterable<O::Arc>, O: DijkstraOps<G, Label = Weight> + Default, P: Potential, B: Borrow<G>> MultiQueryServer for Server<G, O, P, B> {
+    type P<'s> = PathServerWrapper<'s, Query, G, O, P, B> where Self: 's;
+
+    fn multi_query(&mut self, query: &MultiQuery) -> QueryResult<Self::P<'_>, Weight> {
+        let (distance, pair) = self.multi_distance(query).unzip();
+        // without a distance, the path will never be requested, so the pair does not matter
+        QueryResult::new(distance, PathServerWrapper(self, pair.unwrap_or(Query { from: 0, to: 0 })))
+    }
+}
@@ -1,5 +1,7 @@
 use super::*;
 
+pub mod cch;
+
 pub struct HubLabels {
     outgoing: Vec<Vec<(NodeId, Weight)>>,
     incoming: Vec<Vec<(NodeId, Weight)>>,
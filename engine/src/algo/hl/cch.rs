@@ -0,0 +1,385 @@
+//! Hub labels built from a customized CCH.
+//!
+//! The labels are computed top-down along the elimination tree.
+//! The forward label of a node contains the node itself and the labels of its upward neighbors, extended by the arc weight.
+//! All upward neighbors are ancestors in the elimination tree, so all nodes of the same depth can be processed in parallel.
+//! Entries whose distance is not the shortest distance to the hub (which the labels computed so far can tell) are pruned.
+//! With a perfect customization, this prunes much more, so `customize_perfect` should be used before.
+//!
+//! Each entry also stores the first CCH arc of the path to its hub.
+//! Paths are retrieved by following these arcs to the hub and unpacking the shortcuts with the customization.
+//! So the same customization (or a reconstruction of it) is needed for queries.
+
+use crate::{
+    algo::{customizable_contraction_hierarchy::*, *},
+    io::*,
+};
+use rayon::prelude::*;
+
+// first arc of the label entry of a node for itself
+const NO_ARC: EdgeId = EdgeId::MAX;
+
+/// Labels of all nodes for one direction, flattened into one array per entry component.
+/// Node ids are CCH ranks and the entries of each node are sorted by hub.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelSet {
+    first_entry: Vec<EdgeId>,
+    hub: Vec<NodeId>,
+    distance: Vec<Weight>,
+    first_arc: Vec<EdgeId>,
+}
+
+impl LabelSet {
+    fn new(labels: Vec<Vec<(NodeId, Weight, EdgeId)>>) -> Self {
+        let mut first_entry = Vec::with_capacity(labels.len() + 1);
+        first_entry.push(0);
+        let num_entries = labels.iter().map(Vec::len).sum();
+        let mut hub = Vec::with_capacity(num_entries);
+        let mut distance = Vec::with_capacity(num_entries);
+        let mut first_arc = Vec::with_capacity(num_entries);
+
+        for label in labels {
+            for (h, d, arc) in label {
+                hub.push(h);
+                distance.push(d);
+                first_arc.push(arc);
+            }
+            first_entry.push(hub.len() as EdgeId);
+        }
+
+        Self {
+            first_entry,
+            hub,
+            distance,
+            first_arc,
+        }
+    }
+
+    fn range(&self, node: NodeId) -> std::ops::Range<usize> {
+        self.first_entry[node as usize] as usize..self.first_entry[node as usize + 1] as usize
+    }
+
+    fn hubs(&self, node: NodeId) -> &[NodeId] {
+        &self.hub[self.range(node)]
+    }
+
+    fn distances(&self, node: NodeId) -> &[Weight] {
+        &self.distance[self.range(node)]
+    }
+
+    // index of the entry for `hub` in the label of `node`
+    fn entry(&self, node: NodeId, hub: NodeId) -> usize {
+        let range = self.range(node);
+        range.start + self.hub[range].binary_search(&hub).expect("hub missing in label")
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.first_entry.len() - 1
+    }
+
+    pub fn num_entries(&self) -> usize {
+        self.hub.len()
+    }
+}
+
+impl Deconstruct for LabelSet {
+    fn save_each(&self, store: &dyn Fn(&str, &dyn Save) -> std::io::Result<()>) -> std::io::Result<()> {
+        store("first_entry", &self.first_entry)?;
+        store("hub", &self.hub)?;
+        store("distance", &self.distance)?;
+        store("first_arc", &self.first_arc)?;
+        Ok(())
+    }
+}
+
+impl Reconstruct for LabelSet {
+    fn reconstruct_with(loader: Loader) -> std::io::Result<Self> {
+        let labels = Self {
+            first_entry: loader.load("first_entry")?,
+            hub: loader.load("hub")?,
+            distance: loader.load("distance")?,
+            first_arc: loader.load("first_arc")?,
+        };
+        let num_entries = labels.hub.len();
+        if labels.first_entry.is_empty()
+            || *labels.first_entry.last().unwrap() as usize != num_entries
+            || labels.distance.len() != num_entries
+            || labels.first_arc.len() != num_entries
+        {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "inconsistent hub label files"));
+        }
+        Ok(labels)
+    }
+}
+
+/// Forward and backward labels of all nodes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HubLabels {
+    forward: LabelSet,
+    backward: LabelSet,
+}
+
+impl HubLabels {
+    /// Compute the labels for the metric of `customized`.
+    pub fn new<C: Customized>(customized: &C) -> Self {
+        let forward_graph = customized.forward_graph();
+        let backward_graph = customized.backward_graph();
+        let n = forward_graph.num_nodes();
+
+        // group nodes by their depth in the elimination tree, parents always have a higher rank
+        let elimination_tree = customized.cch().elimination_tree();
+        let mut depth = vec![0; n];
+        let mut levels: Vec<Vec<NodeId>> = Vec::new();
+        for node in (0..n).rev() {
+            depth[node] = elimination_tree[node].value().map(|parent| depth[parent as usize] + 1).unwrap_or(0);
+            if levels.len() <= depth[node] {
+                levels.push(Vec::new());
+            }
+            levels[depth[node]].push(node as NodeId);
+        }
+
+        let mut forward = vec![Vec::new(); n];
+        let mut backward = vec![Vec::new(); n];
+
+        for level in levels {
+            let compute = |&node: &NodeId| {
+                let mut out = Self::merge_neighbor_labels(node, &forward_graph, &forward);
+                let mut inc = Self::merge_neighbor_labels(node, &backward_graph, &backward);
+                out = out
+                    .iter()
+                    .copied()
+                    .filter(|&(hub, dist, _)| Self::best_hub(&out, &backward[hub as usize]).is_none_or(|(_, best)| best >= dist))
+                    .collect();
+                inc = inc
+                    .iter()
+                    .copied()
+                    .filter(|&(hub, dist, _)| Self::best_hub(&forward[hub as usize], &inc).is_none_or(|(_, best)| best >= dist))
+                    .collect();
+                (out, inc)
+            };
+            let computed: Vec<_> = if cfg!(feature = "cch-disable-par") {
+                level.iter().map(compute).collect()
+            } else {
+                level.par_iter().map(compute).collect()
+            };
+            for (node, (out, inc)) in level.into_iter().zip(computed) {
+                forward[node as usize] = out;
+                backward[node as usize] = inc;
+            }
+        }
+
+        Self {
+            forward: LabelSet::new(forward),
+            backward: LabelSet::new(backward),
+        }
+    }
+
+    // The node itself and the labels of all upward neighbors extended by the arc weight, keeping the shortest entry for each hub.
+    fn merge_neighbor_labels(node: NodeId, graph: &BorrowedGraph, labels: &[Vec<(NodeId, Weight, EdgeId)>]) -> Vec<(NodeId, Weight, EdgeId)> {
+        let mut label = vec![(node, 0, NO_ARC)];
+        for (NodeIdT(head), EdgeIdT(edge)) in LinkIterable::<(NodeIdT, EdgeIdT)>::link_iter(graph, node) {
+            let weight = graph.weight()[edge as usize];
+            if weight >= INFINITY {
+                continue;
+            }
+            label.extend(
+                labels[head as usize]
+                    .iter()
+                    .filter(|&&(_, dist, _)| dist + weight < INFINITY)
+                    .map(|&(hub, dist, _)| (hub, dist + weight, edge)),
+            );
+        }
+        label.sort_unstable();
+        label.dedup_by_key(|&mut (hub, _, _)| hub);
+        label
+    }
+
+    fn best_hub(out: &[(NodeId, Weight, EdgeId)], inc: &[(NodeId, Weight, EdgeId)]) -> Option<(NodeId, Weight)> {
+        let mut out_iter = out.iter().peekable();
+        let mut inc_iter = inc.iter().peekable();
+        let mut result: Option<(NodeId, Weight)> = None;
+
+        while let (Some(&&(out_hub, out_dist, _)), Some(&&(inc_hub, inc_dist, _))) = (out_iter.peek(), inc_iter.peek()) {
+            match out_hub.cmp(&inc_hub) {
+                std::cmp::Ordering::Less => {
+                    out_iter.next();
+                }
+                std::cmp::Ordering::Greater => {
+                    inc_iter.next();
+                }
+                std::cmp::Ordering::Equal => {
+                    if result.is_none_or(|(_, best)| out_dist + inc_dist < best) {
+                        result = Some((out_hub, out_dist + inc_dist));
+                    }
+                    out_iter.next();
+                    inc_iter.next();
+                }
+            }
+        }
+
+        result
+    }
+
+    pub fn forward(&self) -> &LabelSet {
+        &self.forward
+    }
+
+    pub fn backward(&self) -> &LabelSet {
+        &self.backward
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.forward.num_nodes()
+    }
+
+    /// Total number of entries in forward and backward labels.
+    pub fn num_entries(&self) -> usize {
+        self.forward.num_entries() + self.backward.num_entries()
+    }
+}
+
+impl Deconstruct for HubLabels {
+    fn save_each(&self, store: &dyn Fn(&str, &dyn Save) -> std::io::Result<()>) -> std::io::Result<()> {
+        store("forward_labels", &Sub(&self.forward))?;
+        store("backward_labels", &Sub(&self.backward))?;
+        Ok(())
+    }
+}
+
+impl Reconstruct for HubLabels {
+    fn reconstruct_with(loader: Loader) -> std::io::Result<Self> {
+        let labels = Self {
+            forward: loader.reconstruct("forward_labels")?,
+            backward: loader.reconstruct("backward_labels")?,
+        };
+        if labels.forward.num_nodes() != labels.backward.num_nodes() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "inconsistent hub label files"));
+        }
+        Ok(labels)
+    }
+}
+
+/// Hub label query server.
+/// Distances only need the labels, `customized` is used to unpack paths.
+pub struct Server<C> {
+    labels: HubLabels,
+    customized: C,
+    // rank of source and target and the hub of the last query
+    meeting: (NodeId, NodeId, NodeId),
+}
+
+impl<C: Customized> Server<C> {
+    /// `labels` have to be computed from `customized` (or the same metric on the same CCH).
+    pub fn new(labels: HubLabels, customized: C) -> Self {
+        assert_eq!(labels.num_nodes(), customized.forward_graph().num_nodes());
+        Self {
+            labels,
+            customized,
+            meeting: (0, 0, 0),
+        }
+    }
+
+    pub fn labels(&self) -> &HubLabels {
+        &self.labels
+    }
+
+    fn distance(&mut self, from: NodeId, to: NodeId) -> Option<Weight> {
+        let order = self.customized.cch().node_order();
+        let (from, to) = (order.rank(from), order.rank(to));
+        let (out_hubs, out_dists) = (self.labels.forward.hubs(from), self.labels.forward.distances(from));
+        let (inc_hubs, inc_dists) = (self.labels.backward.hubs(to), self.labels.backward.distances(to));
+
+        let mut best = INFINITY;
+        let (mut i, mut j) = (0, 0);
+        while i < out_hubs.len() && j < inc_hubs.len() {
+            match out_hubs[i].cmp(&inc_hubs[j]) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => {
+                    if out_dists[i] + inc_dists[j] < best {
+                        best = out_dists[i] + inc_dists[j];
+                        self.meeting = (from, to, out_hubs[i]);
+                    }
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+
+        match best {
+            INFINITY => None,
+            dist => Some(dist),
+        }
+    }
+
+    fn path(&self) -> Vec<NodeId> {
+        let (from, to, hub) = self.meeting;
+        let forward_graph = self.customized.forward_graph();
+        let backward_graph = self.customized.backward_graph();
+
+        let mut path = vec![from];
+        let mut node = from;
+        while node != hub {
+            let arc = self.labels.forward.first_arc[self.labels.forward.entry(node, hub)];
+            self.unpack(arc, true, &mut path);
+            node = forward_graph.head()[arc as usize];
+        }
+
+        // the backward entries lead from the target up to the hub, so collect them first and then walk down
+        let mut down_arcs = Vec::new();
+        let mut node = to;
+        while node != hub {
+            let arc = self.labels.backward.first_arc[self.labels.backward.entry(node, hub)];
+            down_arcs.push(arc);
+            node = backward_graph.head()[arc as usize];
+        }
+        for &arc in down_arcs.iter().rev() {
+            self.unpack(arc, false, &mut path);
+        }
+
+        for node in &mut path {
+            *node = self.customized.cch().node_order().node(*node);
+        }
+        path
+    }
+
+    // Append the nodes of the unpacked `arc` except for the first one to `path`.
+    // Upward arcs are forward CCH edges, downward arcs backward CCH edges which are traversed from head to tail.
+    fn unpack(&self, arc: EdgeId, upward: bool, path: &mut Vec<NodeId>) {
+        let unpacked = if upward {
+            self.customized.unpack_outgoing(EdgeIdT(arc))
+        } else {
+            self.customized.unpack_incoming(EdgeIdT(arc))
+        };
+        match unpacked {
+            Some((EdgeIdT(down), EdgeIdT(up), _)) => {
+                self.unpack(down, false, path);
+                self.unpack(up, true, path);
+            }
+            None if upward => path.push(self.customized.forward_graph().head()[arc as usize]),
+            None => path.push(self.customized.backward_tail()[arc as usize]),
+        }
+    }
+}
+
+pub struct PathServerWrapper<'s, C>(&'s Server<C>);
+
+impl<'s, C: Customized> PathServer for PathServerWrapper<'s, C> {
+    type NodeInfo = NodeId;
+    type EdgeInfo = ();
+
+    fn reconstruct_node_path(&mut self) -> Vec<Self::NodeInfo> {
+        self.0.path()
+    }
+    fn reconstruct_edge_path(&mut self) -> Vec<Self::EdgeInfo> {
+        vec![(); self.reconstruct_node_path().len() - 1]
+    }
+}
+
+impl<C: Customized> QueryServer for Server<C> {
+    type P<'s> = PathServerWrapper<'s, C> where Self: 's;
+
+    fn query(&mut self, query: Query) -> QueryResult<Self::P<'_>, Weight> {
+        QueryResult::new(self.distance(query.from, query.to), PathServerWrapper(self))
+    }
+}
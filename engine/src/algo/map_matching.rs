@@ -0,0 +1,232 @@
+//! Map matching of GPS traces with a hidden Markov model.
+//!
+//! Follows Newson and Krumm, "Hidden Markov Map Matching Through Noise and Sparseness".
+//! Candidates for each GPS point are the arcs within a search radius, found with the `SpatialIndex`.
+//! Emission probabilities depend on the distance between point and arc,
+//! transition probabilities on the difference between route distance and great circle distance of consecutive points.
+//! Route distances come from one-to-many CCH queries, the most likely candidate sequence is decoded with Viterbi.
+//!
+//! The result is the `TraceData` stream consumed by `link_speed_estimates::estimate_iter`, together with the matched route.
+
+use super::*;
+use crate::{
+    algo::{customizable_contraction_hierarchy::*, rphast::DistanceMatrixServer},
+    datastr::{
+        graph::first_out_graph::BorrowedGraph,
+        spatial::{distance, EdgeSnap, SpatialIndex},
+    },
+    link_speed_estimates::TraceData,
+};
+
+/// A raw GPS measurement.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GpsPoint {
+    pub timestamp: u64, // [ms]
+    pub lat: f32,
+    pub lng: f32,
+}
+
+/// Model parameters, all distances in meters.
+#[derive(Debug, Clone, Copy)]
+pub struct MapMatchingParams {
+    /// Standard deviation of the GPS noise.
+    pub sigma: f64,
+    /// Scale of the exponential distribution of the difference between route and great circle distance.
+    pub beta: f64,
+    /// Only arcs at most this far away from a point are candidates.
+    pub search_radius: f64,
+    /// Maximum number of candidates per point, the closest ones are kept.
+    pub max_candidates: usize,
+}
+
+impl Default for MapMatchingParams {
+    fn default() -> Self {
+        Self {
+            sigma: 4.07,
+            beta: 10.0,
+            search_radius: 50.0,
+            max_candidates: 8,
+        }
+    }
+}
+
+/// One continuous piece of a matched trace.
+#[derive(Debug)]
+pub struct MatchedTrace {
+    /// All arcs of the matched route, in travel order.
+    pub path: Vec<EdgeId>,
+    /// One entry for each matched GPS point.
+    /// `link_id` is the arc id, which has to be mapped to HERE link ids (`LinkIdMapper::local_to_here_link_id`) for HERE data.
+    pub traces: Vec<TraceData>,
+}
+
+// Viterbi state of one point: the point index, its candidates, their scores and the index of their predecessor
+type ViterbiStep = (usize, Vec<EdgeSnap>, Vec<f64>, Vec<usize>);
+
+/// Map matcher for a graph with a CCH customized with the arc lengths in meters.
+pub struct MapMatcher<'a, C> {
+    graph: BorrowedGraph<'a>,
+    tail: Vec<NodeId>,
+    index: &'a SpatialIndex,
+    distances: DistanceMatrixServer<BorrowedGraph<'a>, BorrowedGraph<'a>>,
+    paths: query::Server<&'a C>,
+    params: MapMatchingParams,
+}
+
+impl<'a, C: Customized> MapMatcher<'a, C> {
+    /// `graph` has to have the arc lengths in meters as weights, and `customized` has to be customized with the same lengths.
+    /// `index` has to be built for the arcs of `graph`.
+    pub fn new(graph: BorrowedGraph<'a>, index: &'a SpatialIndex, customized: &'a C, params: MapMatchingParams) -> Self {
+        let tail: Vec<NodeId> = (0..graph.num_nodes() as NodeId)
+            .flat_map(|node| graph.neighbor_edge_indices(node).map(move |_| node))
+            .collect();
+
+        Self {
+            distances: DistanceMatrixServer::new(customized.forward_graph(), customized.backward_graph(), customized.cch().node_order().clone()),
+            paths: query::Server::new(customized),
+            graph,
+            tail,
+            index,
+            params,
+        }
+    }
+
+    /// Match a trace, ordered by timestamp.
+    /// Points without any arc in the search radius are skipped.
+    /// Where consecutive points can't be connected by any route, the trace is split into multiple pieces.
+    pub fn match_trace(&mut self, points: &[GpsPoint]) -> Vec<MatchedTrace> {
+        let mut matched = Vec::new();
+        // Viterbi states of the current piece
+        let mut steps: Vec<ViterbiStep> = Vec::new();
+
+        for (point_idx, point) in points.iter().enumerate() {
+            let candidates = self.candidates(point);
+            if candidates.is_empty() {
+                continue;
+            }
+            let emission: Vec<f64> = candidates.iter().map(|c| -0.5 * (f64::from(c.distance) / self.params.sigma).powi(2)).collect();
+
+            if let Some((prev_idx, prev_candidates, prev_scores, _)) = steps.last() {
+                let transitions = self.transitions(&points[*prev_idx], prev_candidates, point, &candidates);
+                let mut scores = vec![f64::NEG_INFINITY; candidates.len()];
+                let mut parents = vec![0; candidates.len()];
+                for (i, &prev_score) in prev_scores.iter().enumerate() {
+                    for (j, &transition) in transitions[i].iter().enumerate() {
+                        let score = prev_score + transition + emission[j];
+                        if score > scores[j] {
+                            scores[j] = score;
+                            parents[j] = i;
+                        }
+                    }
+                }
+
+                if scores.iter().any(|score| score.is_finite()) {
+                    steps.push((point_idx, candidates, scores, parents));
+                    continue;
+                }
+                // no route between the last and this point, start a new piece
+                matched.push(self.backtrack(points, &steps));
+                steps.clear();
+            }
+
+            let parents = vec![0; candidates.len()];
+            steps.push((point_idx, candidates, emission, parents));
+        }
+
+        if !steps.is_empty() {
+            matched.push(self.backtrack(points, &steps));
+        }
+        matched
+    }
+
+    fn candidates(&self, point: &GpsPoint) -> Vec<EdgeSnap> {
+        let mut candidates = self.index.within(point.lat, point.lng, self.params.search_radius as f32);
+        candidates.truncate(self.params.max_candidates);
+        candidates
+    }
+
+    // Log transition probabilities from each of `from_candidates` to each of `to_candidates`.
+    fn transitions(&mut self, from: &GpsPoint, from_candidates: &[EdgeSnap], to: &GpsPoint, to_candidates: &[EdgeSnap]) -> Vec<Vec<f64>> {
+        let great_circle = f64::from(distance((from.lat, from.lng), (to.lat, to.lng)));
+        let sources: Vec<NodeId> = from_candidates.iter().map(|c| self.graph.head()[c.arc as usize]).collect();
+        let targets: Vec<NodeId> = to_candidates.iter().map(|c| self.tail[c.arc as usize]).collect();
+        let matrix = self.distances.distances(&sources, &targets);
+
+        from_candidates
+            .iter()
+            .zip(&matrix)
+            .map(|(from, row)| {
+                to_candidates
+                    .iter()
+                    .zip(row)
+                    .map(|(to, &between)| {
+                        let (from_fraction, to_fraction) = (f64::from(from.fraction), f64::from(to.fraction));
+                        let route = if from.arc == to.arc {
+                            // moving backwards on the same arc is GPS noise, not a loop
+                            f64::max(to_fraction - from_fraction, 0.0) * self.length(from.arc)
+                        } else if between < INFINITY {
+                            (1.0 - from_fraction) * self.length(from.arc) + f64::from(between) + to_fraction * self.length(to.arc)
+                        } else {
+                            return f64::NEG_INFINITY;
+                        };
+                        -(route - great_circle).abs() / self.params.beta
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn backtrack(&mut self, points: &[GpsPoint], steps: &[ViterbiStep]) -> MatchedTrace {
+        let (_, _, scores, _) = steps.last().unwrap();
+        let mut idx = (0..scores.len()).max_by(|&a, &b| scores[a].total_cmp(&scores[b])).unwrap();
+        let mut sequence = Vec::with_capacity(steps.len());
+        for (point_idx, candidates, _, parents) in steps.iter().rev() {
+            sequence.push((*point_idx, candidates[idx]));
+            idx = parents[idx];
+        }
+        sequence.reverse();
+
+        let mut path = vec![sequence[0].1.arc];
+        let mut traces = Vec::with_capacity(sequence.len());
+        let mut fraction = 0.0;
+        for (point_idx, candidate) in sequence {
+            let last_arc = *path.last().unwrap();
+            if candidate.arc != last_arc {
+                self.connect(last_arc, candidate.arc, &mut path);
+                fraction = 0.0;
+            }
+            // keep the fractions on one arc monotone, see `transitions`
+            fraction = f32::max(fraction, candidate.fraction);
+            traces.push(TraceData {
+                timestamp: points[point_idx].timestamp,
+                link_id: u64::from(candidate.arc),
+                traversed_in_travel_direction_fraction: fraction,
+            });
+        }
+
+        MatchedTrace { path, traces }
+    }
+
+    // Append the arcs of the shortest route from the end of `from_arc` to the start of `to_arc`, including `to_arc`.
+    fn connect(&mut self, from_arc: EdgeId, to_arc: EdgeId, path: &mut Vec<EdgeId>) {
+        let from = self.graph.head()[from_arc as usize];
+        let to = self.tail[to_arc as usize];
+        if from != to {
+            let nodes = self.paths.query(Query { from, to }).node_path().expect("transition without route");
+            for pair in nodes.windows(2) {
+                let arc = self
+                    .graph
+                    .neighbor_edge_indices(pair[0])
+                    .filter(|&arc| self.graph.head()[arc as usize] == pair[1])
+                    .min_by_key(|&arc| self.graph.weight()[arc as usize])
+                    .unwrap();
+                path.push(arc);
+            }
+        }
+        path.push(to_arc);
+    }
+
+    fn length(&self, arc: EdgeId) -> f64 {
+        f64::from(self.graph.weight()[arc as usize])
+    }
+}
@@ -0,0 +1,276 @@
+//! Queries between points in the middle of edges.
+//!
+//! A mid-edge query is answered with a multi query between the end nodes of the source and the target edge.
+//! The source edge can be left at its head, or at its tail through the reverse edge, if there is one.
+//! Similarly, the target edge can be entered at its tail or through the reverse edge at its head.
+//! The offsets are the parts of the edge weights driven to reach these nodes.
+//! When source and target lie on the same road, the path can also stay on it.
+
+use super::*;
+
+/// A point on an edge, `fraction` is the part of the edge between its tail and the point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EdgePoint {
+    pub edge: EdgeId,
+    pub fraction: f32,
+}
+
+/// A query from a point on one edge to a point on another (or the same) edge.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MidEdgeQuery {
+    pub from: EdgePoint,
+    pub to: EdgePoint,
+}
+
+/// The driven part of an edge, as fractions from the tail of the edge.
+/// All edges of a path except the first and the last one are driven completely.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PartialEdge {
+    pub edge: EdgeId,
+    pub from_fraction: f32,
+    pub to_fraction: f32,
+}
+
+/// Weight of a part of an edge, rounded to the closest integer.
+pub fn partial_weight(weight: Weight, fraction: f32) -> Weight {
+    if weight >= INFINITY {
+        return INFINITY;
+    }
+    (f64::from(weight) * f64::from(fraction)).round() as Weight
+}
+
+/// The nodes at which a point on an edge can be left, the head of the edge and the tail through the reverse edge, if there is one.
+/// Each node comes with the part of the edge weight driven to reach it, ready to be used as offsets of a multi source search.
+pub fn exits<G: EdgeRandomAccessGraph<Link>>(graph: &G, point: EdgePoint) -> Vec<(NodeId, Weight)> {
+    both_directions(graph, point)
+        .into_iter()
+        .map(|point| (graph.link(point.edge).node, partial_weight(graph.link(point.edge).weight, 1.0 - point.fraction)))
+        .collect()
+}
+
+// A way to leave the source edge or to enter the target edge at `node`.
+#[derive(Debug, Clone, Copy)]
+struct Access {
+    node: NodeId,
+    offset: Weight,
+    part: PartialEdge,
+    // index of the source or target point
+    point: usize,
+}
+
+/// Servers which can answer multi queries can also answer mid-edge queries.
+/// The graph has to be the one the server was built and customized with.
+pub trait MidEdgeQueryServer: MultiQueryServer {
+    /// Calculate the shortest distance between two points on edges, including the driven parts of the source and target edge.
+    /// The node path contains the nodes between the source and the target edge, it is empty when the path does not leave the edge.
+    /// The edge path starts with the driven part of the source edge (or its reverse edge) and ends with the driven part of the target edge.
+    fn mid_edge_query<'s, G: EdgeRandomAccessGraph<Link>>(
+        &'s mut self,
+        graph: &'s G,
+        query: MidEdgeQuery,
+    ) -> QueryResult<PathServerWrapper<'s, Self::P<'s>, G>, Weight> {
+        self.mid_edge_multi_query(graph, &[query.from], &[query.to])
+    }
+
+    /// Like `mid_edge_query`, but from any of the `sources` to any of the `targets`, all in one multi query.
+    /// `PathServerWrapper::endpoints` tells which of the points the path connects.
+    fn mid_edge_multi_query<'s, G: EdgeRandomAccessGraph<Link>>(
+        &'s mut self,
+        graph: &'s G,
+        sources: &[EdgePoint],
+        targets: &[EdgePoint],
+    ) -> QueryResult<PathServerWrapper<'s, Self::P<'s>, G>, Weight>;
+}
+
+impl<S: MultiQueryServer> MidEdgeQueryServer for S {
+    fn mid_edge_multi_query<'s, G: EdgeRandomAccessGraph<Link>>(
+        &'s mut self,
+        graph: &'s G,
+        sources: &[EdgePoint],
+        targets: &[EdgePoint],
+    ) -> QueryResult<PathServerWrapper<'s, Self::P<'s>, G>, Weight> {
+        let from = all_directions(graph, sources);
+        let to = all_directions(graph, targets);
+
+        let sources: Vec<Access> = from
+            .iter()
+            .map(|&(idx, point)| Access {
+                node: graph.link(point.edge).node,
+                offset: partial_weight(graph.link(point.edge).weight, 1.0 - point.fraction),
+                part: PartialEdge {
+                    edge: point.edge,
+                    from_fraction: point.fraction,
+                    to_fraction: 1.0,
+                },
+                point: idx,
+            })
+            .collect();
+        let targets: Vec<Access> = to
+            .iter()
+            .map(|&(idx, point)| Access {
+                node: tail(graph, point.edge),
+                offset: partial_weight(graph.link(point.edge).weight, point.fraction),
+                part: PartialEdge {
+                    edge: point.edge,
+                    from_fraction: 0.0,
+                    to_fraction: point.fraction,
+                },
+                point: idx,
+            })
+            .collect();
+
+        let direct = from
+            .iter()
+            .flat_map(|source| to.iter().map(move |target| (source, target)))
+            .filter(|((_, source), (_, target))| source.edge == target.edge && source.fraction <= target.fraction)
+            .map(|(&(source_idx, source), &(target_idx, target))| {
+                let part = PartialEdge {
+                    edge: source.edge,
+                    from_fraction: source.fraction,
+                    to_fraction: target.fraction,
+                };
+                (partial_weight(graph.link(source.edge).weight, target.fraction - source.fraction), (part, source_idx, target_idx))
+            })
+            .filter(|&(dist, _)| dist < INFINITY)
+            .min_by_key(|&(dist, _)| dist);
+
+        let multi_query = MultiQuery::new(
+            sources.iter().map(|access| (access.node, access.offset)),
+            targets.iter().map(|access| (access.node, access.offset)),
+        );
+        let (distance, path_server) = self.multi_query(&multi_query).decompose();
+
+        let (distance, direct) = match (distance, direct) {
+            (Some(dist), Some((direct_dist, part))) if direct_dist <= dist => (Some(direct_dist), Some(part)),
+            (None, Some((direct_dist, part))) => (Some(direct_dist), Some(part)),
+            (distance, _) => (distance, None),
+        };
+
+        QueryResult::new(
+            distance,
+            PathServerWrapper {
+                path_server,
+                graph,
+                sources,
+                targets,
+                direct,
+            },
+        )
+    }
+}
+
+// The points on their own edges and on the reverse edges, together with the index of the point they belong to.
+fn all_directions<G: EdgeRandomAccessGraph<Link>>(graph: &G, points: &[EdgePoint]) -> Vec<(usize, EdgePoint)> {
+    points
+        .iter()
+        .enumerate()
+        .flat_map(|(idx, &point)| both_directions(graph, point).into_iter().map(move |point| (idx, point)))
+        .collect()
+}
+
+// The point on its own edge and on the reverse edge, if there is one.
+fn both_directions<G: EdgeRandomAccessGraph<Link>>(graph: &G, point: EdgePoint) -> Vec<EdgePoint> {
+    let point = EdgePoint {
+        fraction: point.fraction.clamp(0.0, 1.0),
+        ..point
+    };
+    let mut points = vec![point];
+    if let Some(reverse) = cheapest_edge(graph, graph.link(point.edge).node, tail(graph, point.edge)) {
+        points.push(EdgePoint {
+            edge: reverse,
+            fraction: 1.0 - point.fraction,
+        });
+    }
+    points
+}
+
+fn cheapest_edge<G: EdgeRandomAccessGraph<Link>>(graph: &G, from: NodeId, to: NodeId) -> Option<EdgeId> {
+    graph
+        .edge_indices(from, to)
+        .map(|EdgeIdT(edge)| edge)
+        .min_by_key(|&edge| graph.link(edge).weight)
+}
+
+// The last node whose edges start at or before `edge`.
+// Nodes without edges share their start with the next node, so this is the tail.
+fn tail(graph: &impl EdgeIdGraph, edge: EdgeId) -> NodeId {
+    let mut low = 0;
+    let mut high = graph.num_nodes() as NodeId;
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        if graph.neighbor_edge_indices(mid).start <= edge {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    low
+}
+
+pub struct PathServerWrapper<'s, P, G> {
+    path_server: P,
+    graph: &'s G,
+    sources: Vec<Access>,
+    targets: Vec<Access>,
+    // set when the path stays on the common edge of source and target, with the indices of their points
+    direct: Option<(PartialEdge, usize, usize)>,
+}
+
+impl<'s, P, G> PathServer for PathServerWrapper<'s, P, G>
+where
+    P: PathServer<NodeInfo = NodeId>,
+    G: EdgeRandomAccessGraph<Link>,
+{
+    type NodeInfo = NodeId;
+    type EdgeInfo = PartialEdge;
+
+    fn reconstruct_node_path(&mut self) -> Vec<Self::NodeInfo> {
+        if self.direct.is_some() {
+            return Vec::new();
+        }
+        self.path_server.reconstruct_node_path()
+    }
+
+    fn reconstruct_edge_path(&mut self) -> Vec<Self::EdgeInfo> {
+        if let Some((part, _, _)) = self.direct {
+            return vec![part];
+        }
+
+        let path = self.path_server.reconstruct_node_path();
+        let mut edges = vec![access(&self.sources, path[0]).part];
+        edges.extend(path.windows(2).map(|nodes| PartialEdge {
+            edge: cheapest_edge(self.graph, nodes[0], nodes[1]).unwrap(),
+            from_fraction: 0.0,
+            to_fraction: 1.0,
+        }));
+        edges.push(access(&self.targets, path[path.len() - 1]).part);
+        edges
+    }
+}
+
+// The access at `node`, the multi query kept the smallest offset for each node.
+fn access(accesses: &[Access], node: NodeId) -> &Access {
+    accesses
+        .iter()
+        .filter(|access| access.node == node)
+        .min_by_key(|access| access.offset)
+        .unwrap()
+}
+
+impl<'s, P: PathServer<NodeInfo = NodeId>, G> PathServerWrapper<'s, P, G> {
+    /// Indices of the source and the target point the path connects.
+    pub fn endpoints(&mut self) -> (usize, usize) {
+        if let Some((_, source, target)) = self.direct {
+            return (source, target);
+        }
+        let path = self.path_server.reconstruct_node_path();
+        (access(&self.sources, path[0]).point, access(&self.targets, path[path.len() - 1]).point)
+    }
+}
+
+impl<'s, P, G> PathServerWrapper<'s, P, G> {
+    /// Path server of the multi query between the end nodes of the source and target edge
+    pub fn inner(&mut self) -> &mut P {
+        &mut self.path_server
+    }
+}
@@ -12,9 +12,13 @@ pub mod contraction_hierarchy;
 pub mod customizable_contraction_hierarchy;
 pub mod dijkstra;
 pub mod hl;
+pub mod map_matching;
 pub mod metric_merging;
+pub mod mid_edge;
 pub mod minimal_nonshortest_subpaths;
+pub mod pareto;
 pub mod rphast;
+pub mod strongly_connected_components;
 pub mod td_astar;
 pub mod time_dependent_sampling;
 pub mod topocore;
@@ -87,6 +91,54 @@ impl<T: Copy> GenQuery<T> for TDQuery<T> {
     }
 }
 
+/// Several sources and targets, each with an offset.
+/// The distance of such a query is the minimum over all source-target pairs
+/// of the source offset, the shortest distance between them and the target offset.
+#[derive(Debug, Clone, Default)]
+pub struct MultiQuery {
+    sources: Vec<(NodeId, Weight)>,
+    targets: Vec<(NodeId, Weight)>,
+}
+
+impl MultiQuery {
+    /// Endpoints with an infinite offset are dropped, for duplicate nodes only the smallest offset is kept.
+    pub fn new(sources: impl IntoIterator<Item = (NodeId, Weight)>, targets: impl IntoIterator<Item = (NodeId, Weight)>) -> Self {
+        Self {
+            sources: Self::normalize(sources),
+            targets: Self::normalize(targets),
+        }
+    }
+
+    fn normalize(endpoints: impl IntoIterator<Item = (NodeId, Weight)>) -> Vec<(NodeId, Weight)> {
+        let mut endpoints: Vec<_> = endpoints.into_iter().filter(|&(_, offset)| offset < INFINITY).collect();
+        endpoints.sort_unstable();
+        endpoints.dedup_by_key(|&mut (node, _)| node);
+        endpoints
+    }
+
+    /// Sources with their offsets, sorted by node id
+    pub fn sources(&self) -> &[(NodeId, Weight)] {
+        &self.sources
+    }
+
+    /// Targets with their offsets, sorted by node id
+    pub fn targets(&self) -> &[(NodeId, Weight)] {
+        &self.targets
+    }
+
+    pub fn source_offset(&self, node: NodeId) -> Option<Weight> {
+        Self::offset(&self.sources, node)
+    }
+
+    pub fn target_offset(&self, node: NodeId) -> Option<Weight> {
+        Self::offset(&self.targets, node)
+    }
+
+    fn offset(endpoints: &[(NodeId, Weight)], node: NodeId) -> Option<Weight> {
+        endpoints.binary_search_by_key(&node, |&(node, _)| node).ok().map(|idx| endpoints[idx].1)
+    }
+}
+
 /// Generic container for query results.
 /// Contains a distance and allows fetching the actual path.
 /// Since queries usually modify the state of the internal algorithm data structures,
@@ -231,6 +283,18 @@ pub trait TDQueryServer<T: Copy, W> {
     fn td_query(&mut self, query: TDQuery<T>) -> QueryResult<Self::P<'_>, W>;
 }
 
+/// Trait for query algorithm servers which support several weighted sources and targets.
+pub trait MultiQueryServer {
+    /// Just for internal use. Type of the object that can retrieve the actual shortest path.
+    type P<'s>: PathServer<NodeInfo = NodeId>
+    where
+        Self: 's;
+    /// Calculate the shortest distance from any source to any target including the offsets.
+    /// The node path starts at the source and ends at the target of the best pair.
+    /// Will return None if no source is connected to any target.
+    fn multi_query(&mut self, query: &MultiQuery) -> QueryResult<Self::P<'_>, Weight>;
+}
+
 /// Just for internal use.
 /// Trait for path retrievers.
 pub trait PathServer {
@@ -0,0 +1,281 @@
+//! Multi-criteria route queries computing the Pareto set of paths between two nodes.
+//!
+//! A label correcting search on `MultiCritDijkstraRun`, where each node keeps all its non-dominated labels.
+//! Each criterion has its own metric and its own `CCHPotData` with exact lower bounds for that metric.
+//! The queue is ordered lexicographically by the costs plus the lower bounds, so labels are settled in the same order at each node
+//! and a settled label is never dominated by a label found later.
+//! Labels whose lower bounds on all criteria are dominated by a path already found at the target get pruned.
+//!
+//! The size of the Pareto set can grow very large, so queries take an epsilon for approximate pruning:
+//! a label is also dropped if a path at the target is at most a factor of `1 + epsilon` worse in every criterion.
+//! Every Pareto optimal path is then within that factor of some returned path in all criteria.
+//! With an epsilon of zero, the exact Pareto set is computed.
+
+use super::*;
+use crate::{
+    algo::{
+        a_star::Potential,
+        ch_potentials::{BorrowedCCHPot, CCHPotData},
+        dijkstra::{gen_topo_dijkstra::Neutral, generic_dijkstra::*, *},
+    },
+    datastr::timestamped_vector::*,
+    report::*,
+};
+
+/// Queue key: the costs plus the lower bounds to the target, compared lexicographically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ParetoKey<const N: usize>(pub [Weight; N]);
+
+impl<const N: usize> std::ops::Sub<Neutral> for ParetoKey<N> {
+    type Output = Self;
+
+    fn sub(self, _rhs: Neutral) -> Self::Output {
+        self
+    }
+}
+
+/// A label with the costs of a path from the source for each criterion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParetoLabel<const N: usize> {
+    key: ParetoKey<N>,
+    costs: [Weight; N],
+    // index into the labels of `ParetoOps`, for path reconstruction
+    id: usize,
+}
+
+impl<const N: usize> Reset for ParetoLabel<N> {
+    const DEFAULT: Self = Self {
+        key: ParetoKey([INFINITY; N]),
+        costs: [INFINITY; N],
+        id: usize::MAX,
+    };
+}
+
+impl<const N: usize> Label for ParetoLabel<N> {
+    type Key = ParetoKey<N>;
+
+    fn neutral() -> Self {
+        Self::DEFAULT
+    }
+
+    fn key(&self) -> Self::Key {
+        self.key
+    }
+}
+
+impl<const N: usize> PartialOrd for NodeQueueLabelOrder<ParetoLabel<N>> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<const N: usize> Ord for NodeQueueLabelOrder<ParetoLabel<N>> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        // switched for reversing
+        other.0.key.cmp(&self.0.key)
+    }
+}
+
+impl<const N: usize> PartialEq for NodeQueueLabelOrder<ParetoLabel<N>> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.key == other.0.key
+    }
+}
+
+impl<const N: usize> Eq for NodeQueueLabelOrder<ParetoLabel<N>> {}
+
+fn dominates<const N: usize>(lhs: &[Weight; N], rhs: &[Weight; N]) -> bool {
+    lhs.iter().zip(rhs).all(|(l, r)| l <= r)
+}
+
+pub struct ParetoOps<'a, const N: usize> {
+    metrics: [&'a [Weight]; N],
+    potentials: [BorrowedCCHPot<'a>; N],
+    target: NodeId,
+    epsilon: f64,
+    // head, arc and parent label of each label created in the current query, the first entry is the source
+    labels: Vec<(NodeId, EdgeId, usize)>,
+}
+
+impl<'a, const N: usize> ParetoOps<'a, N> {
+    // Whether `target` is at most a factor of `1 + epsilon` worse than `lower_bound` in all criteria.
+    fn epsilon_dominates(&self, target: &[Weight; N], lower_bound: &[Weight; N]) -> bool {
+        target
+            .iter()
+            .zip(lower_bound)
+            .all(|(&t, &lb)| f64::from(t) <= (1.0 + self.epsilon) * f64::from(lb))
+    }
+}
+
+impl<'a, G, const N: usize> MultiCritDijkstraOps<G> for ParetoOps<'a, N> {
+    type Label = ParetoLabel<N>;
+    type Arc = (NodeIdT, EdgeIdT);
+    type LinkResult = Option<(ParetoLabel<N>, NodeId, EdgeId)>;
+    type PredecessorLink = ();
+
+    fn link(
+        &mut self,
+        _graph: &G,
+        labels: &TimestampedVector<MultiCritNodeData<Self::Label>>,
+        _parents: &[(NodeId, Self::PredecessorLink)],
+        _tail: NodeIdT,
+        _key: ParetoKey<N>,
+        label: &Self::Label,
+        &(NodeIdT(head), EdgeIdT(edge)): &Self::Arc,
+    ) -> Self::LinkResult {
+        let mut costs = label.costs;
+        for (cost, metric) in costs.iter_mut().zip(&self.metrics) {
+            let weight = metric[edge as usize];
+            if weight >= INFINITY {
+                return None;
+            }
+            *cost += weight;
+        }
+
+        let mut key = costs;
+        for (key, potential) in key.iter_mut().zip(&mut self.potentials) {
+            *key += potential.potential(head)?;
+        }
+        if labels[self.target as usize]
+            .iter()
+            .any(|NodeQueueLabelOrder(target)| self.epsilon_dominates(&target.costs, &key))
+        {
+            return None;
+        }
+
+        Some((
+            ParetoLabel {
+                key: ParetoKey(key),
+                costs,
+                id: label.id,
+            },
+            head,
+            edge,
+        ))
+    }
+
+    fn merge(&mut self, label: &mut MultiCritNodeData<Self::Label>, linked: Self::LinkResult) -> Option<ParetoKey<N>> {
+        let (linked, head, edge) = linked?;
+        if label.iter().any(|NodeQueueLabelOrder(old)| dominates(&old.costs, &linked.costs)) {
+            return None;
+        }
+        // settled labels are never dominated, see module docs, so this only removes queued labels
+        label.retain(|NodeQueueLabelOrder(old)| !dominates(&linked.costs, &old.costs));
+
+        let id = self.labels.len();
+        self.labels.push((head, edge, linked.id));
+        label.push(NodeQueueLabelOrder(ParetoLabel { id, ..linked }));
+        Some(linked.key)
+    }
+
+    fn predecessor_link(&self, _link: &Self::Arc) -> Self::PredecessorLink {}
+}
+
+/// One path of the Pareto set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParetoPath<const N: usize> {
+    pub costs: [Weight; N],
+    pub node_path: Vec<NodeId>,
+    pub edge_path: Vec<EdgeId>,
+}
+
+/// Pareto query server for `N` criteria, usually two or three.
+pub struct Server<'a, G, const N: usize> {
+    graph: G,
+    dijkstra_data: DijkstraData<ParetoLabel<N>, (), MultiCritNodeData<ParetoLabel<N>>>,
+    ops: ParetoOps<'a, N>,
+}
+
+impl<'a, G: LinkIterable<(NodeIdT, EdgeIdT)>, const N: usize> Server<'a, G, N> {
+    /// `metrics` contains the weights of all arcs of `graph` for each criterion,
+    /// `pot_data` the CCH lower bounds built with the same metrics (or lower bounds of them).
+    pub fn new(graph: G, metrics: [&'a [Weight]; N], pot_data: [&'a CCHPotData; N]) -> Self {
+        for metric in &metrics {
+            assert_eq!(metric.len(), graph.num_arcs());
+        }
+
+        Self {
+            dijkstra_data: DijkstraData::new(graph.num_nodes()),
+            ops: ParetoOps {
+                metrics,
+                potentials: pot_data.map(|data| data.forward_potential()),
+                target: graph.num_nodes() as NodeId,
+                epsilon: 0.0,
+                labels: Vec::new(),
+            },
+            graph,
+        }
+    }
+
+    /// Compute the Pareto set between `query.from` and `query.to`, sorted by the first criterion.
+    /// With a positive `epsilon`, only an approximation of the Pareto set is computed, see module docs.
+    pub fn query(&mut self, query: Query, epsilon: f64) -> Vec<ParetoPath<N>> {
+        report!("algo", "pareto_dijkstra");
+        assert!(epsilon >= 0.0);
+
+        let mut key = [0; N];
+        for (key, potential) in key.iter_mut().zip(&mut self.ops.potentials) {
+            potential.init(query.to);
+            match potential.potential(query.from) {
+                Some(lower_bound) => *key = lower_bound,
+                None => return Vec::new(),
+            }
+        }
+        self.ops.target = query.to;
+        self.ops.epsilon = epsilon;
+        self.ops.labels.clear();
+        self.ops.labels.push((query.from, 0, 0));
+
+        let mut run = MultiCritDijkstraRun::query(
+            &self.graph,
+            &mut self.dijkstra_data,
+            &mut self.ops,
+            DijkstraInit {
+                source: NodeIdT(query.from),
+                initial_state: ParetoLabel {
+                    key: ParetoKey(key),
+                    costs: [0; N],
+                    id: 0,
+                },
+            },
+            |_| Some(Neutral()),
+        );
+
+        let mut num_queue_pops: usize = 0;
+        while run.next().is_some() {
+            num_queue_pops += 1;
+        }
+        report!("num_queue_pops", num_queue_pops);
+        report!("num_labels", self.ops.labels.len());
+
+        let mut paths: Vec<_> = self.dijkstra_data.distances[query.to as usize]
+            .popped()
+            .iter()
+            .map(|NodeQueueLabelOrder(label)| self.path(label))
+            .collect();
+        paths.sort_unstable_by_key(|path| path.costs);
+        report!("num_pareto_paths", paths.len());
+        paths
+    }
+
+    fn path(&self, label: &ParetoLabel<N>) -> ParetoPath<N> {
+        let mut node_path = Vec::new();
+        let mut edge_path = Vec::new();
+        let mut id = label.id;
+        while id != 0 {
+            let (node, edge, parent) = self.ops.labels[id];
+            node_path.push(node);
+            edge_path.push(edge);
+            id = parent;
+        }
+        node_path.push(self.ops.labels[0].0);
+        node_path.reverse();
+        edge_path.reverse();
+
+        ParetoPath {
+            costs: label.costs,
+            node_path,
+            edge_path,
+        }
+    }
+}
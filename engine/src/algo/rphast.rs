@@ -202,3 +202,76 @@ impl<GF: LinkIterGraph, GB: LinkIterGraph> SSERPHASTResult<'_, GF, GB> {
         &self.0.restricted_distances[restricted_id * self.2..(restricted_id + 1) * self.2]
     }
 }
+
+/// Up to this number of sources, distance matrices are computed with one RPHAST query per source.
+/// For more sources, all sources are processed together with the bucket based SSE variant.
+const MAX_SOURCES_FOR_ONE_TO_MANY: usize = 4;
+
+/// Many-to-many distances on a CH or CCH.
+/// Picks between one-to-many RPHAST queries and a single SSE RPHAST query depending on the number of sources.
+pub struct DistanceMatrixServer<GF, GB> {
+    rphast: RPHAST<GF, GB>,
+    one_to_many: RPHASTQuery,
+    many_to_many: SSERPHASTQuery,
+    unique_sources: Vec<NodeId>,
+    source_idxs: Vec<usize>,
+}
+
+impl<GF: LinkIterGraph, GB: LinkIterGraph> DistanceMatrixServer<GF, GB> {
+    /// `forward` and `backward` are the upward and downward graphs of the hierarchy, both indexed by rank.
+    pub fn new(forward: GF, backward: GB, order: NodeOrder) -> Self {
+        let rphast = RPHAST::new(forward, backward, order);
+        Self {
+            one_to_many: RPHASTQuery::new(&rphast),
+            many_to_many: SSERPHASTQuery::new(&rphast),
+            rphast,
+            unique_sources: Vec::new(),
+            source_idxs: Vec::new(),
+        }
+    }
+
+    /// Compute the distances from each source to each target.
+    /// The result contains one row per source with one entry per target.
+    /// Unreachable targets have distance `INFINITY`.
+    pub fn distances(&mut self, sources: &[NodeId], targets: &[NodeId]) -> Vec<Vec<Weight>> {
+        if sources.is_empty() || targets.is_empty() {
+            return vec![Vec::new(); sources.len()];
+        }
+
+        self.rphast.select(targets);
+
+        if sources.len() <= MAX_SOURCES_FOR_ONE_TO_MANY {
+            return sources
+                .iter()
+                .map(|&source| {
+                    let result = self.one_to_many.query(source, &self.rphast);
+                    targets.iter().map(|&target| result.distance(target)).collect()
+                })
+                .collect();
+        }
+
+        // the bucket search can't handle the same terminal twice
+        self.unique_sources.clear();
+        self.source_idxs.clear();
+        for &source in sources {
+            let idx = match self.unique_sources.iter().position(|&s| s == source) {
+                Some(idx) => idx,
+                None => {
+                    self.unique_sources.push(source);
+                    self.unique_sources.len() - 1
+                }
+            };
+            self.source_idxs.push(idx);
+        }
+
+        let mut matrix = vec![vec![INFINITY; targets.len()]; sources.len()];
+        let result = self.many_to_many.query(&self.unique_sources, &self.rphast);
+        for (target_idx, &target) in targets.iter().enumerate() {
+            let distances = result.distances(target);
+            for (row, &source_idx) in matrix.iter_mut().zip(&self.source_idxs) {
+                row[target_idx] = distances[source_idx];
+            }
+        }
+        matrix
+    }
+}
@@ -0,0 +1,79 @@
+//! Strongly connected components with Tarjan's algorithm.
+//!
+//! Road networks usually consist of one huge component and many tiny ones, for example parking lots with broken one-way tagging.
+//! Queries with endpoints outside of the main component mostly fail, so endpoints should be snapped to the main component.
+
+use super::*;
+
+/// Compute the strongly connected component of each node.
+/// Components are numbered from zero in the order Tarjan's algorithm completes them, that is in reverse topological order.
+pub fn strongly_connected_components<G: LinkIterable<NodeIdT>>(graph: &G) -> Vec<u32> {
+    const UNVISITED: u32 = u32::MAX;
+    let n = graph.num_nodes();
+
+    let mut component = vec![UNVISITED; n];
+    let mut dfs_idx = vec![UNVISITED; n];
+    let mut low_link = vec![0; n];
+    let mut num_visited = 0;
+    let mut num_components = 0;
+    // nodes visited, but not yet assigned to a component
+    let mut stack = Vec::new();
+    // explicit recursion stack with the remaining neighbors of each node, road networks are too deep for the call stack
+    let mut dfs = Vec::new();
+
+    for root in 0..n as NodeId {
+        if dfs_idx[root as usize] != UNVISITED {
+            continue;
+        }
+
+        dfs_idx[root as usize] = num_visited;
+        low_link[root as usize] = num_visited;
+        num_visited += 1;
+        stack.push(root);
+        dfs.push((root, graph.link_iter(root)));
+
+        while let Some((node, neighbors)) = dfs.last_mut() {
+            let node = *node;
+            if let Some(NodeIdT(head)) = neighbors.next() {
+                if dfs_idx[head as usize] == UNVISITED {
+                    dfs_idx[head as usize] = num_visited;
+                    low_link[head as usize] = num_visited;
+                    num_visited += 1;
+                    stack.push(head);
+                    dfs.push((head, graph.link_iter(head)));
+                } else if component[head as usize] == UNVISITED {
+                    // still on the stack, so in the component currently explored
+                    low_link[node as usize] = std::cmp::min(low_link[node as usize], dfs_idx[head as usize]);
+                }
+                continue;
+            }
+
+            dfs.pop();
+            if let Some(&(parent, _)) = dfs.last() {
+                low_link[parent as usize] = std::cmp::min(low_link[parent as usize], low_link[node as usize]);
+            }
+            if low_link[node as usize] == dfs_idx[node as usize] {
+                loop {
+                    let member = stack.pop().unwrap();
+                    component[member as usize] = num_components;
+                    if member == node {
+                        break;
+                    }
+                }
+                num_components += 1;
+            }
+        }
+    }
+
+    component
+}
+
+/// The id of the component with the most nodes, `None` for empty graphs.
+pub fn largest_component(component: &[u32]) -> Option<u32> {
+    let num_components = component.iter().max().map(|&max| max as usize + 1)?;
+    let mut sizes = vec![0usize; num_components];
+    for &c in component {
+        sizes[c as usize] += 1;
+    }
+    (0..num_components as u32).max_by_key(|&c| sizes[c as usize])
+}
@@ -0,0 +1,105 @@
+// Compute a nested dissection order for CCHs without InertialFlowCutter.
+// Takes a directory as argument, which has to contain the graph (in RoutingKit format) with `latitude` and `longitude`.
+// With `--turns`, the order is computed for the turn expanded graph.
+// The order is written to `cch_perm` (`cch_exp_perm` with `--turns`) or the file given with `--out`.
+// If that file already exists, the quality of the old order is reported, too, before it gets replaced.
+// An existing file which is no order of the graph's nodes is an error.
+
+use std::{env, error::Error, path::Path};
+
+#[macro_use]
+extern crate rust_road_router;
+use rust_road_router::{
+    algo::customizable_contraction_hierarchy::{nested_dissection::*, *},
+    cli::CliErr,
+    datastr::{
+        graph::{link_id_to_tail_mapper::link_id_to_tail, *},
+        node_order::NodeOrder,
+    },
+    io::*,
+    report::*,
+};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let _reporter = enable_reporting("cch_nested_dissection");
+    report!("num_threads", rayon::current_num_threads());
+
+    let mut args = env::args().skip(1);
+    let arg = &args.next().ok_or(CliErr("No directory arg given"))?;
+    let path = Path::new(arg);
+    let mut turns = false;
+    let mut out = None;
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--turns" => turns = true,
+            "--out" => out = Some(args.next().ok_or(CliErr("No file name given for --out"))?),
+            _ => return Err(Box::new(CliErr("Unknown argument"))),
+        }
+    }
+    let out = out.unwrap_or_else(|| if turns { "cch_exp_perm" } else { "cch_perm" }.to_string());
+    report!("turns", turns);
+
+    let graph = WeightedGraphReconstructor("travel_time").reconstruct_from(&path)?;
+    let latitude = Vec::<f32>::load_from(path.join("latitude"))?;
+    let longitude = Vec::<f32>::load_from(path.join("longitude"))?;
+
+    let (graph, order) = if turns {
+        let tail: Vec<NodeId> = (0..graph.num_arcs() as EdgeId).map(|arc| link_id_to_tail(graph.first_out(), arc)).collect();
+        // forbidden turns only remove a few arcs, so the order is computed on the line graph without U-turns
+        let exp_graph = line_graph(&graph, |from_arc, to_arc| {
+            if tail[from_arc as usize] == graph.head()[to_arc as usize] {
+                None
+            } else {
+                Some(0)
+            }
+        });
+        let order = turn_expanded_nested_dissection_order(&graph, &exp_graph, &latitude, &longitude);
+        (exp_graph, order)
+    } else {
+        let order = nested_dissection_order(&graph, &latitude, &longitude);
+        (graph, order)
+    };
+
+    if let Ok(previous) = Vec::<NodeId>::load_from(path.join(&out)) {
+        if !is_permutation(&previous, graph.num_nodes()) {
+            return Err(Box::new(CliErr("Existing order does not fit the graph, choose another file with --out")));
+        }
+        let _ctx = push_context("previous_order");
+        report_quality(&graph, NodeOrder::from_node_order(previous));
+    }
+    {
+        let _ctx = push_context("order");
+        report_quality(&graph, order.clone());
+    }
+
+    order.order().write_to(&path.join(&out))?;
+
+    Ok(())
+}
+
+fn is_permutation(order: &[NodeId], num_nodes: usize) -> bool {
+    let mut seen = vec![false; num_nodes];
+    order.len() == num_nodes
+        && order
+            .iter()
+            .all(|&node| (node as usize) < num_nodes && !std::mem::replace(&mut seen[node as usize], true))
+}
+
+fn report_quality(graph: &OwnedGraph, order: NodeOrder) {
+    let cch = CCH::fix_order_and_build(graph, order);
+    report!("num_cch_arcs", cch.num_arcs());
+
+    // the parent of a node in the elimination tree always has a higher rank
+    let elimination_tree = cch.elimination_tree();
+    let mut depth = vec![0usize; elimination_tree.len()];
+    for node in (0..elimination_tree.len()).rev() {
+        if let Some(parent) = elimination_tree[node].value() {
+            depth[node] = depth[parent as usize] + 1;
+        }
+    }
+    report!("max_elimination_tree_depth", depth.iter().max().copied().unwrap_or(0));
+    report!("avg_elimination_tree_depth", depth.iter().sum::<usize>() as f64 / depth.len() as f64);
+
+    let top_level_separator = cch.separators().children.iter().map(|child| child.nodes.len()).max().unwrap_or(0);
+    report!("top_level_separator_size", top_level_separator);
+}
@@ -0,0 +1,62 @@
+// Hub labels built from a perfectly customized CCH.
+// Takes a directory as argument, which has to contain the graph (in RoutingKit format), a nested disection order and queries.
+// The labels are stored in the `hub_labels` subdirectory and loaded from there on subsequent runs.
+
+use std::{env, error::Error, path::Path};
+
+#[macro_use]
+extern crate rust_road_router;
+use rust_road_router::{
+    algo::{
+        customizable_contraction_hierarchy::*,
+        hl::cch::{HubLabels, Server},
+    },
+    cli::CliErr,
+    datastr::{graph::*, node_order::NodeOrder},
+    experiments,
+    io::*,
+    report::*,
+};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let _reporter = enable_reporting("hl");
+    report!("num_threads", rayon::current_num_threads());
+    let arg = &env::args().skip(1).next().ok_or(CliErr("No directory arg given"))?;
+    let path = Path::new(arg);
+
+    let graph = WeightedGraphReconstructor("travel_time").reconstruct_from(&path)?;
+    let order = NodeOrder::from_node_order(Vec::load_from(path.join("cch_perm"))?);
+    let cch = CCH::fix_order_and_build(&graph, order);
+    let customized = customize_perfect(customize(&cch, &graph));
+
+    let labels_dir = path.join("hub_labels");
+    let labels = if labels_dir.exists() {
+        HubLabels::reconstruct_from(&labels_dir)?
+    } else {
+        let labels = report_time_with_key("hub label construction", "hl_construction_running_time_ms", || HubLabels::new(&customized));
+        labels.deconstruct_to(&labels_dir)?;
+        labels
+    };
+    report!("num_label_entries", labels.num_entries());
+
+    let mut server = Server::new(labels, &customized);
+
+    let from = Vec::load_from(path.join("test/source"))?;
+    let to = Vec::load_from(path.join("test/target"))?;
+    let ground_truth = Vec::load_from(path.join("test/travel_time_length"))?;
+
+    let mut gt_iter = ground_truth.iter().map(|&gt| match gt {
+        INFINITY => None,
+        val => Some(val),
+    });
+
+    experiments::run_queries(
+        from.iter().copied().zip(to.iter().copied()).take(100000),
+        &mut server,
+        None,
+        |_, _, _| (),
+        |_, _| gt_iter.next(),
+    );
+
+    Ok(())
+}
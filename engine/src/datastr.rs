@@ -6,4 +6,6 @@ pub mod heap;
 pub mod index_heap;
 pub mod node_order;
 pub mod rank_select_map;
+pub mod spatial;
 pub mod timestamped_vector;
+pub mod vehicle_restrictions;
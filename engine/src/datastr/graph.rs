@@ -11,7 +11,8 @@ pub mod link_id_to_tail_mapper;
 pub mod time_dependent;
 
 pub use self::first_out_graph::{
-    BorrowedGraph, FirstOutGraph, OwnedGraph, ReversedGraphWithEdgeIds, UnweightedFirstOutGraph, UnweightedOwnedGraph, WeightedGraphReconstructor,
+    BorrowedGraph, FirstOutGraph, MappedGraph, MappedGraphReconstructor, OwnedGraph, ReversedGraphWithEdgeIds, UnweightedFirstOutGraph, UnweightedOwnedGraph,
+    WeightedGraphReconstructor,
 };
 
 /// Node ids are 32bit unsigned ints
@@ -200,6 +201,48 @@ pub fn line_graph(graph: &impl EdgeRandomAccessGraph<Link>, mut turn_costs: impl
     OwnedGraph::new(first_out, head, weight)
 }
 
+/// Build the line graph without the turns in `forbidden_turn_from_arc` and `forbidden_turn_to_arc`,
+/// which have to be sorted lexicographically, as in the RoutingKit format.
+/// U-turns are left out too, except at dead ends, where turning around is the only way to continue.
+/// The other turns cost nothing, so the weight of a turn is the weight of the arc it starts at.
+pub fn turn_restricted_line_graph(
+    graph: &impl EdgeRandomAccessGraph<Link>,
+    forbidden_turn_from_arc: &[EdgeId],
+    forbidden_turn_to_arc: &[EdgeId],
+) -> OwnedGraph {
+    let mut tail = vec![0; graph.num_arcs()];
+    for node in 0..graph.num_nodes() {
+        for edge in graph.neighbor_edge_indices(node as NodeId) {
+            tail[edge as usize] = node as NodeId;
+        }
+    }
+
+    // turns are enumerated in lexicographic order, so the forbidden turns can be merged in
+    let mut forbidden = forbidden_turn_from_arc.iter().zip(forbidden_turn_to_arc.iter()).peekable();
+    line_graph(graph, |from_arc, to_arc| {
+        while let Some((&forbidden_from, &forbidden_to)) = forbidden.peek() {
+            if forbidden_from < from_arc || (forbidden_from == from_arc && forbidden_to < to_arc) {
+                forbidden.next();
+            } else {
+                break;
+            }
+        }
+        if forbidden.peek() == Some(&(&from_arc, &to_arc)) {
+            return None;
+        }
+
+        let origin = tail[from_arc as usize];
+        if graph.link(to_arc).node == origin {
+            let via = graph.link(from_arc).node;
+            let dead_end = graph.neighbor_edge_indices(via).all(|edge| graph.link(edge).node == origin);
+            if !dead_end {
+                return None;
+            }
+        }
+        Some(0)
+    })
+}
+
 /// Generic Trait for building reversed graphs.
 /// Type setup similar to `FromIter` for `std::iter::collect`.
 pub trait BuildReversed<G> {
@@ -107,6 +107,32 @@ where
 
 pub type OwnedGraph<W = Weight> = FirstOutGraph<Vec<EdgeId>, Vec<NodeId>, Vec<W>, W>;
 pub type BorrowedGraph<'a, W = Weight> = FirstOutGraph<&'a [EdgeId], &'a [NodeId], &'a [W], W>;
+/// Graph with memory mapped read-only data, see `io::Mapped`.
+pub type MappedGraph<W = Weight> = FirstOutGraph<Mapped<EdgeId>, Mapped<NodeId>, Mapped<W>, W>;
+
+impl<W: Copy> MappedGraph<W> {
+    // Mapped files are not read before they are used, so check the sizes here and fail with an error rather than a panic in `new`.
+    fn new_checked(first_out: Mapped<EdgeId>, head: Mapped<NodeId>, weight: Mapped<W>) -> std::io::Result<Self> {
+        if first_out.first() != Some(&0) || first_out.last().map(|&m| m as usize) != Some(head.len()) || weight.len() != head.len() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "inconsistent graph data: {} first_out entries, {} head entries, {} weights",
+                    first_out.len(),
+                    head.len(),
+                    weight.len()
+                ),
+            ));
+        }
+        Ok(Self::new(first_out, head, weight))
+    }
+}
+
+impl<W: Copy> Reconstruct for MappedGraph<W> {
+    fn reconstruct_with(loader: Loader) -> std::io::Result<Self> {
+        Self::new_checked(loader.map("first_out")?, loader.map("head")?, loader.map("weights")?)
+    }
+}
 
 impl OwnedGraph {
     pub fn from_adjancecy_lists(adjancecy_lists: Vec<Vec<Link>>) -> OwnedGraph {
@@ -198,6 +224,17 @@ impl ReconstructPrepared<OwnedGraph> for WeightedGraphReconstructor {
     }
 }
 
+/// Like `WeightedGraphReconstructor` but memory maps the graph instead of loading it.
+pub struct MappedGraphReconstructor(pub &'static str);
+
+impl ReconstructPrepared<MappedGraph> for MappedGraphReconstructor {
+    fn reconstruct_with(self, loader: Loader) -> std::io::Result<MappedGraph> {
+        let g = MappedGraph::new_checked(loader.map("first_out")?, loader.map("head")?, loader.map(self.0)?)?;
+        report!("graph", { "num_nodes": g.num_nodes(), "num_arcs": g.num_arcs() });
+        Ok(g)
+    }
+}
+
 impl<FirstOutContainer, HeadContainer> FirstOutGraph<FirstOutContainer, HeadContainer, Vec<Weight>> {
     pub fn swap_weights(&mut self, new_weights: &mut Vec<Weight>) {
         assert!(new_weights.len() == self.weight.len());
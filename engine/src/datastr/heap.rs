@@ -15,6 +15,11 @@ impl<T> Heap<T> {
         // pub fn new(cmp: C) -> Self {
         Self { data: vec![], split: 0 }
     }
+
+    /// All elements, queued and popped, in no particular order.
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.data.iter()
+    }
 }
 
 #[allow(unused_unsafe)]
@@ -0,0 +1,313 @@
+//! Spatial index over the arcs of a graph, to snap coordinates to the closest point on the road network.
+//!
+//! The index is a static R-tree over all arc segments, bulk loaded with sort-tile-recursive packing.
+//! Arcs are straight lines between the coordinates of their tail and head unless a link geometry is given,
+//! in which case each arc consists of one segment per pair of consecutive points.
+//! Distances are in meters, computed with an equirectangular projection around the query point,
+//! which is precise enough for the distances snapping deals with.
+
+use crate::datastr::graph::*;
+use std::{cmp::Ordering, collections::BinaryHeap, ops::Range};
+
+const EARTH_RADIUS: f64 = 6_371_000.0; // [m]
+const METERS_PER_DEGREE: f64 = EARTH_RADIUS * std::f64::consts::PI / 180.0;
+/// Number of children of each R-tree node.
+const NODE_CAPACITY: usize = 16;
+
+/// The side of an arc a point lies on, relative to the direction of travel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// The closest point on an arc to some query coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EdgeSnap {
+    pub arc: EdgeId,
+    /// Fraction of the arc (by length) before the snapped point.
+    pub fraction: f32,
+    /// Distance between query coordinates and snapped point in meters.
+    pub distance: f32,
+    /// Coordinates of the snapped point.
+    pub point: (f32, f32),
+    /// Which side of the arc the query coordinates are on.
+    /// With two arcs for both directions of a road, this hints at the one the query belongs to.
+    pub side: Side,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    arc: EdgeId,
+    from: (f32, f32),
+    to: (f32, f32),
+    // length of the arc before this segment in meters
+    offset: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Rect {
+    min: (f32, f32),
+    max: (f32, f32),
+}
+
+impl Rect {
+    fn of_segment(segment: &Segment) -> Self {
+        Rect {
+            min: (segment.from.0.min(segment.to.0), segment.from.1.min(segment.to.1)),
+            max: (segment.from.0.max(segment.to.0), segment.from.1.max(segment.to.1)),
+        }
+    }
+
+    fn union(rects: &[Rect]) -> Self {
+        rects.iter().skip(1).fold(rects[0], |acc, rect| Rect {
+            min: (acc.min.0.min(rect.min.0), acc.min.1.min(rect.min.1)),
+            max: (acc.max.0.max(rect.max.0), acc.max.1.max(rect.max.1)),
+        })
+    }
+
+    fn center(&self) -> (f32, f32) {
+        ((self.min.0 + self.max.0) / 2.0, (self.min.1 + self.max.1) / 2.0)
+    }
+
+    // Lower bound for the distance in meters from the point to anything in the rectangle.
+    fn distance(&self, (lat, lng): (f32, f32), lng_scale: f64) -> f64 {
+        let dlat = f64::from((self.min.0 - lat).max(lat - self.max.0).max(0.0)) * METERS_PER_DEGREE;
+        let dlng = f64::from((self.min.1 - lng).max(lng - self.max.1).max(0.0)) * lng_scale;
+        (dlat * dlat + dlng * dlng).sqrt()
+    }
+}
+
+/// Static R-tree over the arc segments of a graph.
+pub struct SpatialIndex {
+    // segments in the order of the leaves
+    segments: Vec<Segment>,
+    // length of each arc in meters
+    arc_length: Vec<f32>,
+    // bounding boxes for each level, starting with the segments;
+    // entry `i` of level `l + 1` covers the entries `i * NODE_CAPACITY..(i + 1) * NODE_CAPACITY` of level `l`
+    levels: Vec<Vec<Rect>>,
+}
+
+impl SpatialIndex {
+    /// Index the arcs of a graph in RoutingKit format as straight lines between the node coordinates.
+    pub fn new(first_out: &[EdgeId], head: &[NodeId], latitude: &[f32], longitude: &[f32]) -> Self {
+        Self::build(first_out, head, |_arc, tail, head| {
+            vec![(latitude[tail as usize], longitude[tail as usize]), (latitude[head as usize], longitude[head as usize])]
+        })
+    }
+
+    /// Index the arcs with their link geometry.
+    /// The geometry is given in the RoutingKit format, that is the points between tail and head of `arc`
+    /// are the ones in `first_modelling_node[arc]..first_modelling_node[arc + 1]`.
+    pub fn with_geometry(
+        first_out: &[EdgeId],
+        head: &[NodeId],
+        latitude: &[f32],
+        longitude: &[f32],
+        first_modelling_node: &[u32],
+        modelling_node_latitude: &[f32],
+        modelling_node_longitude: &[f32],
+    ) -> Self {
+        assert_eq!(first_modelling_node.len(), head.len() + 1);
+        Self::build(first_out, head, |arc, tail, head| {
+            let modelling_nodes = first_modelling_node[arc as usize] as usize..first_modelling_node[arc as usize + 1] as usize;
+            std::iter::once((latitude[tail as usize], longitude[tail as usize]))
+                .chain(modelling_nodes.map(|point| (modelling_node_latitude[point], modelling_node_longitude[point])))
+                .chain(std::iter::once((latitude[head as usize], longitude[head as usize])))
+                .collect()
+        })
+    }
+
+    fn build(first_out: &[EdgeId], head: &[NodeId], geometry: impl Fn(EdgeId, NodeId, NodeId) -> Vec<(f32, f32)>) -> Self {
+        let mut segments = Vec::with_capacity(head.len());
+        let mut arc_length = Vec::with_capacity(head.len());
+        for tail in 0..first_out.len() - 1 {
+            for arc in first_out[tail]..first_out[tail + 1] {
+                let points = geometry(arc, tail as NodeId, head[arc as usize]);
+                let mut offset = 0.0;
+                for pair in points.windows(2) {
+                    segments.push(Segment {
+                        arc,
+                        from: pair[0],
+                        to: pair[1],
+                        offset,
+                    });
+                    offset += distance(pair[0], pair[1]);
+                }
+                arc_length.push(offset);
+            }
+        }
+
+        // sort-tile-recursive: slices by latitude, sorted by longitude within each slice
+        let num_leaves = segments.len().div_ceil(NODE_CAPACITY);
+        let num_slices = (num_leaves as f64).sqrt().ceil().max(1.0) as usize;
+        let slice_size = num_slices * NODE_CAPACITY;
+        segments.sort_by(|a, b| Rect::of_segment(a).center().0.total_cmp(&Rect::of_segment(b).center().0));
+        for slice in segments.chunks_mut(slice_size) {
+            slice.sort_by(|a, b| Rect::of_segment(a).center().1.total_cmp(&Rect::of_segment(b).center().1));
+        }
+
+        let mut levels = vec![segments.iter().map(Rect::of_segment).collect::<Vec<_>>()];
+        while levels.last().unwrap().len() > 1 {
+            let parents = levels.last().unwrap().chunks(NODE_CAPACITY).map(Rect::union).collect();
+            levels.push(parents);
+        }
+
+        SpatialIndex { segments, arc_length, levels }
+    }
+
+    /// The closest arc to the given coordinates, `None` only for graphs without arcs.
+    /// With two arcs for both directions of a road, either one may be returned.
+    pub fn nearest(&self, lat: f32, lng: f32) -> Option<EdgeSnap> {
+        self.nearest_within(lat, lng, f32::INFINITY, 0.0, &|_| true).into_iter().next()
+    }
+
+    /// The closest arc and all other arcs at most `tolerance` meters farther away, sorted by distance.
+    /// For example both directions of the closest road.
+    pub fn nearest_with_tolerance(&self, lat: f32, lng: f32, tolerance: f32) -> Vec<EdgeSnap> {
+        self.nearest_within(lat, lng, f32::INFINITY, tolerance, &|_| true)
+    }
+
+    /// Like `nearest_with_tolerance`, but only arcs for which `filter` returns `true` are considered.
+    /// For example to snap only to arcs in the main strongly connected component.
+    pub fn nearest_filtered(&self, lat: f32, lng: f32, tolerance: f32, filter: impl Fn(EdgeId) -> bool) -> Vec<EdgeSnap> {
+        self.nearest_within(lat, lng, f32::INFINITY, tolerance, &filter)
+    }
+
+    /// All arcs at most `radius` meters away, each with its closest point, sorted by distance.
+    pub fn within(&self, lat: f32, lng: f32, radius: f32) -> Vec<EdgeSnap> {
+        self.nearest_within(lat, lng, radius, f32::INFINITY, &|_| true)
+    }
+
+    // Best first search over the tree, stops at the first arc farther away than `radius` or `tolerance` more than the closest one.
+    fn nearest_within(&self, lat: f32, lng: f32, radius: f32, tolerance: f32, filter: &dyn Fn(EdgeId) -> bool) -> Vec<EdgeSnap> {
+        let mut result: Vec<EdgeSnap> = Vec::new();
+        if self.segments.is_empty() {
+            return result;
+        }
+        let lng_scale = f64::from(lat).to_radians().cos() * METERS_PER_DEGREE;
+
+        let mut queue = BinaryHeap::new();
+        queue.push(Entry {
+            distance: 0.0,
+            level: self.levels.len() - 1,
+            idx: 0,
+            snap: None,
+        });
+        while let Some(Entry { distance, level, idx, snap }) = queue.pop() {
+            if distance > f64::from(radius) || result.first().is_some_and(|first| distance > f64::from(first.distance + tolerance)) {
+                break;
+            }
+            if let Some(snap) = snap {
+                // segments of an arc already found are farther away
+                if result.iter().all(|found| found.arc != snap.arc) {
+                    result.push(snap);
+                }
+                continue;
+            }
+            if level == 0 {
+                if !filter(self.segments[idx].arc) {
+                    continue;
+                }
+                let snap = self.snap_to_segment(&self.segments[idx], (lat, lng), lng_scale);
+                queue.push(Entry {
+                    distance: f64::from(snap.distance),
+                    level,
+                    idx,
+                    snap: Some(snap),
+                });
+                continue;
+            }
+            for child in children(idx, self.levels[level - 1].len()) {
+                queue.push(Entry {
+                    distance: self.levels[level - 1][child].distance((lat, lng), lng_scale),
+                    level: level - 1,
+                    idx: child,
+                    snap: None,
+                });
+            }
+        }
+        result
+    }
+
+    fn snap_to_segment(&self, segment: &Segment, (lat, lng): (f32, f32), lng_scale: f64) -> EdgeSnap {
+        // local cartesian coordinates in meters around the query point
+        let to_local = |(p_lat, p_lng): (f32, f32)| (f64::from(p_lng - lng) * lng_scale, f64::from(p_lat - lat) * METERS_PER_DEGREE);
+        let (ax, ay) = to_local(segment.from);
+        let (bx, by) = to_local(segment.to);
+        let (dx, dy) = (bx - ax, by - ay);
+        let squared_length = dx * dx + dy * dy;
+        let t = if squared_length > 0.0 {
+            (-(ax * dx + ay * dy) / squared_length).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let (px, py) = (ax + t * dx, ay + t * dy);
+
+        let arc_length = self.arc_length[segment.arc as usize];
+        let fraction = if arc_length > 0.0 {
+            ((segment.offset + t as f32 * squared_length.sqrt() as f32) / arc_length).min(1.0)
+        } else {
+            0.0
+        };
+        // the query point is the origin, so the cross product of segment direction and the vector to the origin gives the side
+        let side = if dx * -ay - dy * -ax > 0.0 { Side::Left } else { Side::Right };
+
+        EdgeSnap {
+            arc: segment.arc,
+            fraction,
+            distance: (px * px + py * py).sqrt() as f32,
+            point: (
+                segment.from.0 + t as f32 * (segment.to.0 - segment.from.0),
+                segment.from.1 + t as f32 * (segment.to.1 - segment.from.1),
+            ),
+            side,
+        }
+    }
+}
+
+fn children(idx: usize, num_children: usize) -> Range<usize> {
+    idx * NODE_CAPACITY..std::cmp::min((idx + 1) * NODE_CAPACITY, num_children)
+}
+
+// Queue entry for the best first search, ordered by distance with the smallest first.
+struct Entry {
+    distance: f64,
+    level: usize,
+    idx: usize,
+    snap: Option<EdgeSnap>,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // exact distances before lower bounds, so arcs are reported as soon as they are the closest
+        other
+            .distance
+            .total_cmp(&self.distance)
+            .then_with(|| self.snap.is_some().cmp(&other.snap.is_some()))
+    }
+}
+
+/// Distance in meters between two coordinates, with the equirectangular approximation.
+/// Only suitable for short distances, like between consecutive GPS points.
+pub fn distance((lat1, lng1): (f32, f32), (lat2, lng2): (f32, f32)) -> f32 {
+    let mean_lat = (f64::from(lat1) + f64::from(lat2)) / 2.0;
+    let x = f64::from(lng2 - lng1) * mean_lat.to_radians().cos();
+    let y = f64::from(lat2 - lat1);
+    ((x * x + y * y).sqrt() * METERS_PER_DEGREE) as f32
+}
@@ -0,0 +1,145 @@
+//! Vehicle restrictions of arcs and vehicle profiles which are not allowed to use some of them.
+//!
+//! The restrictions are stored next to `head` and `travel_time`, one file per attribute and one entry per arc.
+//! A `VehicleProfile` turns them into a metric where all arcs the vehicle may not use have an infinite weight.
+//! So all profiles share a single CCH and only need their own customization.
+
+use super::graph::*;
+use crate::io::*;
+
+/// Marks arcs without a height or weight limit.
+pub const NO_LIMIT: u32 = u32::MAX;
+
+/// Bits for classes of dangerous goods.
+pub mod hazmat {
+    /// Goods which pollute water, restricted in water protection areas.
+    pub const WATER_POLLUTING: u8 = 1;
+    pub const EXPLOSIVE: u8 = 2;
+    pub const FLAMMABLE: u8 = 4;
+    /// All other dangerous goods.
+    pub const OTHER: u8 = 8;
+    pub const ALL: u8 = u8::MAX;
+}
+
+/// Bits for vehicle classes.
+pub mod vehicle_class {
+    pub const CAR: u32 = 1;
+    pub const TRUCK: u32 = 2;
+    pub const BUS: u32 = 4;
+    pub const DELIVERY: u32 = 8;
+    pub const ALL: u32 = u32::MAX;
+}
+
+/// Per arc restrictions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkAttributes {
+    /// Maximum vehicle height in cm, `NO_LIMIT` if unrestricted.
+    pub max_height: Vec<u32>,
+    /// Maximum vehicle weight in kg, `NO_LIMIT` if unrestricted.
+    pub max_weight: Vec<u32>,
+    /// Bits of the `hazmat` classes which are not allowed.
+    pub forbidden_hazmat: Vec<u8>,
+    /// Bits of the vehicle classes which are allowed.
+    pub allowed_vehicle_classes: Vec<u32>,
+}
+
+impl LinkAttributes {
+    /// Attributes of `num_arcs` arcs without any restrictions.
+    pub fn unrestricted(num_arcs: usize) -> Self {
+        Self {
+            max_height: vec![NO_LIMIT; num_arcs],
+            max_weight: vec![NO_LIMIT; num_arcs],
+            forbidden_hazmat: vec![0; num_arcs],
+            allowed_vehicle_classes: vec![vehicle_class::ALL; num_arcs],
+        }
+    }
+
+    pub fn num_arcs(&self) -> usize {
+        self.max_height.len()
+    }
+}
+
+impl Deconstruct for LinkAttributes {
+    fn save_each(&self, store: &dyn Fn(&str, &dyn Save) -> std::io::Result<()>) -> std::io::Result<()> {
+        store("link_max_height", &self.max_height)?;
+        store("link_max_weight", &self.max_weight)?;
+        store("link_forbidden_hazmat", &self.forbidden_hazmat)?;
+        store("link_allowed_vehicle_classes", &self.allowed_vehicle_classes)?;
+        Ok(())
+    }
+}
+
+/// Load `LinkAttributes` for the given number of arcs.
+/// Missing files are treated as no restrictions for that attribute.
+pub struct LinkAttributesReconstructor(pub usize);
+
+impl ReconstructPrepared<LinkAttributes> for LinkAttributesReconstructor {
+    fn reconstruct_with(self, loader: Loader) -> std::io::Result<LinkAttributes> {
+        let num_arcs = self.0;
+        fn load_or<T: Default + Copy>(loader: &Loader, name: &str, num_arcs: usize, default: T) -> std::io::Result<Vec<T>> {
+            if !loader.path().join(name).exists() {
+                return Ok(vec![default; num_arcs]);
+            }
+            let values: Vec<T> = loader.load(name)?;
+            if values.len() != num_arcs {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("{} does not match the number of arcs", name),
+                ));
+            }
+            Ok(values)
+        }
+
+        Ok(LinkAttributes {
+            max_height: load_or(&loader, "link_max_height", num_arcs, NO_LIMIT)?,
+            max_weight: load_or(&loader, "link_max_weight", num_arcs, NO_LIMIT)?,
+            forbidden_hazmat: load_or(&loader, "link_forbidden_hazmat", num_arcs, 0)?,
+            allowed_vehicle_classes: load_or(&loader, "link_allowed_vehicle_classes", num_arcs, vehicle_class::ALL)?,
+        })
+    }
+}
+
+/// Dimensions and load of a vehicle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VehicleProfile {
+    /// Height in cm
+    pub height: u32,
+    /// Total weight in kg
+    pub weight: u32,
+    /// Bits of the `hazmat` classes of the load
+    pub hazmat: u8,
+    /// Bits of the classes this vehicle belongs to, usually exactly one
+    pub vehicle_class: u32,
+}
+
+impl Default for VehicleProfile {
+    fn default() -> Self {
+        Self {
+            height: 0,
+            weight: 0,
+            hazmat: 0,
+            vehicle_class: vehicle_class::CAR,
+        }
+    }
+}
+
+impl VehicleProfile {
+    /// Whether this vehicle may use `arc`. Limits are inclusive.
+    pub fn allows(&self, attributes: &LinkAttributes, arc: EdgeId) -> bool {
+        let arc = arc as usize;
+        self.height <= attributes.max_height[arc]
+            && self.weight <= attributes.max_weight[arc]
+            && self.hazmat & attributes.forbidden_hazmat[arc] == 0
+            && self.vehicle_class & attributes.allowed_vehicle_classes[arc] != 0
+    }
+
+    /// `weights` with all arcs this vehicle may not use set to `INFINITY`, to be passed on to `customize`.
+    pub fn metric(&self, attributes: &LinkAttributes, weights: &[Weight]) -> Vec<Weight> {
+        assert_eq!(weights.len(), attributes.num_arcs());
+        weights
+            .iter()
+            .enumerate()
+            .map(|(arc, &weight)| if self.allows(attributes, arc as EdgeId) { weight } else { INFINITY })
+            .collect()
+    }
+}
@@ -15,16 +15,36 @@
 //! head.write_to(&"output_file")?;
 //! # Ok::<(), Box<dyn std::error::Error>>(())
 //! ```
+//!
+//! Large read-only data can also be memory mapped instead of loaded with `Mapped::map_from` or `Loader::map`.
+//! Mapped data is loaded lazily by the OS and the pages are shared between all processes mapping the same file.
+//!
+//! By default, files contain only the raw bytes of the data.
+//! Optionally, files can be written with a self-describing header (`Store::write_with_header_to`, see `header`)
+//! and directories can contain a manifest of the files which belong together (see `manifest`).
+//! Both are validated when loading, and legacy files without header or manifest are still accepted.
 
 use std::{
     ffi::OsStr,
     fs::{metadata, File},
-    io::{prelude::*, Result},
+    io::{prelude::*, Error, ErrorKind, Result},
+    marker::PhantomData,
     mem,
+    ops::Deref,
     path::Path,
     slice,
+    sync::Arc,
 };
 
+use memmap2::Mmap;
+
+pub mod header;
+pub mod manifest;
+
+use self::header::{invalid_data, Header, HEADER_SIZE};
+pub use self::header::{ElementLayout, ElementType};
+pub use self::manifest::{Manifest, ManifestEntry};
+
 /// A trait which allows accessing the data of an object as a slice of bytes.
 /// The bytes should represent a serialization of the object and allow
 /// recreating it when reading these bytes again from the disk.
@@ -33,6 +53,11 @@ use std::{
 pub trait DataBytes {
     /// Should return the serialized object as a slice of bytes
     fn data_bytes(&self) -> &[u8];
+
+    /// Type of the elements in `data_bytes`, recorded in headers and manifests
+    fn element_layout(&self) -> ElementLayout {
+        ElementLayout::bytes()
+    }
 }
 
 /// A trait which mutably exposes the internal data of an object so that
@@ -50,6 +75,10 @@ impl<T: Copy> DataBytes for [T] {
         let num_bytes = self.len() * mem::size_of::<T>();
         unsafe { slice::from_raw_parts(self.as_ptr() as *const u8, num_bytes) }
     }
+
+    fn element_layout(&self) -> ElementLayout {
+        ElementLayout::of::<T>()
+    }
 }
 
 impl<T: Copy> DataBytes for &[T] {
@@ -57,18 +86,30 @@ impl<T: Copy> DataBytes for &[T] {
         let num_bytes = self.len() * mem::size_of::<T>();
         unsafe { slice::from_raw_parts(self.as_ptr() as *const u8, num_bytes) }
     }
+
+    fn element_layout(&self) -> ElementLayout {
+        ElementLayout::of::<T>()
+    }
 }
 
 impl<T: Copy> DataBytes for Vec<T> {
     fn data_bytes(&self) -> &[u8] {
         self[..].data_bytes()
     }
+
+    fn element_layout(&self) -> ElementLayout {
+        ElementLayout::of::<T>()
+    }
 }
 
 impl<T: Copy> DataBytes for Box<[T]> {
     fn data_bytes(&self) -> &[u8] {
         self[..].data_bytes()
     }
+
+    fn element_layout(&self) -> ElementLayout {
+        ElementLayout::of::<T>()
+    }
 }
 
 impl<T: Copy> DataBytesMut for [T] {
@@ -96,6 +137,14 @@ pub trait Store: DataBytes {
     fn write_to(&self, path: &dyn AsRef<Path>) -> Result<()> {
         File::create(path)?.write_all(self.data_bytes())
     }
+
+    /// Writes the serialized object prefixed with a header describing the data, see `header`.
+    fn write_with_header_to(&self, path: &dyn AsRef<Path>) -> Result<()> {
+        let data = self.data_bytes();
+        let mut file = File::create(path)?;
+        file.write_all(&Header::new(self.element_layout(), data).to_bytes())?;
+        file.write_all(data)
+    }
 }
 
 impl<T: DataBytes> Store for T {}
@@ -107,21 +156,67 @@ pub trait Load: DataBytesMut + Sized {
     /// It should not be necessary to call this method directly.
     fn new_with_bytes(num_bytes: usize) -> Self;
 
+    /// The expected type of the elements, if known.
+    /// Used to validate files with a header or a manifest.
+    fn element_layout() -> Option<ElementLayout> {
+        None
+    }
+
     /// This method will load serialized data from the disk, create an object of the appropriate size,
     /// deserialize the bytes into the object and return the object.
+    /// If the file has a header, the data will be validated against it.
+    /// If the directory of the file contains a manifest, the data will be checked against the manifest.
     fn load_from<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let metadata = metadata(path.as_ref())?;
-        let mut file = File::open(path)?;
+        let path = path.as_ref();
+        let manifest = match path.parent() {
+            Some(dir) => Manifest::read_from_dir(dir)?,
+            None => None,
+        };
+        load_checked(path, manifest.as_ref())
+    }
+}
 
-        let mut object = Self::new_with_bytes(metadata.len() as usize);
-        assert_eq!(metadata.len() as usize, object.data_bytes_mut().len());
-        file.read_exact(object.data_bytes_mut())?;
+// Load a file and check it against its header and the given manifest of its directory.
+fn load_checked<T: Load>(path: &Path, manifest: Option<&Manifest>) -> Result<T> {
+    let metadata = metadata(path)?;
+    let mut file = File::open(path)?;
+
+    let header = Header::read(&mut file).map_err(|e| invalid_data(format!("{}: {}", path.display(), e)))?;
+    let num_bytes = metadata.len() as usize - if header.is_some() { HEADER_SIZE } else { 0 };
+    if let Some(header) = &header {
+        header
+            .validate_layout(T::element_layout(), num_bytes)
+            .map_err(|e| invalid_data(format!("{}: {}", path.display(), e)))?;
+    } else if let Some(layout) = T::element_layout() {
+        if !num_bytes.is_multiple_of(layout.size) {
+            return Err(invalid_data(format!(
+                "size of {} ({} bytes) is not a multiple of the element size ({} bytes)",
+                path.display(),
+                num_bytes,
+                layout.size
+            )));
+        }
+    }
 
-        Ok(object)
+    let mut object = T::new_with_bytes(num_bytes);
+    assert_eq!(num_bytes, object.data_bytes_mut().len());
+    file.read_exact(object.data_bytes_mut())?;
+
+    if let Some(header) = &header {
+        header
+            .validate_checksum(object.data_bytes_mut())
+            .map_err(|e| invalid_data(format!("{}: {}", path.display(), e)))?;
     }
+    manifest::check_loaded(manifest, path, T::element_layout(), object.data_bytes_mut())?;
+
+    Ok(object)
 }
 
 impl<T: Default + Copy> Load for Vec<T> {
+    fn element_layout() -> Option<ElementLayout> {
+        Some(ElementLayout::of::<T>())
+    }
+
     fn new_with_bytes(num_bytes: usize) -> Self {
         assert_eq!(num_bytes % mem::size_of::<T>(), 0);
         let num_elements = num_bytes / mem::size_of::<T>();
@@ -130,6 +225,10 @@ impl<T: Default + Copy> Load for Vec<T> {
 }
 
 impl<T: Default + Copy> Load for Box<[T]> {
+    fn element_layout() -> Option<ElementLayout> {
+        Some(ElementLayout::of::<T>())
+    }
+
     fn new_with_bytes(num_bytes: usize) -> Self {
         assert_eq!(num_bytes % mem::size_of::<T>(), 0);
         let num_elements = num_bytes / mem::size_of::<T>();
@@ -137,6 +236,114 @@ impl<T: Default + Copy> Load for Box<[T]> {
     }
 }
 
+/// Read-only memory mapped file data, which can be used like a `&[T]`.
+/// Can be used as the container type of graphs, for example `FirstOutGraph<Mapped<EdgeId>, Mapped<NodeId>, Mapped<Weight>>`.
+/// Cloning is cheap, the clones share the same mapping.
+///
+/// `T` has to be a plain data type for which every bit pattern is valid, same as for `Load`.
+/// The file must not be modified while it is mapped.
+/// Headers are validated except for the checksum, and manifests are not checked, because both would require reading the entire file.
+pub struct Mapped<T> {
+    // `None` for empty files, which can't be mapped
+    mmap: Option<Arc<Mmap>>,
+    // start of the data, behind the header if there is one
+    offset: usize,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Copy> Mapped<T> {
+    /// Map the file at the given path.
+    /// Fails with `ErrorKind::InvalidData` if the file size is not a multiple of the size of `T`
+    /// or the mapping is not properly aligned for `T`.
+    pub fn map_from<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut file = File::open(path.as_ref())?;
+        let header = Header::read(&mut file).map_err(|e| invalid_data(format!("{}: {}", path.as_ref().display(), e)))?;
+        let offset = if header.is_some() { HEADER_SIZE } else { 0 };
+        let num_bytes = file.metadata()?.len() as usize - offset;
+        if let Some(header) = &header {
+            header
+                .validate_layout(Some(ElementLayout::of::<T>()), num_bytes)
+                .map_err(|e| invalid_data(format!("{}: {}", path.as_ref().display(), e)))?;
+        }
+        if !num_bytes.is_multiple_of(mem::size_of::<T>()) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "size of {} ({} bytes) is not a multiple of the element size ({} bytes)",
+                    path.as_ref().display(),
+                    num_bytes,
+                    mem::size_of::<T>()
+                ),
+            ));
+        }
+        if num_bytes == 0 {
+            return Ok(Self {
+                mmap: None,
+                offset: 0,
+                _phantom: PhantomData,
+            });
+        }
+
+        let mmap = unsafe { Mmap::map(&file)? };
+        if !(mmap.as_ptr() as usize + offset).is_multiple_of(mem::align_of::<T>()) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("mapping of {} is not aligned to {} bytes", path.as_ref().display(), mem::align_of::<T>()),
+            ));
+        }
+
+        Ok(Self {
+            mmap: Some(Arc::new(mmap)),
+            offset,
+            _phantom: PhantomData,
+        })
+    }
+}
+
+impl<T: Copy> Deref for Mapped<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        match &self.mmap {
+            // size and alignment were checked when mapping
+            Some(mmap) => unsafe { slice::from_raw_parts(mmap.as_ptr().add(self.offset) as *const T, (mmap.len() - self.offset) / mem::size_of::<T>()) },
+            None => &[],
+        }
+    }
+}
+
+impl<T: Copy> AsRef<[T]> for Mapped<T> {
+    fn as_ref(&self) -> &[T] {
+        self
+    }
+}
+
+impl<T: Copy> DataBytes for Mapped<T> {
+    fn data_bytes(&self) -> &[u8] {
+        self[..].data_bytes()
+    }
+
+    fn element_layout(&self) -> ElementLayout {
+        ElementLayout::of::<T>()
+    }
+}
+
+impl<T> Clone for Mapped<T> {
+    fn clone(&self) -> Self {
+        Self {
+            mmap: self.mmap.clone(),
+            offset: self.offset,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: Copy + std::fmt::Debug> std::fmt::Debug for Mapped<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
 /// A trait to allow serializing more complex objects
 /// which need more than a single file.
 pub trait Deconstruct: Sized {
@@ -145,22 +352,56 @@ pub trait Deconstruct: Sized {
     fn save_each(&self, store_callback: &dyn Fn(&str, &dyn Save) -> Result<()>) -> Result<()>;
 
     /// Call with a directory arg to store this object in this directory.
+    /// If the directory already contains a manifest, the entries of the written files are updated.
     fn deconstruct_to(&self, dir: &dyn AsRef<Path>) -> Result<()> {
-        if !dir.as_ref().exists() {
-            std::fs::create_dir(dir)?;
+        deconstruct_into(self, dir.as_ref(), false)
+    }
+
+    /// Like `deconstruct_to` but also creates a manifest for the written files if there is none yet.
+    fn deconstruct_with_manifest_to(&self, dir: &dyn AsRef<Path>) -> Result<()> {
+        deconstruct_into(self, dir.as_ref(), true)
+    }
+}
+
+fn deconstruct_into<D: Deconstruct>(object: &D, dir: &Path, create_manifest: bool) -> Result<()> {
+    if !dir.exists() {
+        std::fs::create_dir(dir)?;
+    }
+    let manifest = Manifest::read_from_dir(dir)?.or_else(|| if create_manifest { Some(Manifest::new()) } else { None });
+    let manifest = std::cell::RefCell::new(manifest);
+    object.save_each(&|name, object: &dyn Save| {
+        object.save(&dir.join(name))?;
+        if let Some(manifest) = manifest.borrow_mut().as_mut() {
+            match object.manifest_entry() {
+                Some(entry) => manifest.files.insert(name.to_string(), entry),
+                None => manifest.files.remove(name),
+            };
         }
-        self.save_each(&|name, object: &dyn Save| object.save(&dir.as_ref().join(name)))
+        Ok(())
+    })?;
+    if let Some(manifest) = manifest.into_inner() {
+        manifest.write_to_dir(dir)?;
     }
+    Ok(())
 }
 
 pub trait Save {
     fn save(&self, path: &dyn AsRef<Path>) -> Result<()>;
+
+    /// The manifest entry for the saved data, `None` for nested objects, which are stored in a subdirectory.
+    fn manifest_entry(&self) -> Option<ManifestEntry> {
+        None
+    }
 }
 
 impl<T: Store> Save for T {
     fn save(&self, path: &dyn AsRef<Path>) -> Result<()> {
         self.write_to(path)
     }
+
+    fn manifest_entry(&self) -> Option<ManifestEntry> {
+        Some(ManifestEntry::new(self.element_layout(), self.data_bytes()))
+    }
 }
 
 pub struct Sub<'a, T>(pub &'a T);
@@ -174,14 +415,34 @@ impl<T: Deconstruct> Save for Sub<'_, T> {
 /// Basically used as a callback for each object to load.
 pub struct Loader<'a> {
     path: &'a Path,
+    // read once for all files loaded from this directory
+    manifest: Option<Manifest>,
 }
 
 impl<'a> Loader<'a> {
+    fn new(path: &'a Path) -> Result<Self> {
+        Ok(Self {
+            path,
+            manifest: Manifest::read_from_dir(path)?,
+        })
+    }
+
     /// Call this method for each file that should be loaded back from disk.
     /// The path param should be the same name that was used with the `store_each` callback.
     /// Will return the deserialized data.
     pub fn load<T: Load, P: AsRef<Path>>(&self, path: P) -> Result<T> {
-        T::load_from(self.path.join(path))
+        let path = path.as_ref();
+        // the manifest only lists the files directly in this directory
+        if path.components().count() == 1 {
+            load_checked(&self.path.join(path), self.manifest.as_ref())
+        } else {
+            T::load_from(self.path.join(path))
+        }
+    }
+
+    /// Like `load` but memory maps the file instead of reading it.
+    pub fn map<T: Copy, P: AsRef<Path>>(&self, path: P) -> Result<Mapped<T>> {
+        Mapped::map_from(self.path.join(path))
     }
 
     pub fn reconstruct<T: Reconstruct, P: AsRef<Path>>(&self, path: P) -> Result<T> {
@@ -208,8 +469,7 @@ pub trait ReconstructPrepared<T: Sized>: Sized {
 
     /// Call with a directory arg to reconstruct an object from this directory.
     fn reconstruct_from<D: AsRef<OsStr>>(self, dir: &D) -> Result<T> {
-        let path = Path::new(dir);
-        self.reconstruct_with(Loader { path })
+        self.reconstruct_with(Loader::new(Path::new(dir))?)
     }
 }
 
@@ -221,7 +481,6 @@ pub trait Reconstruct: Sized {
 
     /// Call with a directory arg to reconstruct an object from this directory.
     fn reconstruct_from<D: AsRef<OsStr>>(dir: &D) -> Result<Self> {
-        let path = Path::new(dir);
-        Self::reconstruct_with(Loader { path })
+        Self::reconstruct_with(Loader::new(Path::new(dir))?)
     }
 }
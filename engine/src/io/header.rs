@@ -0,0 +1,258 @@
+//! Optional self-describing header for vector files.
+//!
+//! Plain vector files are just the raw bytes of the elements, which is what RoutingKit and our other tools expect.
+//! Files written with `Store::write_with_header_to` are prefixed with a fixed size header instead:
+//!
+//! | bytes    | content                                              |
+//! |----------|------------------------------------------------------|
+//! | `0..4`   | magic number `RRRV`                                  |
+//! | `4..6`   | format version, `u16` little endian                  |
+//! | `6`      | element type tag, see `ElementType`                  |
+//! | `7`      | endianness of the data, `0` little, `1` big          |
+//! | `8..12`  | element size in bytes, `u32` little endian           |
+//! | `12..16` | CRC32 of the data, `u32` little endian               |
+//! | `16..24` | number of elements, `u64` little endian              |
+//!
+//! The header is a multiple of eight bytes long, so the data stays aligned when the file is memory mapped.
+//! `Load` and `Mapped` detect the header and validate the data against it.
+//! Files without the magic number are treated as legacy headerless files,
+//! and so are files where the element size and count in the header do not match the size of the file,
+//! as legacy files may start with the bytes of the magic number by chance.
+
+use super::*;
+
+/// Magic number at the start of files with a header
+pub const MAGIC: [u8; 4] = *b"RRRV";
+/// Current version of the header format
+pub const FORMAT_VERSION: u16 = 1;
+/// Size of the header in bytes
+pub const HEADER_SIZE: usize = 24;
+
+/// Type tag of the elements of a vector file.
+/// Compound types (structs, tuples) are all tagged as `Other` and only checked by their size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElementType {
+    Other = 0,
+    U8 = 1,
+    I8 = 2,
+    U16 = 3,
+    I16 = 4,
+    U32 = 5,
+    I32 = 6,
+    U64 = 7,
+    I64 = 8,
+    F32 = 9,
+    F64 = 10,
+}
+
+impl ElementType {
+    const ALL: [ElementType; 11] = [
+        ElementType::Other,
+        ElementType::U8,
+        ElementType::I8,
+        ElementType::U16,
+        ElementType::I16,
+        ElementType::U32,
+        ElementType::I32,
+        ElementType::U64,
+        ElementType::I64,
+        ElementType::F32,
+        ElementType::F64,
+    ];
+
+    /// The type tag for `T`
+    pub fn of<T>() -> Self {
+        Self::from_name(std::any::type_name::<T>()).unwrap_or(ElementType::Other)
+    }
+
+    /// Parse a type name as used by `name`, `decode_vector` and `encode_vector`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "u8" | "uint8" => Some(ElementType::U8),
+            "i8" | "int8" => Some(ElementType::I8),
+            "u16" | "uint16" => Some(ElementType::U16),
+            "i16" | "int16" => Some(ElementType::I16),
+            "u32" | "uint32" => Some(ElementType::U32),
+            "i32" | "int32" => Some(ElementType::I32),
+            "u64" | "uint64" => Some(ElementType::U64),
+            "i64" | "int64" => Some(ElementType::I64),
+            "f32" | "float32" => Some(ElementType::F32),
+            "f64" | "float64" => Some(ElementType::F64),
+            "other" => Some(ElementType::Other),
+            _ => None,
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Option<Self> {
+        Self::ALL.get(tag as usize).copied()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ElementType::Other => "other",
+            ElementType::U8 => "u8",
+            ElementType::I8 => "i8",
+            ElementType::U16 => "u16",
+            ElementType::I16 => "i16",
+            ElementType::U32 => "u32",
+            ElementType::I32 => "i32",
+            ElementType::U64 => "u64",
+            ElementType::I64 => "i64",
+            ElementType::F32 => "f32",
+            ElementType::F64 => "f64",
+        }
+    }
+}
+
+/// Type and size of the elements of a vector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ElementLayout {
+    pub element_type: ElementType,
+    pub size: usize,
+}
+
+impl ElementLayout {
+    pub fn of<T>() -> Self {
+        Self {
+            element_type: ElementType::of::<T>(),
+            size: mem::size_of::<T>(),
+        }
+    }
+
+    /// Plain bytes, used for data without a known element type
+    pub fn bytes() -> Self {
+        Self::of::<u8>()
+    }
+
+    /// Whether data with this layout can be read as data with the `other` layout.
+    /// Compound types only have to match in size.
+    pub fn compatible_with(&self, other: &ElementLayout) -> bool {
+        self.size == other.size
+            && (self.element_type == other.element_type || self.element_type == ElementType::Other || other.element_type == ElementType::Other)
+    }
+}
+
+/// CRC32 checksum of the given data, as stored in headers and manifests
+pub fn checksum(data: &[u8]) -> u32 {
+    crc32fast::hash(data)
+}
+
+/// Parsed file header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub version: u16,
+    pub layout: ElementLayout,
+    pub big_endian: bool,
+    pub num_elements: u64,
+    pub crc: u32,
+}
+
+impl Header {
+    /// Create the header for the given data
+    pub fn new(layout: ElementLayout, data: &[u8]) -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            layout,
+            big_endian: cfg!(target_endian = "big"),
+            num_elements: (data.len() / layout.size.max(1)) as u64,
+            crc: checksum(data),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let mut bytes = [0u8; HEADER_SIZE];
+        bytes[0..4].copy_from_slice(&MAGIC);
+        bytes[4..6].copy_from_slice(&self.version.to_le_bytes());
+        bytes[6] = self.layout.element_type as u8;
+        bytes[7] = self.big_endian as u8;
+        bytes[8..12].copy_from_slice(&(self.layout.size as u32).to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.crc.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.num_elements.to_le_bytes());
+        bytes
+    }
+
+    /// Parse the header from the first bytes of a file with `file_size` bytes in total.
+    /// Returns `None` for legacy files, that is if the bytes do not start with the magic number
+    /// or the announced elements do not fill the rest of the file exactly.
+    pub fn parse(bytes: &[u8], file_size: u64) -> Result<Option<Self>> {
+        if bytes.len() < HEADER_SIZE || bytes[0..4] != MAGIC {
+            return Ok(None);
+        }
+        let announced_size = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as u64;
+        let announced_elements = u64::from_le_bytes(bytes[16..24].try_into().unwrap());
+        if announced_size == 0 || announced_elements.checked_mul(announced_size) != Some(file_size - HEADER_SIZE as u64) {
+            return Ok(None);
+        }
+        let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+        if version > FORMAT_VERSION {
+            return Err(invalid_data(format!("unsupported format version {}", version)));
+        }
+        let element_type = ElementType::from_tag(bytes[6]).ok_or_else(|| invalid_data(format!("unknown element type tag {}", bytes[6])))?;
+
+        Ok(Some(Self {
+            version,
+            layout: ElementLayout {
+                element_type,
+                size: announced_size as usize,
+            },
+            big_endian: bytes[7] != 0,
+            crc: u32::from_le_bytes(bytes[12..16].try_into().unwrap()),
+            num_elements: announced_elements,
+        }))
+    }
+
+    /// Read the header of the given file, if it has one.
+    /// The file position is left at the start of the data.
+    pub fn read(file: &mut File) -> Result<Option<Self>> {
+        let mut bytes = [0u8; HEADER_SIZE];
+        let mut read = 0;
+        while read < HEADER_SIZE {
+            match file.read(&mut bytes[read..])? {
+                0 => break,
+                n => read += n,
+            }
+        }
+        let header = Self::parse(&bytes[..read], file.metadata()?.len())?;
+        if header.is_none() {
+            file.rewind()?;
+        }
+        Ok(header)
+    }
+
+    /// Check everything except the checksum: endianness, element layout (if known) and the length of the data.
+    pub fn validate_layout(&self, expected: Option<ElementLayout>, num_data_bytes: usize) -> Result<()> {
+        if self.big_endian != cfg!(target_endian = "big") {
+            return Err(invalid_data("data was written with a different endianness".to_string()));
+        }
+        if let Some(expected) = expected {
+            if !self.layout.compatible_with(&expected) {
+                return Err(invalid_data(format!(
+                    "file contains {} elements of {} bytes but {} elements of {} bytes were expected",
+                    self.layout.element_type.name(),
+                    self.layout.size,
+                    expected.element_type.name(),
+                    expected.size
+                )));
+            }
+        }
+        if self.num_elements.checked_mul(self.layout.size as u64) != Some(num_data_bytes as u64) {
+            return Err(invalid_data(format!(
+                "header announces {} elements of {} bytes but the file contains {} data bytes",
+                self.num_elements, self.layout.size, num_data_bytes
+            )));
+        }
+        Ok(())
+    }
+
+    /// Compare the checksum of the data against the header
+    pub fn validate_checksum(&self, data: &[u8]) -> Result<()> {
+        if checksum(data) != self.crc {
+            return Err(invalid_data("checksum mismatch".to_string()));
+        }
+        Ok(())
+    }
+}
+
+pub(super) fn invalid_data(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,170 @@
+//! Manifest files recording which files of a directory belong together.
+//!
+//! A manifest lists the element layout, length and CRC32 of each data file in the directory.
+//! When a manifest exists, `Load` checks every listed file against it, so mixing up files
+//! from different graph versions (for example a `cch_perm` of another graph) is caught on loading.
+//! `Loader` reads the manifest only once for all files of an object.
+//! Memory mapped files are not checked, since that would require reading them entirely.
+
+use super::{header::*, *};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+/// Name of the manifest file in a directory
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Description of the data of a single file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub layout: ElementLayout,
+    pub num_elements: u64,
+    pub crc: u32,
+}
+
+impl ManifestEntry {
+    pub fn new(layout: ElementLayout, data: &[u8]) -> Self {
+        Self {
+            layout,
+            num_elements: (data.len() / layout.size) as u64,
+            crc: checksum(data),
+        }
+    }
+
+    fn num_bytes(&self) -> u64 {
+        self.num_elements * self.layout.size as u64
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub files: BTreeMap<String, ManifestEntry>,
+}
+
+impl Manifest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an entry for the file `name` in `dir` by reading it.
+    /// For files with a header, the layout is taken from the header, legacy files are recorded as plain bytes.
+    pub fn add_file(&mut self, dir: &Path, name: &str) -> Result<()> {
+        let mut file = File::open(dir.join(name))?;
+        let header = Header::read(&mut file)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        let layout = header.map(|header| header.layout).unwrap_or_else(ElementLayout::bytes);
+        self.files.insert(name.to_string(), ManifestEntry::new(layout, &data));
+        Ok(())
+    }
+
+    /// Read the manifest of the given directory, if there is one.
+    pub fn read_from_dir(dir: &Path) -> Result<Option<Self>> {
+        let path = dir.join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let value: Value = serde_json::from_reader(File::open(&path)?).map_err(|e| invalid_data(format!("{}: {}", path.display(), e)))?;
+        Self::from_json(&value)
+            .map(Some)
+            .ok_or_else(|| invalid_data(format!("{}: malformed manifest", path.display())))
+    }
+
+    pub fn write_to_dir(&self, dir: &Path) -> Result<()> {
+        let file = File::create(dir.join(MANIFEST_FILE))?;
+        serde_json::to_writer_pretty(file, &self.to_json()).map_err(Error::from)
+    }
+
+    /// Check the data of the file `name` against the manifest.
+    /// Files not listed in the manifest are accepted.
+    pub fn check(&self, name: &str, layout: Option<ElementLayout>, data: &[u8]) -> Result<()> {
+        let entry = match self.files.get(name) {
+            Some(entry) => entry,
+            None => return Ok(()),
+        };
+        if let Some(layout) = layout {
+            if !entry.layout.compatible_with(&layout) && entry.layout != ElementLayout::bytes() {
+                return Err(invalid_data(format!(
+                    "{} is listed with {} elements of {} bytes in the manifest but {} elements of {} bytes were expected",
+                    name,
+                    entry.layout.element_type.name(),
+                    entry.layout.size,
+                    layout.element_type.name(),
+                    layout.size
+                )));
+            }
+        }
+        if entry.num_bytes() != data.len() as u64 || entry.crc != checksum(data) {
+            return Err(invalid_data(format!(
+                "{} does not match the manifest, it was changed or belongs to other data",
+                name
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check all files listed in the manifest of `dir`.
+    pub fn verify(&self, dir: &Path) -> Result<()> {
+        for name in self.files.keys() {
+            let mut file = File::open(dir.join(name))?;
+            Header::read(&mut file)?;
+            let mut data = Vec::new();
+            file.read_to_end(&mut data)?;
+            self.check(name, None, &data)?;
+        }
+        Ok(())
+    }
+
+    fn to_json(&self) -> Value {
+        let files: Map<String, Value> = self
+            .files
+            .iter()
+            .map(|(name, entry)| {
+                (
+                    name.clone(),
+                    json!({
+                        "element_type": entry.layout.element_type.name(),
+                        "element_size": entry.layout.size,
+                        "num_elements": entry.num_elements,
+                        "crc": entry.crc,
+                    }),
+                )
+            })
+            .collect();
+        json!({ "format_version": FORMAT_VERSION, "files": files })
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        if value.get("format_version")?.as_u64()? > FORMAT_VERSION as u64 {
+            return None;
+        }
+        let mut files = BTreeMap::new();
+        for (name, entry) in value.get("files")?.as_object()? {
+            let layout = ElementLayout {
+                element_type: ElementType::from_name(entry.get("element_type")?.as_str()?)?,
+                size: entry.get("element_size")?.as_u64()? as usize,
+            };
+            if layout.size == 0 {
+                return None;
+            }
+            files.insert(
+                name.clone(),
+                ManifestEntry {
+                    layout,
+                    num_elements: entry.get("num_elements")?.as_u64()?,
+                    crc: entry.get("crc")?.as_u64()? as u32,
+                },
+            );
+        }
+        Some(Self { files })
+    }
+}
+
+/// Check a file that was just loaded against `manifest`, the manifest of its directory, if there is one.
+pub(super) fn check_loaded(manifest: Option<&Manifest>, path: &Path, layout: Option<ElementLayout>, data: &[u8]) -> Result<()> {
+    match (manifest, path.parent(), path.file_name().and_then(OsStr::to_str)) {
+        (Some(manifest), Some(dir), Some(name)) if name != MANIFEST_FILE => manifest
+            .check(name, layout, data)
+            .map_err(|e| invalid_data(format!("{}: {}", dir.display(), e))),
+        _ => Ok(()),
+    }
+}
@@ -1,14 +1,46 @@
 extern crate rust_road_router;
 
+use std::{
+    ffi::OsStr,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
 use rust_road_router::{
     algo::{
+        a_star::ZeroPotential,
+        alt::{ALTPotData, BiDirServer as ALTServer, LandmarkUpdater},
+        catchup::{self, profiles::Server as ProfileServer},
+        ch_potentials::{penalty::Penalty, query::Server as TopoServer, CCHPotData},
+        contraction_hierarchy::{
+            ordering::{contract_with_ordering, OrderingParams},
+            query::Server as CHServer,
+            ContractionHierarchy,
+        },
+        customizable_contraction_hierarchy::{
+            customize, customize_incremental, customize_perfect, nested_dissection::nested_dissection_order, query::isochrone, query::Server as CCHServer,
+            CCHReconstrctor, Customized, CustomizedBasic, CCH, CCHT,
+        },
         dijkstra::{
             query::{bidirectional_dijkstra::Server as BiDijkServer, dijkstra::Server as DijkServer},
             *,
         },
+        hl::cch::{HubLabels, Server as HLServer},
+        map_matching::{GpsPoint, MapMatcher, MapMatchingParams},
+        mid_edge::*,
+        pareto::Server as ParetoServer,
+        rphast::DistanceMatrixServer,
+        strongly_connected_components::{largest_component, strongly_connected_components},
         *,
     },
-    datastr::graph::*,
+    datastr::{
+        graph::*,
+        node_order::NodeOrder,
+        spatial::{Side, SpatialIndex},
+        vehicle_restrictions::{hazmat, vehicle_class, LinkAttributes, LinkAttributesReconstructor, VehicleProfile},
+    },
+    io::*,
+    link_speed_estimates::{estimate_iter, LinkData, LinkSpeedData},
 };
 
 fn graph() -> OwnedGraph {
@@ -64,3 +96,1038 @@ fn bidir_dijkstra_stopping_crit() {
 
     assert_eq!(server.query(Query { from: 0, to: 4 }).distance(), Some(12));
 }
+
+#[test]
+fn cch_distance_matrix_matches_dijkstra() {
+    let graph = graph();
+    let cch = CCH::fix_order_and_build(&graph, NodeOrder::from_node_order(vec![4, 0, 1, 2, 3, 5]));
+    let customized = customize(&cch, &graph);
+    let mut matrix_server = DistanceMatrixServer::new(customized.forward_graph(), customized.backward_graph(), cch.node_order().clone());
+    let mut dijkstra = DijkServer::<_, DefaultOps>::new(graph.clone());
+
+    let targets = [0, 1, 2, 3, 4, 5];
+    // few sources use one-to-many queries, many sources (including duplicates) the bucket based variant
+    for sources in [&[0, 3][..], &[0, 1, 2, 3, 4, 5, 3, 0][..]] {
+        let matrix = matrix_server.distances(sources, &targets);
+        assert_eq!(matrix.len(), sources.len());
+        for (&source, row) in sources.iter().zip(&matrix) {
+            for (&target, &distance) in targets.iter().zip(row) {
+                let expected = dijkstra.query(Query { from: source, to: target }).distance().unwrap_or(INFINITY);
+                assert_eq!(distance, expected, "{} -> {}", source, target);
+            }
+        }
+    }
+}
+
+#[test]
+fn cch_isochrone_matches_dijkstra() {
+    let graph = graph();
+    let cch = CCH::fix_order_and_build(&graph, NodeOrder::from_node_order(vec![4, 0, 1, 2, 3, 5]));
+    let mut server = isochrone::Server::new(customize(&cch, &graph));
+    let mut dijkstra = DijkServer::<_, DefaultOps>::new(graph.clone());
+
+    for source in 0..6 {
+        for budget in [0, 1, 3, 5, 10] {
+            let reached = server.isochrone(source, budget);
+            let expected: Vec<_> = (0..6)
+                .filter_map(|target| dijkstra.query(Query { from: source, to: target }).distance().map(|dist| (target, dist)))
+                .filter(|&(_, dist)| dist <= budget)
+                .collect();
+            let mut sorted = reached.clone();
+            sorted.sort_unstable();
+            assert_eq!(sorted, expected, "source {} budget {}", source, budget);
+
+            for (EdgeIdT(edge), fraction) in isochrone::boundary_arcs(&graph, &reached, budget) {
+                assert!((0.0..1.0).contains(&fraction));
+                let tail = (0..6).find(|&node| graph.neighbor_edge_indices(node).contains(&edge)).unwrap();
+                assert!(reached.iter().any(|&(node, _)| node == tail));
+            }
+        }
+    }
+}
+
+#[test]
+fn cch_isochrone_from_points_on_edges_matches_dijkstra() {
+    let graph = graph();
+    let cch = CCH::fix_order_and_build(&graph, NodeOrder::from_node_order(vec![4, 0, 1, 2, 3, 5]));
+    let mut server = isochrone::Server::new(customize(&cch, &graph));
+    let mut dijkstra = DijkServer::<_, DefaultOps>::new(graph.clone());
+
+    for edge in 0..graph.num_arcs() as EdgeId {
+        for fraction in [0.0, 0.5, 1.0] {
+            let sources = exits(&graph, EdgePoint { edge, fraction });
+            for budget in [0, 3, 10] {
+                let mut reached = server.isochrone_from(&sources, budget);
+                reached.sort_unstable();
+                let expected: Vec<_> = (0..6)
+                    .filter_map(|target| {
+                        sources
+                            .iter()
+                            .filter_map(|&(source, offset)| dijkstra.query(Query { from: source, to: target }).distance().map(|dist| offset + dist))
+                            .min()
+                            .map(|dist| (target, dist))
+                    })
+                    .filter(|&(_, dist)| dist <= budget)
+                    .collect();
+                assert_eq!(reached, expected, "edge {} fraction {} budget {}", edge, fraction, budget);
+            }
+        }
+    }
+}
+
+#[test]
+fn penalty_alternative_routes_are_valid_paths() {
+    let graph = graph();
+    let cch = CCH::fix_order_and_build(&graph, NodeOrder::from_node_order(vec![4, 0, 1, 2, 3, 5]));
+    let potentials = CCHPotData::new(&cch, &graph);
+    let mut penalty = Penalty::new(&graph, potentials.forward_potential(), potentials.backward_potential());
+    let mut dijkstra = DijkServer::<_, DefaultOps>::new(graph.clone());
+
+    let routes = penalty.alternative_routes(Query { from: 0, to: 3 }, 3).unwrap();
+    assert!(routes.len() <= 4);
+    assert_eq!(routes[0].path.first(), Some(&0));
+    assert_eq!(routes[0].path.last(), Some(&3));
+    assert_eq!(Some(routes[0].distance), dijkstra.query(Query { from: 0, to: 3 }).distance());
+    assert_eq!(routes[0].detour, 1.0);
+    assert_eq!(routes[0].sharing, 1.0);
+
+    for route in &routes {
+        let length: Weight = route
+            .path
+            .windows(2)
+            .map(|nodes| {
+                graph
+                    .edge_indices(nodes[0], nodes[1])
+                    .map(|EdgeIdT(edge)| graph.weight()[edge as usize])
+                    .min()
+                    .unwrap()
+            })
+            .sum();
+        assert_eq!(length, route.distance);
+        assert!(route.detour >= 1.0);
+        assert!((0.0..=1.0).contains(&route.sharing));
+    }
+}
+
+// Build a graph from (tail, head, weight) triples in any order
+fn graph_from_arcs(num_nodes: usize, mut arcs: Vec<(NodeId, NodeId, Weight)>) -> OwnedGraph {
+    arcs.sort_unstable();
+    let mut first_out = vec![0; num_nodes + 1];
+    for &(tail, _, _) in &arcs {
+        first_out[tail as usize + 1] += 1;
+    }
+    for node in 0..num_nodes {
+        first_out[node + 1] += first_out[node];
+    }
+    OwnedGraph::new(first_out, arcs.iter().map(|arc| arc.1).collect(), arcs.iter().map(|arc| arc.2).collect())
+}
+
+// A 4x4 grid as core with a dead end road of two nodes at one corner and of one node at the opposite corner
+fn grid_with_dead_ends(weight: impl Fn(usize, usize) -> Weight) -> OwnedGraph {
+    let (grid, _, _) = grid_graph(4, 1.0, &weight);
+    let mut arcs: Vec<(NodeId, NodeId, Weight)> = (0..grid.num_nodes() as NodeId)
+        .flat_map(|tail| LinkIterable::<Link>::link_iter(&grid, tail).map(move |link| (tail, link.node, link.weight)))
+        .collect();
+    for &(node, neighbor) in &[(0, 16), (16, 17), (15, 18)] {
+        arcs.push((node, neighbor, weight(node as usize, neighbor as usize)));
+        arcs.push((neighbor, node, weight(neighbor as usize, node as usize)));
+    }
+    graph_from_arcs(19, arcs)
+}
+
+#[test]
+fn penalty_alternative_routes_include_the_way_to_the_core() {
+    let graph = grid_with_dead_ends(|tail, head| ((tail * 7 + head * 3) % 10 + 1) as Weight);
+    let cch = CCH::fix_order_and_build(&graph, NodeOrder::identity(graph.num_nodes()));
+    let (forward_potential, backward_potential) = CCHPotData::new(&cch, &graph).shared_potentials();
+    let mut penalty = Penalty::new(&graph, forward_potential, backward_potential);
+
+    let check = |graph: &OwnedGraph, penalty: &mut Penalty<_>| {
+        let mut dijkstra = DijkServer::<_, DefaultOps>::new(graph.clone());
+        for &(from, to) in &[(17, 18), (18, 16), (17, 5), (10, 18), (17, 16)] {
+            let routes = penalty.alternative_routes(Query { from, to }, 3).unwrap();
+            assert_eq!(Some(routes[0].distance), dijkstra.query(Query { from, to }).distance(), "{} -> {}", from, to);
+            for route in &routes {
+                assert_eq!(route.path.first(), Some(&from));
+                assert_eq!(route.path.last(), Some(&to));
+                let length: Weight = route
+                    .path
+                    .windows(2)
+                    .map(|nodes| {
+                        graph
+                            .edge_indices(nodes[0], nodes[1])
+                            .map(|EdgeIdT(edge)| graph.weight()[edge as usize])
+                            .min()
+                            .unwrap()
+                    })
+                    .sum();
+                assert_eq!(length, route.distance);
+                assert!(route.detour >= 1.0);
+                assert!((0.0..=1.0).contains(&route.sharing));
+            }
+        }
+    };
+    check(&graph, &mut penalty);
+
+    // new weights, and potentials for them, replace the old ones without building a new server
+    let graph = grid_with_dead_ends(|tail, head| ((tail * 3 + head * 5) % 7 + 2) as Weight);
+    let (forward_potential, backward_potential) = CCHPotData::new(&cch, &graph).shared_potentials();
+    penalty.set_metric(&graph, forward_potential, backward_potential);
+    check(&graph, &mut penalty);
+}
+
+#[test]
+fn turn_restricted_line_graph_queries_match_dijkstra() {
+    //  0 --- 1 --- 2 --- 5
+    //         \   /
+    //          \ /
+    //      4 -- 3
+    // all roads in both directions, 5 and 4 are dead ends
+    let roads = [(0, 1, 3), (1, 2, 2), (2, 5, 4), (1, 3, 5), (2, 3, 1), (3, 4, 2)];
+    let graph = graph_from_arcs(6, roads.iter().flat_map(|&(a, b, weight)| vec![(a, b, weight), (b, a, weight)]).collect());
+    let arc = |tail: NodeId, head: NodeId| graph.edge_indices(tail, head).next().unwrap().0;
+
+    // no left turn from 0 -> 1 into 1 -> 2, no U-turn at 5
+    let mut forbidden = vec![(arc(0, 1), arc(1, 2)), (arc(2, 5), arc(5, 2))];
+    forbidden.sort_unstable();
+    let (forbidden_from, forbidden_to): (Vec<EdgeId>, Vec<EdgeId>) = forbidden.into_iter().unzip();
+    let line_graph = turn_restricted_line_graph(&graph, &forbidden_from, &forbidden_to);
+
+    let turns = |from: EdgeId| LinkIterable::<NodeIdT>::link_iter(&line_graph, from).map(|NodeIdT(to)| to).collect::<Vec<_>>();
+    assert!(!turns(arc(0, 1)).contains(&arc(1, 2)));
+    assert!(!turns(arc(1, 2)).contains(&arc(2, 1)));
+    assert!(!turns(arc(2, 5)).contains(&arc(5, 2)));
+    assert!(turns(arc(3, 4)).contains(&arc(4, 3)));
+
+    let cch = CCH::fix_order_and_build(&line_graph, NodeOrder::identity(line_graph.num_nodes()));
+    let mut server = CCHServer::new(customize(&cch, &line_graph));
+    let mut dijkstra = DijkServer::<_, DefaultOps>::new(line_graph.clone());
+
+    // routes between nodes start at any arc leaving the source and end with any arc entering the target
+    for from in 0..graph.num_nodes() as NodeId {
+        for to in 0..graph.num_nodes() as NodeId {
+            let sources: Vec<EdgeId> = graph.neighbor_edge_indices(from).collect();
+            let targets: Vec<EdgeId> = (0..graph.num_arcs() as EdgeId).filter(|&edge| graph.link(edge).node == to).collect();
+            let expected = sources
+                .iter()
+                .flat_map(|&source| targets.iter().map(move |&target| (source, target)))
+                .filter_map(|(source, target)| Some(dijkstra.query(Query { from: source, to: target }).distance()? + graph.weight()[target as usize]))
+                .min();
+
+            let query = MultiQuery::new(
+                sources.iter().map(|&arc| (arc, 0)),
+                targets.iter().map(|&arc| (arc, graph.weight()[arc as usize])),
+            );
+            let result = server.multi_query(&query);
+            assert_eq!(result.distance(), expected, "{} -> {}", from, to);
+            if let Some(mut result) = result.found() {
+                let path = result.node_path();
+                assert!(sources.contains(&path[0]) && targets.contains(path.last().unwrap()));
+            }
+        }
+    }
+
+    // turning around at the dead end 4 is allowed
+    let u_turn = Query {
+        from: arc(3, 4),
+        to: arc(4, 3),
+    };
+    assert_eq!(dijkstra.query(u_turn).distance(), Some(2));
+    // going left at 1 coming from 0 is forbidden, so the route detours through 3
+    let detour = Query {
+        from: arc(0, 1),
+        to: arc(2, 5),
+    };
+    assert_eq!(server.query(detour).distance(), Some(3 + 5 + 1));
+}
+
+#[test]
+fn catchup_profile_switches_paths() {
+    use rust_road_router::datastr::graph::floating_time_dependent::{self as ftd, PeriodicPiecewiseLinearFunction, PLF};
+
+    // 0 -> 2 directly is fast at night and slow at noon, 0 -> 1 -> 2 always takes 20s
+    let graph = ftd::TDGraph::new(
+        vec![0, 2, 3, 3],
+        vec![1, 2, 2],
+        vec![0, 1, 4, 5],
+        vec![0, 0, 43_200_000, 86_400_000, 0],
+        vec![10_000, 5_000, 50_000, 5_000, 10_000],
+    );
+    let cch = CCH::fix_order_and_build(&graph, NodeOrder::from_node_order(vec![0, 2, 1]));
+    let customized = catchup::customize(&cch, &graph);
+    let mut server = ProfileServer::new(&cch, &customized);
+
+    let profile = server.profile(0, 2).unwrap();
+    let ttf: PeriodicPiecewiseLinearFunction = profile.travel_time_function();
+    assert!(ttf.evaluate(ftd::Timestamp::ZERO).fuzzy_eq(ftd::FlWeight::new(5.0)));
+    assert!(ttf.evaluate(ftd::Timestamp::new(43_200.0)).fuzzy_eq(ftd::FlWeight::new(20.0)));
+
+    let mut edges: Vec<_> = profile.paths.iter().map(|path| path.edges.clone()).collect();
+    edges.sort();
+    assert_eq!(edges, vec![vec![0, 2], vec![1]]);
+    let covered = profile
+        .paths
+        .iter()
+        .flat_map(|path| path.valid.iter())
+        .fold(ftd::FlWeight::ZERO, |acc, valid| acc + (valid.end - valid.start));
+    assert!(covered.fuzzy_eq(ftd::FlWeight::from(ftd::period())));
+
+    assert!(server.profile(2, 0).is_none());
+
+    let breakpoints: Vec<_> = server.profile(1, 1).unwrap().breakpoints().iter().map(|point| (point.at, point.val)).collect();
+    assert_eq!(
+        breakpoints,
+        vec![(ftd::Timestamp::ZERO, ftd::FlWeight::ZERO), (ftd::period(), ftd::FlWeight::ZERO)]
+    );
+}
+
+#[test]
+fn cch_incremental_customization_matches_full_customization() {
+    let mut graph = graph();
+    let cch = CCH::fix_order_and_build(&graph, NodeOrder::from_node_order(vec![4, 0, 1, 2, 3, 5]));
+    let mut customized = customize(&cch, &graph);
+
+    let tails: Vec<NodeId> = (0..graph.num_nodes() as NodeId)
+        .flat_map(|node| graph.neighbor_edge_indices(node).map(move |_| node))
+        .collect();
+
+    for updates in [vec![(6, 1)], vec![(2, 20), (5, 0)], vec![(6, INFINITY), (0, 1)], vec![(2, 2), (5, 1), (6, 7)]] {
+        let mut changed = Vec::new();
+        for (edge, weight) in updates {
+            graph.weights_mut()[edge as usize] = weight;
+            changed.push((NodeIdT(tails[edge as usize]), EdgeIdT(edge)));
+        }
+        customize_incremental(&mut customized, &graph, &changed);
+
+        let expected = customize(&cch, &graph);
+        assert_eq!(customized.forward_graph().weight(), expected.forward_graph().weight());
+        assert_eq!(customized.backward_graph().weight(), expected.backward_graph().weight());
+    }
+}
+
+#[test]
+fn cch_incremental_customization_ignores_self_loops() {
+    // a path 0 - 1 - 2 with a self loop at 1
+    let mut graph = graph_from_arcs(3, vec![(0, 1, 2), (1, 0, 2), (1, 1, 5), (1, 2, 3), (2, 1, 3)]);
+    let cch = CCH::fix_order_and_build(&graph, NodeOrder::from_node_order(vec![0, 2, 1]));
+    let mut customized = customize(&cch, &graph);
+
+    // arcs are sorted by tail and head, so the self loop is arc 2
+    graph.weights_mut()[2] = 1;
+    customize_incremental(&mut customized, &graph, &[(NodeIdT(1), EdgeIdT(2)), (NodeIdT(0), EdgeIdT(0))]);
+
+    let expected = customize(&cch, &graph);
+    assert_eq!(customized.forward_graph().weight(), expected.forward_graph().weight());
+    assert_eq!(customized.backward_graph().weight(), expected.backward_graph().weight());
+}
+
+// Temporary directory for the files of a test, removed again when dropped, also when the test fails
+struct TestDir(PathBuf);
+
+impl TestDir {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("rrr_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        TestDir(dir)
+    }
+}
+
+impl Deref for TestDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TestDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<OsStr> for TestDir {
+    fn as_ref(&self) -> &OsStr {
+        self.0.as_os_str()
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn cch_and_customization_roundtrip_through_disk() {
+    let graph = graph();
+    let cch = CCH::fix_order_and_build(&graph, NodeOrder::from_node_order(vec![4, 0, 1, 2, 3, 5]));
+    let customized = customize(&cch, &graph);
+
+    let dir = TestDir::new("cch_roundtrip");
+    cch.deconstruct_to(&dir.join("cch")).unwrap();
+    cch.node_order().deconstruct_to(&dir.join("cch")).unwrap();
+    customized.deconstruct_to(&dir.join("customized")).unwrap();
+
+    let reloaded_cch = CCHReconstrctor(&graph).reconstruct_from(&dir.join("cch")).unwrap();
+    let reloaded: CustomizedBasic<CCH> = (&reloaded_cch).reconstruct_from(&dir.join("customized")).unwrap();
+
+    assert_eq!(reloaded_cch.node_order().order(), cch.node_order().order());
+    assert_eq!(reloaded.forward_graph().weight(), customized.forward_graph().weight());
+    assert_eq!(reloaded.backward_graph().weight(), customized.backward_graph().weight());
+    assert_eq!(reloaded.forward_unpacking(), customized.forward_unpacking());
+    assert_eq!(reloaded.backward_unpacking(), customized.backward_unpacking());
+}
+
+// Square grid with arcs in both directions between neighboring nodes, `spacing` degrees apart.
+// `weight` gets the ids of tail and head of each arc.
+fn grid_graph(size: usize, spacing: f32, weight: impl Fn(usize, usize) -> Weight) -> (OwnedGraph, Vec<f32>, Vec<f32>) {
+    let mut first_out = vec![0];
+    let mut head = Vec::new();
+    let mut weights = Vec::new();
+    let (mut latitude, mut longitude) = (Vec::new(), Vec::new());
+    for row in 0..size {
+        for col in 0..size {
+            let node = row * size + col;
+            let neighbors = [
+                (row > 0, node.wrapping_sub(size)),
+                (col > 0, node.wrapping_sub(1)),
+                (col + 1 < size, node + 1),
+                (row + 1 < size, node + size),
+            ];
+            for &(_, neighbor) in neighbors.iter().filter(|(exists, _)| *exists) {
+                head.push(neighbor as NodeId);
+                weights.push(weight(node, neighbor));
+            }
+            first_out.push(head.len() as EdgeId);
+            latitude.push(row as f32 * spacing);
+            longitude.push(col as f32 * spacing);
+        }
+    }
+    (OwnedGraph::new(first_out, head, weights), latitude, longitude)
+}
+
+// Arc weights for `grid_graph` between 1 and 10, different in both directions
+fn grid_weight(tail: usize, head: usize) -> Weight {
+    ((tail * 7 + head * 3) % 10 + 1) as Weight
+}
+
+// Length of a node path, using the cheapest arc between consecutive nodes
+fn path_length(graph: &OwnedGraph, path: &[NodeId]) -> Weight {
+    path.windows(2)
+        .map(|nodes| {
+            graph
+                .edge_indices(nodes[0], nodes[1])
+                .map(|EdgeIdT(edge)| graph.weight()[edge as usize])
+                .min()
+                .unwrap()
+        })
+        .sum()
+}
+
+#[test]
+fn nested_dissection_order_gives_correct_cch() {
+    let (graph, latitude, longitude) = grid_graph(12, 1.0, |tail, head| ((tail * 7 + head * 13) % 10 + 1) as Weight);
+
+    let order = nested_dissection_order(&graph, &latitude, &longitude);
+    let mut nodes = order.order().to_vec();
+    nodes.sort_unstable();
+    assert_eq!(nodes, (0..graph.num_nodes() as NodeId).collect::<Vec<_>>());
+
+    let cch = CCH::fix_order_and_build(&graph, order);
+    // the grid is connected, so there is a single top level cell, which is split into at least two parts
+    // by a separator no larger than a straight cut through the grid
+    let top_level = &cch.separators().children[0];
+    assert!(top_level.nodes.len() <= 12, "top level separator has {} nodes", top_level.nodes.len());
+    assert!(top_level.children.len() >= 2);
+    let identity_cch = CCH::fix_order_and_build(&graph, NodeOrder::identity(graph.num_nodes()));
+    assert!(cch.num_arcs() < identity_cch.num_arcs());
+
+    let mut server = CCHServer::new(customize(&cch, &graph));
+    let mut dijkstra = DijkServer::<_, DefaultOps>::new(graph.clone());
+    for from in (0..graph.num_nodes() as NodeId).step_by(7) {
+        for to in (0..graph.num_nodes() as NodeId).step_by(11) {
+            let expected = dijkstra.query(Query { from, to }).distance();
+            assert_eq!(server.query(Query { from, to }).distance(), expected, "{} -> {}", from, to);
+        }
+    }
+}
+
+#[test]
+fn map_matching_follows_noisy_trace() {
+    // at the equator, 0.001 degrees are about 111 meters in both directions
+    let (graph, latitude, longitude) = grid_graph(6, 0.001, |_, _| 111);
+    let cch = CCH::fix_order_and_build(&graph, nested_dissection_order(&graph, &latitude, &longitude));
+    let customized = customize(&cch, &graph);
+    let index = SpatialIndex::new(graph.first_out(), graph.head(), &latitude, &longitude);
+    let mut matcher = MapMatcher::new(graph.borrowed(), &index, &customized, MapMatchingParams::default());
+
+    // east along the bottom row from node 0 to node 3, then north to node 15, a few meters off the road
+    let points = [(0.00002, 0.0005), (-0.00003, 0.0015), (0.00001, 0.0025), (0.0005, 0.00303), (0.0015, 0.00298)];
+    let points: Vec<GpsPoint> = points
+        .iter()
+        .enumerate()
+        .map(|(i, &(lat, lng))| GpsPoint {
+            timestamp: 100_000 + 10_000 * i as u64,
+            lat,
+            lng,
+        })
+        .collect();
+    let matched = matcher.match_trace(&points);
+    assert_eq!(matched.len(), 1);
+
+    let arc = |from: NodeId, to: NodeId| graph.edge_indices(from, to).next().unwrap().0;
+    let expected_path = vec![arc(0, 1), arc(1, 2), arc(2, 3), arc(3, 9), arc(9, 15)];
+    assert_eq!(matched[0].path, expected_path);
+
+    // one point on each arc
+    assert_eq!(matched[0].traces.len(), points.len());
+    for ((trace, point), &link) in matched[0].traces.iter().zip(&points).zip(&expected_path) {
+        assert_eq!(trace.timestamp, point.timestamp);
+        assert_eq!(trace.link_id, u64::from(link));
+        assert!((trace.traversed_in_travel_direction_fraction - 0.5).abs() < 0.05);
+    }
+
+    // the output can be fed directly into the link speed estimation
+    let links: Vec<LinkData> = expected_path
+        .iter()
+        .map(|&arc| LinkData {
+            link_id: u64::from(arc),
+            length: 111_000,
+            speed_limit: 50,
+        })
+        .collect();
+    let estimates: Vec<LinkSpeedData> = estimate_iter(Box::new(links.iter()), Box::new(matched[0].traces.iter())).unwrap().collect();
+    assert_eq!(estimates.len(), links.len());
+}
+
+#[test]
+fn spatial_index_snaps_to_closest_arc() {
+    let (graph, latitude, longitude) = grid_graph(6, 0.001, |_, _| 111);
+    let index = SpatialIndex::new(graph.first_out(), graph.head(), &latitude, &longitude);
+    let arc = |from: NodeId, to: NodeId| graph.edge_indices(from, to).next().unwrap().0;
+
+    // slightly north of the middle between node 7 and node 8, so on the left of 7 -> 8 and on the right of 8 -> 7
+    let snaps = index.nearest_with_tolerance(0.00101, 0.00125, 0.1);
+    assert_eq!(snaps.len(), 2);
+    for snap in &snaps {
+        assert!((snap.distance - 1.1).abs() < 0.1);
+        assert!((snap.point.0 - 0.001).abs() < 1e-6);
+        if snap.arc == arc(7, 8) {
+            assert!((snap.fraction - 0.25).abs() < 1e-3);
+            assert_eq!(snap.side, Side::Left);
+        } else {
+            assert_eq!(snap.arc, arc(8, 7));
+            assert!((snap.fraction - 0.75).abs() < 1e-3);
+            assert_eq!(snap.side, Side::Right);
+        }
+    }
+    assert!(snaps.contains(&index.nearest(0.00101, 0.00125).unwrap()));
+
+    // the arcs of the four roads around node 14, each in both directions
+    let within = index.within(0.002, 0.002, 60.0);
+    assert_eq!(within.len(), 8);
+    assert!(within.windows(2).all(|pair| pair[0].distance <= pair[1].distance));
+
+    // with link geometry, arc 0 -> 1 makes a detour to the north
+    let mut first_modelling_node = vec![0; graph.num_arcs() + 1];
+    for entry in &mut first_modelling_node[arc(0, 1) as usize + 1..] {
+        *entry = 2;
+    }
+    let index = SpatialIndex::with_geometry(
+        graph.first_out(),
+        graph.head(),
+        &latitude,
+        &longitude,
+        &first_modelling_node,
+        &[0.0005, 0.0005],
+        &[0.0, 0.001],
+    );
+    let snap = index.nearest(0.0006, 0.0005).unwrap();
+    assert_eq!(snap.arc, arc(0, 1));
+    assert!((snap.fraction - 0.5).abs() < 1e-3);
+}
+
+#[test]
+fn strongly_connected_components_of_simple_graph() {
+    // 0, 1, 2 and 3 are on a common cycle, 4 has no outgoing arcs and 5 no arcs at all
+    let component = strongly_connected_components(&graph());
+    assert_eq!(component.len(), 6);
+    assert!(component[1..4].iter().all(|&c| c == component[0]));
+    assert_ne!(component[4], component[0]);
+    assert_ne!(component[5], component[0]);
+    assert_ne!(component[4], component[5]);
+    assert_eq!(largest_component(&component), Some(component[0]));
+    assert_eq!(largest_component(&[]), None);
+}
+
+#[test]
+fn pareto_query_finds_pareto_set() {
+    // the top and right border is a fast toll road
+    let on_border = |node: usize| node < 4 || node % 4 == 3;
+    let toll_road = |tail: usize, head: usize| on_border(tail) && on_border(head);
+    let (time, latitude, longitude) = grid_graph(4, 1.0, |tail, head| if toll_road(tail, head) { 1 } else { 3 + ((tail + head) % 3) as Weight });
+    let (cost, _, _) = grid_graph(4, 1.0, |tail, head| if toll_road(tail, head) { 9 } else { 1 + ((tail * head) % 2) as Weight });
+    let cch = CCH::fix_order_and_build(&time, nested_dissection_order(&time, &latitude, &longitude));
+    let (time_pot, cost_pot) = (CCHPotData::new(&cch, &time), CCHPotData::new(&cch, &cost));
+    let mut server = ParetoServer::new(time.clone(), [time.weight(), cost.weight()], [&time_pot, &cost_pot]);
+
+    // all simple paths from 0 to 15, the Pareto set has to be among them
+    fn simple_paths(graph: &OwnedGraph, path: &mut Vec<EdgeId>, node: NodeId, visited: &mut Vec<bool>, paths: &mut Vec<Vec<EdgeId>>) {
+        if node == 15 {
+            paths.push(path.clone());
+            return;
+        }
+        for (NodeIdT(head), EdgeIdT(edge)) in LinkIterable::<(NodeIdT, EdgeIdT)>::link_iter(graph, node) {
+            if !visited[head as usize] {
+                visited[head as usize] = true;
+                path.push(edge);
+                simple_paths(graph, path, head, visited, paths);
+                path.pop();
+                visited[head as usize] = false;
+            }
+        }
+    }
+    let mut paths = Vec::new();
+    let mut visited = vec![false; 16];
+    visited[0] = true;
+    simple_paths(&time, &mut Vec::new(), 0, &mut visited, &mut paths);
+    let costs = |path: &[EdgeId]| {
+        [
+            path.iter().map(|&edge| time.weight()[edge as usize]).sum::<Weight>(),
+            path.iter().map(|&edge| cost.weight()[edge as usize]).sum::<Weight>(),
+        ]
+    };
+    let all_costs: Vec<[Weight; 2]> = paths.iter().map(|path| costs(path)).collect();
+    let mut pareto_costs: Vec<[Weight; 2]> = all_costs
+        .iter()
+        .filter(|c| !all_costs.iter().any(|o| o != *c && o[0] <= c[0] && o[1] <= c[1]))
+        .copied()
+        .collect();
+    pareto_costs.sort_unstable();
+    pareto_costs.dedup();
+    assert!(pareto_costs.len() > 1);
+
+    let exact = server.query(Query { from: 0, to: 15 }, 0.0);
+    assert_eq!(exact.iter().map(|path| path.costs).collect::<Vec<_>>(), pareto_costs);
+    for path in &exact {
+        assert_eq!(costs(&path.edge_path), path.costs);
+        assert_eq!(path.node_path.len(), path.edge_path.len() + 1);
+        assert_eq!(path.node_path.first(), Some(&0));
+        assert_eq!(path.node_path.last(), Some(&15));
+    }
+
+    let approximate = server.query(Query { from: 0, to: 15 }, 0.5);
+    assert!(approximate.len() <= exact.len());
+    for c in &pareto_costs {
+        assert!(approximate
+            .iter()
+            .any(|path| path.costs.iter().zip(c).all(|(&a, &c)| f64::from(a) <= 1.5 * f64::from(c))));
+    }
+}
+
+// The arc 0 -> 1 of `graph()` has a height limit of 3m and the arc 2 -> 3 is closed for trucks
+fn restricted_attributes(num_arcs: usize) -> LinkAttributes {
+    let mut attributes = LinkAttributes::unrestricted(num_arcs);
+    attributes.max_height[1] = 300;
+    attributes.allowed_vehicle_classes[4] = vehicle_class::CAR;
+    attributes
+}
+
+#[test]
+fn link_attributes_roundtrip_through_disk() {
+    let num_arcs = graph().num_arcs();
+    let attributes = restricted_attributes(num_arcs);
+
+    // missing files mean no restrictions
+    let dir = TestDir::new("vehicle_restrictions");
+    attributes.deconstruct_to(&dir).unwrap();
+    std::fs::remove_file(dir.join("link_forbidden_hazmat")).unwrap();
+    let loaded = LinkAttributesReconstructor(num_arcs).reconstruct_from(&dir).unwrap();
+    assert_eq!(loaded, attributes);
+}
+
+#[test]
+fn vehicle_profile_avoids_restricted_arcs() {
+    let graph = graph();
+    let cch = CCH::fix_order_and_build(&graph, NodeOrder::from_node_order(vec![4, 0, 1, 2, 3, 5]));
+    let attributes = restricted_attributes(graph.num_arcs());
+
+    let car = VehicleProfile::default();
+    let truck = VehicleProfile {
+        height: 400,
+        weight: 40_000,
+        hazmat: hazmat::FLAMMABLE,
+        vehicle_class: vehicle_class::TRUCK,
+    };
+    assert!(car.allows(&attributes, 1));
+    assert!(!truck.allows(&attributes, 1));
+    assert!(!truck.allows(&attributes, 4));
+
+    let distance = |vehicle: &VehicleProfile, from, to| {
+        let travel_time = vehicle.metric(&attributes, graph.weight());
+        let metric = FirstOutGraph::new(graph.first_out(), graph.head(), &travel_time[..]);
+        let mut server = CCHServer::new(customize(&cch, &metric));
+        server.query(Query { from, to }).distance()
+    };
+    assert_eq!(distance(&car, 0, 3), Some(3));
+    assert_eq!(distance(&truck, 0, 3), Some(13));
+    assert_eq!(distance(&truck, 2, 3), Some(3));
+}
+
+#[test]
+fn hub_labels_roundtrip_through_disk() {
+    let (graph, latitude, longitude) = grid_graph(5, 0.01, grid_weight);
+    let cch = CCH::fix_order_and_build(&graph, nested_dissection_order(&graph, &latitude, &longitude));
+    let labels = HubLabels::new(&customize_perfect(customize(&cch, &graph)));
+
+    let dir = TestDir::new("hub_labels");
+    labels.deconstruct_to(&dir).unwrap();
+    assert_eq!(HubLabels::reconstruct_from(&dir).unwrap(), labels);
+}
+
+#[test]
+fn hub_labels_match_dijkstra() {
+    let (graph, latitude, longitude) = grid_graph(5, 0.01, grid_weight);
+    let cch = CCH::fix_order_and_build(&graph, nested_dissection_order(&graph, &latitude, &longitude));
+    let customized = customize_perfect(customize(&cch, &graph));
+
+    let mut server = HLServer::new(HubLabels::new(&customized), &customized);
+    let mut dijkstra = DijkServer::<_, DefaultOps>::new(graph.clone());
+    for from in 0..25 {
+        for to in 0..25 {
+            let expected = dijkstra.query(Query { from, to }).distance();
+            let mut result = server.query(Query { from, to });
+            assert_eq!(result.distance(), expected, "{} -> {}", from, to);
+
+            let path = result.node_path().unwrap();
+            assert_eq!(path.first(), Some(&from));
+            assert_eq!(path.last(), Some(&to));
+            assert_eq!(Some(path_length(&graph, &path)), expected);
+        }
+    }
+}
+
+#[test]
+fn alt_landmarks_roundtrip_through_disk() {
+    let (graph, _, _) = grid_graph(5, 0.01, grid_weight);
+    let pot_data = ALTPotData::new(&graph, vec![0, 24, 4]);
+
+    let dir = TestDir::new("alt");
+    pot_data.deconstruct_to(&dir).unwrap();
+    assert_eq!(ALTPotData::reconstruct_from(&dir).unwrap(), pot_data);
+}
+
+#[test]
+fn alt_landmark_update_matches_recomputation() {
+    let (graph, _, _) = grid_graph(5, 0.01, grid_weight);
+    let mut pot_data = ALTPotData::new(&graph, vec![0, 24, 4]);
+
+    let mut server = ALTServer::new(graph.clone(), &pot_data);
+    let mut dijkstra = DijkServer::<_, DefaultOps>::new(graph.clone());
+    for from in 0..25 {
+        for to in 0..25 {
+            assert_eq!(server.query(Query { from, to }).distance(), dijkstra.query(Query { from, to }).distance());
+        }
+    }
+
+    // increase every third arc, including arcs on the shortest path trees of the landmarks,
+    // and then every fifth arc with the same updater
+    let mut updater = LandmarkUpdater::new(&graph);
+    let mut weights = graph.weight().to_vec();
+    let mut increased_graph = graph.clone();
+    for (first, step, delta) in [(0, 3, 5), (1, 5, 3)] {
+        let mut increased = Vec::new();
+        for edge in (first..weights.len()).step_by(step) {
+            increased.push((edge as EdgeId, weights[edge]));
+            weights[edge] += delta;
+        }
+        increased_graph = OwnedGraph::new(graph.first_out().to_vec(), graph.head().to_vec(), weights.clone());
+        updater.update_increased(&mut pot_data, &increased_graph, &increased);
+        assert_eq!(pot_data, ALTPotData::new(&increased_graph, vec![0, 24, 4]));
+    }
+
+    let mut server = ALTServer::new(increased_graph.clone(), &pot_data);
+    let mut dijkstra = DijkServer::<_, DefaultOps>::new(increased_graph);
+    for from in 0..25 {
+        for to in 0..25 {
+            assert_eq!(server.query(Query { from, to }).distance(), dijkstra.query(Query { from, to }).distance());
+        }
+    }
+}
+
+#[test]
+fn ch_roundtrip_through_disk() {
+    let (graph, _, _) = grid_graph(6, 0.01, grid_weight);
+    let (ch, _) = contract_with_ordering(&graph, OrderingParams::default());
+
+    let dir = TestDir::new("ch");
+    ch.deconstruct_to(&dir).unwrap();
+    let reloaded = ContractionHierarchy::reconstruct_from(&dir).unwrap();
+
+    // storing the reloaded hierarchy again gives the same files
+    let copy = TestDir::new("ch_copy");
+    reloaded.deconstruct_to(&copy).unwrap();
+    let files = [
+        "forward/first_out",
+        "forward/head",
+        "forward/weights",
+        "forward_middle_nodes",
+        "backward/first_out",
+        "backward/head",
+        "backward/weights",
+        "backward_middle_nodes",
+    ];
+    for file in files {
+        assert_eq!(std::fs::read(dir.join(file)).unwrap(), std::fs::read(copy.join(file)).unwrap(), "{}", file);
+    }
+}
+
+#[test]
+fn ch_with_own_ordering_matches_dijkstra() {
+    let (graph, _, _) = grid_graph(6, 0.01, grid_weight);
+    let (ch, order) = contract_with_ordering(&graph, OrderingParams::default());
+
+    let mut server = CHServer::new(ch, order);
+    let mut dijkstra = DijkServer::<_, DefaultOps>::new(graph.clone());
+    for from in 0..36 {
+        for to in 0..36 {
+            let expected = dijkstra.query(Query { from, to }).distance();
+            let mut result = server.query(Query { from, to });
+            assert_eq!(result.distance(), expected, "{} -> {}", from, to);
+
+            let path = result.node_path().unwrap();
+            assert_eq!(path.first(), Some(&from));
+            assert_eq!(path.last(), Some(&to));
+            assert_eq!(Some(path_length(&graph, &path)), expected);
+        }
+    }
+}
+
+#[test]
+fn mapped_graph_matches_loaded_graph() {
+    let (graph, _, _) = grid_graph(5, 0.01, |tail, head| ((tail * 3 + head * 5) % 7 + 1) as Weight);
+
+    let dir = TestDir::new("mapped");
+    graph.deconstruct_to(&dir).unwrap();
+    let mapped = <MappedGraph>::reconstruct_from(&dir).unwrap();
+    assert_eq!(mapped.first_out(), graph.first_out());
+    assert_eq!(mapped.head(), graph.head());
+    assert_eq!(mapped.weight(), graph.weight());
+
+    let mut server = DijkServer::<_, DefaultOps>::new(mapped.clone());
+    let mut dijkstra = DijkServer::<_, DefaultOps>::new(graph.clone());
+    for from in 0..25 {
+        for to in 0..25 {
+            assert_eq!(server.query(Query { from, to }).distance(), dijkstra.query(Query { from, to }).distance());
+        }
+    }
+}
+
+#[test]
+fn mapped_graph_rejects_invalid_files() {
+    let (graph, _, _) = grid_graph(5, 0.01, |tail, head| ((tail * 3 + head * 5) % 7 + 1) as Weight);
+    let dir = TestDir::new("mapped_invalid");
+    graph.deconstruct_to(&dir).unwrap();
+
+    // truncated and inconsistent files are rejected with an error instead of a panic
+    std::fs::write(dir.join("weights"), [0u8; 3]).unwrap();
+    assert_eq!(
+        Mapped::<Weight>::map_from(dir.join("weights")).unwrap_err().kind(),
+        std::io::ErrorKind::InvalidData
+    );
+    Vec::<Weight>::new().write_to(&dir.join("weights")).unwrap();
+    assert_eq!(<MappedGraph>::reconstruct_from(&dir).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn vector_headers_are_validated() {
+    let dir = TestDir::new("headers");
+    let data: Vec<u32> = (0..100).map(|x| x * 7).collect();
+
+    // headers are detected and validated, legacy files still load
+    data.write_with_header_to(&dir.join("with_header")).unwrap();
+    data.write_to(&dir.join("legacy")).unwrap();
+    assert_eq!(Vec::<u32>::load_from(dir.join("with_header")).unwrap(), data);
+    assert_eq!(Vec::<u32>::load_from(dir.join("legacy")).unwrap(), data);
+    assert_eq!(&Mapped::<u32>::map_from(dir.join("with_header")).unwrap()[..], &data[..]);
+
+    // legacy files which happen to start with the magic number are not mistaken for headered ones
+    let legacy: Vec<u8> = b"RRRV".iter().copied().chain(0..60).collect();
+    legacy.write_to(&dir.join("legacy_magic")).unwrap();
+    assert_eq!(Vec::<u8>::load_from(dir.join("legacy_magic")).unwrap(), legacy);
+    assert_eq!(&Mapped::<u8>::map_from(dir.join("legacy_magic")).unwrap()[..], &legacy[..]);
+    assert_eq!(
+        Vec::<f32>::load_from(dir.join("with_header")).unwrap_err().kind(),
+        std::io::ErrorKind::InvalidData
+    );
+    assert_eq!(
+        Vec::<u64>::load_from(dir.join("with_header")).unwrap_err().kind(),
+        std::io::ErrorKind::InvalidData
+    );
+
+    let mut bytes = std::fs::read(dir.join("with_header")).unwrap();
+    *bytes.last_mut().unwrap() ^= 1;
+    std::fs::write(dir.join("with_header"), &bytes).unwrap();
+    assert_eq!(
+        Vec::<u32>::load_from(dir.join("with_header")).unwrap_err().kind(),
+        std::io::ErrorKind::InvalidData
+    );
+}
+
+#[test]
+fn manifests_catch_files_of_another_graph() {
+    let dir = TestDir::new("manifests");
+    let (graph, _, _) = grid_graph(4, 0.01, |tail, head| (tail + head) as Weight);
+    let (other, _, _) = grid_graph(4, 0.01, |tail, head| (tail * head) as Weight);
+    graph.deconstruct_with_manifest_to(&dir).unwrap();
+    assert!(dir.join("manifest.json").exists());
+    assert_eq!(<OwnedGraph>::reconstruct_from(&dir).unwrap().weight(), graph.weight());
+    other.weight().write_to(&dir.join("weights")).unwrap();
+    assert_eq!(<OwnedGraph>::reconstruct_from(&dir).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+
+    // deconstructing again updates the manifest
+    other.deconstruct_to(&dir).unwrap();
+    assert_eq!(<OwnedGraph>::reconstruct_from(&dir).unwrap().weight(), other.weight());
+    Manifest::read_from_dir(&dir).unwrap().unwrap().verify(&dir).unwrap();
+}
+
+// Distance, node path and edge path of a mid-edge query
+fn mid_edge<S: MultiQueryServer>(server: &mut S, graph: &OwnedGraph, query: MidEdgeQuery) -> Option<(Weight, Vec<NodeId>, Vec<PartialEdge>)> {
+    server
+        .mid_edge_query(graph, query)
+        .found()
+        .map(|mut result| (result.distance(), result.node_path(), result.edge_path()))
+}
+
+#[test]
+fn mid_edge_queries_use_both_directions_of_a_road() {
+    //  0 <--8--> 1
+    //  |
+    //  1
+    //  v
+    //  2 --4--> 3
+    let graph = OwnedGraph::new(vec![0, 2, 3, 4, 4], vec![1, 2, 0, 3], vec![8, 1, 8, 4]);
+    let point = |edge, fraction| EdgePoint { edge, fraction };
+    let part = |edge, from_fraction, to_fraction| PartialEdge {
+        edge,
+        from_fraction,
+        to_fraction,
+    };
+    let cases = [
+        // leaving the source road backwards is shorter than driving on to its head
+        (
+            point(0, 0.25),
+            point(3, 0.5),
+            5,
+            vec![0, 2],
+            vec![part(2, 0.75, 1.0), part(1, 0.0, 1.0), part(3, 0.0, 0.5)],
+        ),
+        // source and target on the same road, in both orders
+        (point(0, 0.25), point(0, 0.75), 4, vec![], vec![part(0, 0.25, 0.75)]),
+        (point(0, 0.75), point(0, 0.25), 4, vec![], vec![part(2, 0.25, 0.75)]),
+        (point(0, 0.5), point(2, 0.25), 2, vec![], vec![part(0, 0.5, 0.75)]),
+    ];
+
+    let cch = CCH::fix_order_and_build(&graph, NodeOrder::from_node_order(vec![3, 1, 2, 0]));
+    let mut cch_server = CCHServer::new(customize(&cch, &graph));
+    let mut dijkstra = DijkServer::<_, DefaultOps>::new(graph.clone());
+    let mut topo = TopoServer::<OwnedGraph, _, _, true, true, true>::new(&graph, ZeroPotential(), DefaultOps::default());
+    for (from, to, distance, nodes, edges) in cases {
+        let query = MidEdgeQuery { from, to };
+        let expected = Some((distance, nodes, edges));
+        assert_eq!(mid_edge(&mut cch_server, &graph, query), expected, "{:?}", query);
+        assert_eq!(mid_edge(&mut dijkstra, &graph, query), expected, "{:?}", query);
+        assert_eq!(mid_edge(&mut topo, &graph, query), expected, "{:?}", query);
+    }
+    assert_eq!(
+        mid_edge(
+            &mut cch_server,
+            &graph,
+            MidEdgeQuery {
+                from: point(3, 0.5),
+                to: point(0, 0.5)
+            }
+        ),
+        None
+    );
+}
+
+#[test]
+fn mid_edge_queries_match_dijkstra() {
+    let (graph, latitude, longitude) = grid_graph(6, 0.01, |tail, head| ((tail * 7 + head * 3) % 10 + 1) as Weight * 4);
+    let tails: Vec<NodeId> = (0..graph.num_nodes() as NodeId)
+        .flat_map(|node| graph.neighbor_edge_indices(node).map(move |_| node))
+        .collect();
+
+    let cch = CCH::fix_order_and_build(&graph, nested_dissection_order(&graph, &latitude, &longitude));
+    let mut cch_server = CCHServer::new(customize(&cch, &graph));
+    let mut dijkstra = DijkServer::<_, DefaultOps>::new(graph.clone());
+    let mut topo = TopoServer::<OwnedGraph, _, _, true, true, true>::new(&graph, ZeroPotential(), DefaultOps::default());
+
+    for from_edge in (0..graph.num_arcs() as EdgeId).step_by(7) {
+        for to_edge in (0..graph.num_arcs() as EdgeId).step_by(5) {
+            for (from_fraction, to_fraction) in [(0.0, 1.0), (0.25, 0.75), (1.0, 0.0), (0.5, 0.5)] {
+                let query = MidEdgeQuery {
+                    from: EdgePoint {
+                        edge: from_edge,
+                        fraction: from_fraction,
+                    },
+                    to: EdgePoint {
+                        edge: to_edge,
+                        fraction: to_fraction,
+                    },
+                };
+                let expected = mid_edge(&mut dijkstra, &graph, query).map(|(distance, _, _)| distance);
+                assert_eq!(mid_edge(&mut topo, &graph, query).map(|(distance, _, _)| distance), expected, "{:?}", query);
+
+                let (distance, nodes, edges) = mid_edge(&mut cch_server, &graph, query).unwrap();
+                assert_eq!(Some(distance), expected, "{:?}", query);
+                // the parts of the edges add up to the distance and the edges are connected through the nodes of the path
+                let length: Weight = edges
+                    .iter()
+                    .map(|part| partial_weight(graph.weight()[part.edge as usize], part.to_fraction - part.from_fraction))
+                    .sum();
+                assert_eq!(length, distance, "{:?}", query);
+                assert_eq!(nodes.len() + 1, edges.len(), "{:?}", query);
+                for (node, parts) in nodes.iter().zip(edges.windows(2)) {
+                    assert_eq!(graph.head()[parts[0].edge as usize], *node);
+                    assert_eq!(tails[parts[1].edge as usize], *node);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn mid_edge_multi_queries_pick_the_best_pair() {
+    let (graph, latitude, longitude) = grid_graph(6, 0.01, |tail, head| ((tail * 7 + head * 3) % 10 + 1) as Weight * 4);
+    let cch = CCH::fix_order_and_build(&graph, nested_dissection_order(&graph, &latitude, &longitude));
+    let mut cch_server = CCHServer::new(customize(&cch, &graph));
+    let mut dijkstra = DijkServer::<_, DefaultOps>::new(graph.clone());
+    let point = |edge, fraction| EdgePoint { edge, fraction };
+
+    for first_edge in (0..graph.num_arcs() as EdgeId).step_by(11) {
+        let sources = [point(first_edge, 0.25), point((first_edge + 17) % graph.num_arcs() as EdgeId, 0.5)];
+        let targets = [
+            point((first_edge + 29) % graph.num_arcs() as EdgeId, 0.75),
+            point((first_edge + 41) % graph.num_arcs() as EdgeId, 0.0),
+        ];
+        let mut pair_distance = |source: usize, target: usize| {
+            let query = MidEdgeQuery {
+                from: sources[source],
+                to: targets[target],
+            };
+            mid_edge(&mut dijkstra, &graph, query).map(|(distance, _, _)| distance)
+        };
+        let expected = (0..2)
+            .flat_map(|source| (0..2).map(move |target| (source, target)))
+            .filter_map(|(source, target)| pair_distance(source, target))
+            .min();
+
+        let mut result = cch_server.mid_edge_multi_query(&graph, &sources, &targets);
+        assert_eq!(result.distance(), expected, "{:?} {:?}", sources, targets);
+        let (source, target) = result.data().endpoints();
+        assert_eq!(pair_distance(source, target), expected, "{:?} {:?}", sources, targets);
+    }
+}
@@ -0,0 +1,213 @@
+// Small declarative argument parser for the subcommands.
+// Each command declares its positional arguments and named flags, the help output is generated from that.
+
+use std::{collections::HashMap, error::Error, fmt::Write, str::FromStr};
+
+pub type CmdResult = Result<(), Box<dyn Error>>;
+
+pub struct Flag {
+    pub name: &'static str,
+    /// Placeholder for the value of the flag, `None` for switches
+    pub value: Option<&'static str>,
+    pub help: &'static str,
+}
+
+pub struct Command {
+    pub name: &'static str,
+    pub positional: &'static [&'static str],
+    pub about: &'static str,
+    /// Additional explanation printed below the flags
+    pub details: &'static str,
+    pub flags: &'static [Flag],
+    pub run: fn(&Matches) -> CmdResult,
+}
+
+impl Command {
+    pub fn help(&self) -> String {
+        let mut help = format!("{}\n\nUsage: rrr {}", self.about, self.name);
+        for positional in self.positional {
+            write!(help, " <{}>", positional).unwrap();
+        }
+        help.push_str(" [flags]\n\nFlags:\n");
+
+        let labels: Vec<String> = self
+            .flags
+            .iter()
+            .map(|flag| match flag.value {
+                Some(value) => format!("--{} <{}>", flag.name, value),
+                None => format!("--{}", flag.name),
+            })
+            .chain(std::iter::once("--help".to_string()))
+            .collect();
+        let width = labels.iter().map(String::len).max().unwrap_or(0);
+        for (label, help_text) in labels
+            .iter()
+            .zip(self.flags.iter().map(|flag| flag.help).chain(std::iter::once("Print this help")))
+        {
+            writeln!(help, "    {:width$}  {}", label, help_text, width = width).unwrap();
+        }
+
+        if !self.details.is_empty() {
+            write!(help, "\n{}\n", self.details).unwrap();
+        }
+        help
+    }
+
+    /// Parse the arguments following the command name.
+    /// Returns `None` if the help was requested.
+    pub fn parse(&self, args: impl IntoIterator<Item = String>) -> Result<Option<Matches>, Box<dyn Error>> {
+        let mut matches = Matches::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            if arg == "--help" || arg == "-h" {
+                return Ok(None);
+            }
+            if arg == "--" {
+                matches.rest.extend(args.by_ref());
+                break;
+            }
+            if let Some(name) = arg.strip_prefix("--") {
+                let (name, inline_value) = match name.split_once('=') {
+                    Some((name, value)) => (name, Some(value.to_string())),
+                    None => (name, None),
+                };
+                let flag = self
+                    .flags
+                    .iter()
+                    .find(|flag| flag.name == name)
+                    .ok_or_else(|| format!("Unknown flag --{} for {}, see rrr {} --help", name, self.name, self.name))?;
+                let value = match (flag.value, inline_value) {
+                    (Some(_), Some(value)) => value,
+                    (Some(placeholder), None) => args.next().ok_or_else(|| format!("Missing value <{}> for --{}", placeholder, name))?,
+                    (None, None) => String::new(),
+                    (None, Some(_)) => return Err(format!("--{} does not take a value", name).into()),
+                };
+                matches.values.insert(flag.name, value);
+            } else {
+                matches.positional.push(arg);
+            }
+        }
+
+        if matches.positional.len() != self.positional.len() {
+            return Err(format!(
+                "{} expects {} positional argument(s) but got {}, see rrr {} --help",
+                self.name,
+                self.positional.len(),
+                matches.positional.len(),
+                self.name
+            )
+            .into());
+        }
+
+        Ok(Some(matches))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Matches {
+    positional: Vec<String>,
+    values: HashMap<&'static str, String>,
+    rest: Vec<String>,
+}
+
+impl Matches {
+    pub fn positional(&self, idx: usize) -> &str {
+        &self.positional[idx]
+    }
+
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    pub fn value_or<'a>(&'a self, name: &str, default: &'a str) -> &'a str {
+        self.value(name).unwrap_or(default)
+    }
+
+    pub fn parse_value<T: FromStr>(&self, name: &str) -> Result<Option<T>, Box<dyn Error>> {
+        self.value(name)
+            .map(|value| value.parse().map_err(|_| format!("Invalid value {} for --{}", value, name).into()))
+            .transpose()
+    }
+
+    pub fn switch(&self, name: &str) -> bool {
+        self.values.contains_key(name)
+    }
+
+    /// Arguments after `--`
+    pub fn rest(&self) -> &[String] {
+        &self.rest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST: Command = Command {
+        name: "test",
+        positional: &["graph_dir"],
+        about: "Test command",
+        details: "",
+        flags: &[
+            Flag {
+                name: "metric",
+                value: Some("file"),
+                help: "Weight file",
+            },
+            Flag {
+                name: "verbose",
+                value: None,
+                help: "Print more",
+            },
+        ],
+        run: |_| Ok(()),
+    };
+
+    fn parse(args: &[&str]) -> Result<Option<Matches>, Box<dyn Error>> {
+        TEST.parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn flags_and_positionals() {
+        let matches = parse(&["--metric", "distance", "/data", "--verbose"]).unwrap().unwrap();
+        assert_eq!(matches.positional(0), "/data");
+        assert_eq!(matches.value("metric"), Some("distance"));
+        assert!(matches.switch("verbose"));
+
+        let matches = parse(&["/data", "--metric=distance"]).unwrap().unwrap();
+        assert_eq!(matches.value_or("metric", "travel_time"), "distance");
+        assert!(!matches.switch("verbose"));
+
+        assert!(parse(&["/data", "--help"]).unwrap().is_none());
+    }
+
+    #[test]
+    fn missing_positional() {
+        let err = parse(&["--verbose"]).unwrap_err().to_string();
+        assert!(err.contains("expects 1 positional argument(s) but got 0"), "{}", err);
+    }
+
+    #[test]
+    fn unknown_flag() {
+        let err = parse(&["/data", "--weights", "distance"]).unwrap_err().to_string();
+        assert!(err.starts_with("Unknown flag --weights"), "{}", err);
+    }
+
+    #[test]
+    fn flag_missing_value() {
+        let err = parse(&["/data", "--metric"]).unwrap_err().to_string();
+        assert_eq!(err, "Missing value <file> for --metric");
+
+        let err = parse(&["/data", "--verbose=yes"]).unwrap_err().to_string();
+        assert_eq!(err, "--verbose does not take a value");
+    }
+
+    #[test]
+    fn arguments_after_double_dash_are_passed_through() {
+        let matches = parse(&["/data", "--", "--port", "9000", "--unknown", "extra"]).unwrap().unwrap();
+        assert_eq!(matches.positional(0), "/data");
+        assert_eq!(matches.rest(), ["--port", "9000", "--unknown", "extra"]);
+        assert_eq!(matches.value("port"), None);
+    }
+}
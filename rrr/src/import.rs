@@ -0,0 +1,82 @@
+// Import of HERE and OpenStreetMap data into a graph directory.
+
+use super::*;
+use conversion::{
+    here::{self, csv_source::CSVSource},
+    osm::{self, CarProfile},
+};
+
+pub const IMPORT: Command = Command {
+    name: "import",
+    positional: &["here|osm", "input", "graph_dir"],
+    about: "Import HERE or OpenStreetMap data into a graph directory",
+    details: "For here, input is the directory with the HERE CSV files, for osm an .osm.pbf file.
+Writes first_out, head, travel_time, geo_distance, latitude, longitude and the source specific files.",
+    flags: &[
+        Flag {
+            name: "bbox",
+            value: Some("min_lat,min_lng,max_lat,max_lng"),
+            help: "Only import HERE links within this bounding box (degrees)",
+        },
+        Flag {
+            name: "profile",
+            value: Some("file"),
+            help: "Car profile for OSM data, see CarProfile::from_file (default: built-in profile)",
+        },
+    ],
+    run,
+};
+
+fn run(matches: &Matches) -> CmdResult {
+    let input = Path::new(matches.positional(1));
+    let out_dir = Path::new(matches.positional(2));
+    report!("graph_dir", out_dir.display().to_string());
+
+    match matches.positional(0) {
+        "here" => {
+            if matches.switch("profile") {
+                return Err("--profile is only supported for osm".into());
+            }
+            let (min, max) = match matches.value("bbox") {
+                Some(bbox) => parse_bbox(bbox)?,
+                None => ((-360.0, -360.0), (360.0, 360.0)),
+            };
+            let to_here = |(lat, lng): Coords| ((lat * 100_000.) as i64, (lng * 100_000.) as i64);
+
+            let source = CSVSource::new(input);
+            let data = report_time_with_key("HERE import", "import_running_time_ms", || {
+                here::read_graph(&source, to_here(min), to_here(max))
+            });
+            std::fs::create_dir_all(out_dir)?;
+            data.write_to_dir(out_dir)?;
+            report!("graph", { "num_nodes": data.graph.num_nodes(), "num_arcs": data.graph.num_arcs() });
+        }
+        "osm" => {
+            if matches.switch("bbox") {
+                return Err("--bbox is only supported for here".into());
+            }
+            let profile = match matches.value("profile") {
+                Some(file) => CarProfile::from_file(Path::new(file))?,
+                None => CarProfile::default(),
+            };
+
+            let data = report_time_with_key("OSM import", "import_running_time_ms", || osm::read_pbf(input, &profile))?;
+            data.write_to_dir(out_dir)?;
+            report!("graph", { "num_nodes": data.latitude.len(), "num_arcs": data.head.len(), "num_forbidden_turns": data.forbidden_turn_from_arc.len() });
+        }
+        format => return Err(format!("Unknown input format {}, expected here or osm", format).into()),
+    }
+
+    Ok(())
+}
+
+// lat, lng in degrees
+type Coords = (f64, f64);
+
+fn parse_bbox(bbox: &str) -> Result<(Coords, Coords), Box<dyn Error>> {
+    let coords = bbox.split(',').map(|c| c.trim().parse::<f64>()).collect::<Result<Vec<_>, _>>()?;
+    match coords[..] {
+        [min_lat, min_lng, max_lat, max_lng] => Ok(((min_lat, min_lng), (max_lat, max_lng))),
+        _ => Err("--bbox expects four comma separated coordinates".into()),
+    }
+}
@@ -0,0 +1,119 @@
+// rrr - one command line tool for the whole toolchain.
+// Import, preprocessing and queries are subcommands working on a graph directory in the RoutingKit format.
+// Results and statistics are printed as a single JSON object on stdout, progress messages go to stderr.
+
+use std::{env, error::Error, path::Path, process};
+
+#[macro_use]
+extern crate rust_road_router;
+use rust_road_router::{
+    algo::customizable_contraction_hierarchy::{nested_dissection::nested_dissection_order, *},
+    datastr::{graph::*, node_order::NodeOrder},
+    io::*,
+    report::*,
+};
+
+mod args;
+mod import;
+mod preprocessing;
+mod queries;
+mod tools;
+
+use args::*;
+
+const COMMANDS: &[Command] = &[
+    import::IMPORT,
+    preprocessing::ORDER,
+    preprocessing::CONTRACT,
+    preprocessing::CUSTOMIZE,
+    queries::QUERY,
+    queries::MATRIX,
+    tools::SERVE,
+    tools::VALIDATE,
+];
+
+const FILE_LAYOUT: &str = "All commands work on a graph directory in the RoutingKit format.
+Files are named consistently, relative to the graph directory:
+    first_out, head             the graph
+    <metric>                    arc weights, by default travel_time
+    latitude, longitude         node coordinates
+    cch_perm                    nested dissection order, written by order
+    cch/                        CCH for the order, written by contract
+    ch_<metric>/                CH including its node order, written by contract --algorithm ch
+    customized_<metric>/        customized CCH, written by customize";
+
+fn print_help() {
+    println!("rrr - routing toolchain\n\nUsage: rrr <command> [args] [flags]\n\nCommands:");
+    for command in COMMANDS {
+        println!("    {:10}  {}", command.name, command.about);
+    }
+    println!("\nRun rrr <command> --help for the arguments of a command.\n\n{}", FILE_LAYOUT);
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let name = match args.next() {
+        Some(name) if name != "--help" && name != "-h" && name != "help" => name,
+        Some(_) => return print_help(),
+        None => {
+            print_help();
+            process::exit(2);
+        }
+    };
+
+    let command = match COMMANDS.iter().find(|command| command.name == name) {
+        Some(command) => command,
+        None => {
+            eprintln!("Unknown command {}, see rrr --help", name);
+            process::exit(2);
+        }
+    };
+
+    let result = command.parse(args).and_then(|matches| match matches {
+        Some(matches) => {
+            let _reporter = enable_reporting("rrr");
+            report!("command", command.name);
+            (command.run)(&matches)
+        }
+        None => {
+            println!("{}", command.help());
+            Ok(())
+        }
+    });
+
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+/// Load the graph from `dir` with the given metric as weights
+fn load_graph(dir: &Path, metric: &str) -> Result<OwnedGraph, Box<dyn Error>> {
+    let graph = OwnedGraph::new(
+        Vec::load_from(dir.join("first_out"))?,
+        Vec::load_from(dir.join("head"))?,
+        Vec::load_from(dir.join(metric))?,
+    );
+    report!("graph", { "num_nodes": graph.num_nodes(), "num_arcs": graph.num_arcs(), "metric": metric });
+    Ok(graph)
+}
+
+/// Load the CCH from `cch/`, or build it from `cch_perm` if it was not contracted yet
+fn load_cch(dir: &Path, graph: &OwnedGraph) -> Result<CCH, Box<dyn Error>> {
+    let cch_dir = dir.join("cch");
+    if cch_dir.exists() {
+        return Ok(report_time_with_key("CCH loading", "cch_loading_running_time_ms", || {
+            CCHReconstrctor(graph).reconstruct_from(&cch_dir)
+        })?);
+    }
+    let order_file = dir.join("cch_perm");
+    if !order_file.exists() {
+        return Err(format!("Neither cch/ nor cch_perm in {}, run rrr order first", dir.display()).into());
+    }
+    let order = NodeOrder::from_node_order(Vec::load_from(order_file)?);
+    Ok(CCH::fix_order_and_build(graph, order))
+}
+
+fn customized_dir(dir: &Path, metric: &str) -> std::path::PathBuf {
+    dir.join(format!("customized_{}", metric))
+}
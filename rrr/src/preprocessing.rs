@@ -0,0 +1,128 @@
+// Ordering, contraction and customization.
+
+use super::*;
+use rust_road_router::algo::contraction_hierarchy::ordering::{contract_with_ordering, OrderingParams};
+
+const METRIC: Flag = Flag {
+    name: "metric",
+    value: Some("file"),
+    help: "Weight file in the graph directory (default: travel_time)",
+};
+
+pub const ORDER: Command = Command {
+    name: "order",
+    positional: &["graph_dir"],
+    about: "Compute a nested dissection order for CCHs",
+    details: "Uses the inertial flow based nested dissection on latitude and longitude.",
+    flags: &[Flag {
+        name: "output",
+        value: Some("file"),
+        help: "Output file in the graph directory (default: cch_perm)",
+    }],
+    run: order,
+};
+
+fn order(matches: &Matches) -> CmdResult {
+    let dir = Path::new(matches.positional(0));
+    let output = matches.value_or("output", "cch_perm");
+
+    let graph = load_graph(dir, "travel_time")?;
+    let latitude = Vec::<f32>::load_from(dir.join("latitude"))?;
+    let longitude = Vec::<f32>::load_from(dir.join("longitude"))?;
+
+    let order = nested_dissection_order(&graph, &latitude, &longitude);
+    order.order().write_to(&dir.join(output))?;
+    report!("output", output);
+
+    Ok(())
+}
+
+pub const CONTRACT: Command = Command {
+    name: "contract",
+    positional: &["graph_dir"],
+    about: "Build a CCH from a node order or a CH for a metric",
+    details: "The CCH is metric independent and written to cch/ together with its final node order.
+The CH is computed with its own node ordering and written to ch_<metric>/.",
+    flags: &[
+        Flag {
+            name: "algorithm",
+            value: Some("cch|ch"),
+            help: "Hierarchy to build (default: cch)",
+        },
+        Flag {
+            name: "order",
+            value: Some("file"),
+            help: "Node order for the CCH in the graph directory (default: cch_perm)",
+        },
+        METRIC,
+        Flag {
+            name: "witness-search-limit",
+            value: Some("n"),
+            help: "Settled nodes per witness search for the CH (default: 500)",
+        },
+    ],
+    run: contract,
+};
+
+fn contract(matches: &Matches) -> CmdResult {
+    let dir = Path::new(matches.positional(0));
+    let metric = matches.value_or("metric", "travel_time");
+    report!("algorithm", matches.value_or("algorithm", "cch"));
+
+    match matches.value_or("algorithm", "cch") {
+        "cch" => {
+            let graph = load_graph(dir, metric)?;
+            let order = NodeOrder::from_node_order(Vec::load_from(dir.join(matches.value_or("order", "cch_perm")))?);
+            let cch = CCH::fix_order_and_build(&graph, order);
+            report!("num_cch_arcs", cch.num_arcs());
+
+            let cch_dir = dir.join("cch");
+            cch.deconstruct_with_manifest_to(&cch_dir)?;
+            cch.node_order().deconstruct_to(&cch_dir)?;
+            report!("output", "cch");
+        }
+        "ch" => {
+            if matches.switch("order") {
+                return Err("--order is only supported for the CCH, the CH computes its own order".into());
+            }
+            let graph = load_graph(dir, metric)?;
+            let mut params = OrderingParams::default();
+            if let Some(limit) = matches.parse_value("witness-search-limit")? {
+                params.witness_search_limit = limit;
+            }
+            let (ch, order) = contract_with_ordering(&graph, params);
+
+            let ch_dir = dir.join(format!("ch_{}", metric));
+            ch.deconstruct_with_manifest_to(&ch_dir)?;
+            order.deconstruct_to(&ch_dir)?;
+            report!("output", ch_dir.file_name().unwrap().to_string_lossy());
+        }
+        algorithm => return Err(format!("Unknown algorithm {}, expected cch or ch", algorithm).into()),
+    }
+
+    Ok(())
+}
+
+pub const CUSTOMIZE: Command = Command {
+    name: "customize",
+    positional: &["graph_dir"],
+    about: "Customize the CCH with a metric",
+    details: "Uses cch/ if it exists and otherwise builds the CCH from cch_perm first. Writes customized_<metric>/.",
+    flags: &[METRIC],
+    run: customize_cmd,
+};
+
+fn customize_cmd(matches: &Matches) -> CmdResult {
+    let dir = Path::new(matches.positional(0));
+    let metric = matches.value_or("metric", "travel_time");
+
+    let graph = load_graph(dir, metric)?;
+    let cch = load_cch(dir, &graph)?;
+    let customized = customize(&cch, &graph);
+
+    let out_dir = customized_dir(dir, metric);
+    customized.deconstruct_with_manifest_to(&out_dir)?;
+    report!("output", out_dir.file_name().unwrap().to_string_lossy());
+
+    Ok(())
+}
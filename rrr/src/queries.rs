@@ -0,0 +1,209 @@
+// Point to point queries and distance matrices.
+
+use super::*;
+use rust_road_router::algo::{
+    contraction_hierarchy::{self, ContractionHierarchy},
+    customizable_contraction_hierarchy::query::Server as CCHServer,
+    dijkstra::{query::dijkstra::Server as DijkServer, DefaultOps},
+    rphast::DistanceMatrixServer,
+    *,
+};
+
+pub const QUERY: Command = Command {
+    name: "query",
+    positional: &["graph_dir"],
+    about: "Answer shortest path queries",
+    details: "Queries are given either with --from and --to or as a directory with source and target files,
+as written by generate_queries. The CCH uses customized_<metric>/ if it exists and customizes on the fly otherwise.
+The CH requires ch_<metric>/ from rrr contract --algorithm ch. Unreachable targets have a distance of null.",
+    flags: &[
+        Flag {
+            name: "from",
+            value: Some("node"),
+            help: "Source node of a single query",
+        },
+        Flag {
+            name: "to",
+            value: Some("node"),
+            help: "Target node of a single query",
+        },
+        Flag {
+            name: "queries",
+            value: Some("dir"),
+            help: "Directory with source and target files for many queries",
+        },
+        Flag {
+            name: "algorithm",
+            value: Some("cch|ch|dijkstra"),
+            help: "Query algorithm (default: cch)",
+        },
+        Flag {
+            name: "metric",
+            value: Some("file"),
+            help: "Weight file in the graph directory (default: travel_time)",
+        },
+        Flag {
+            name: "path",
+            value: None,
+            help: "Also output the node path of each query",
+        },
+    ],
+    run: query,
+};
+
+fn query(matches: &Matches) -> CmdResult {
+    let dir = Path::new(matches.positional(0));
+    let metric = matches.value_or("metric", "travel_time");
+    let with_path = matches.switch("path");
+
+    let queries: Vec<Query> = match (matches.parse_value("from")?, matches.parse_value("to")?, matches.value("queries")) {
+        (Some(from), Some(to), None) => vec![Query { from, to }],
+        (None, None, Some(queries)) => {
+            let queries = Path::new(queries);
+            let sources: Vec<NodeId> = Vec::load_from(queries.join("source"))?;
+            let targets: Vec<NodeId> = Vec::load_from(queries.join("target"))?;
+            if sources.len() != targets.len() {
+                return Err("Different number of sources and targets".into());
+            }
+            sources.into_iter().zip(targets).map(|(from, to)| Query { from, to }).collect()
+        }
+        _ => return Err("Either --from and --to or --queries are required".into()),
+    };
+
+    let graph = load_graph(dir, metric)?;
+    let n = graph.num_nodes() as NodeId;
+    if let Some(query) = queries.iter().find(|query| query.from >= n || query.to >= n) {
+        return Err(format!("Query from {} to {} is out of range for a graph with {} nodes", query.from, query.to, n).into());
+    }
+
+    let algorithm = matches.value_or("algorithm", "cch");
+    report!("algorithm", algorithm);
+    match algorithm {
+        "cch" => {
+            let cch = load_cch(dir, &graph)?;
+            let customized_dir = customized_dir(dir, metric);
+            let customized = if customized_dir.exists() {
+                (&cch).reconstruct_from(&customized_dir)?
+            } else {
+                customize(&cch, &graph)
+            };
+            let mut server = CCHServer::new(customized);
+            run_queries(&queries, |query| answer(server.query(query), with_path));
+        }
+        "ch" => {
+            let ch_dir = dir.join(format!("ch_{}", metric));
+            if !ch_dir.exists() {
+                return Err(format!("No {} in {}, run rrr contract --algorithm ch first", ch_dir.display(), dir.display()).into());
+            }
+            let ch = ContractionHierarchy::reconstruct_from(&ch_dir)?;
+            let order = NodeOrder::reconstruct_from(&ch_dir)?;
+            let mut server = contraction_hierarchy::query::Server::new(ch, order);
+            run_queries(&queries, |query| answer(server.query(query), with_path));
+        }
+        "dijkstra" => {
+            let mut server = DijkServer::<OwnedGraph, DefaultOps, _, &OwnedGraph>::new(&graph);
+            run_queries(&queries, |query| answer(server.query(query), with_path));
+        }
+        algorithm => return Err(format!("Unknown algorithm {}, expected cch, ch or dijkstra", algorithm).into()),
+    }
+
+    Ok(())
+}
+
+// Distance and, if requested, node path of a query result
+fn answer<P: PathServer<NodeInfo = NodeId>>(mut result: QueryResult<P, Weight>, with_path: bool) -> (Option<Weight>, Option<Vec<NodeId>>) {
+    let path = if with_path { result.node_path() } else { None };
+    (result.distance(), path)
+}
+
+// Generic over the answering closure rather than the `QueryServer`,
+// because bounding the path server of all lifetimes forces the server to be `'static`.
+fn run_queries(queries: &[Query], mut answer: impl FnMut(Query) -> (Option<Weight>, Option<Vec<NodeId>>)) {
+    let mut results_ctxt = push_collection_context("queries");
+    let mut total_time = std::time::Duration::ZERO;
+
+    for &query in queries {
+        let _item = results_ctxt.push_collection_item();
+        report!("from", query.from);
+        report!("to", query.to);
+
+        let ((distance, path), time) = measure(|| answer(query));
+        total_time += time;
+        report!("running_time_ms", time.as_secs_f64() * 1000.0);
+        report!("distance", distance);
+        if let Some(path) = path {
+            report!("path", path);
+        }
+    }
+
+    drop(results_ctxt);
+    report!("num_queries", queries.len());
+    report!("total_query_time_ms", total_time.as_secs_f64() * 1000.0);
+}
+
+pub const MATRIX: Command = Command {
+    name: "matrix",
+    positional: &["graph_dir"],
+    about: "Compute a distance matrix with the CCH",
+    details: "Sources and targets are comma separated node ids or the name of a node id vector file.
+Outputs one row per source, unreachable targets have a distance of null.",
+    flags: &[
+        Flag {
+            name: "sources",
+            value: Some("nodes|file"),
+            help: "Source nodes",
+        },
+        Flag {
+            name: "targets",
+            value: Some("nodes|file"),
+            help: "Target nodes",
+        },
+        Flag {
+            name: "metric",
+            value: Some("file"),
+            help: "Weight file in the graph directory (default: travel_time)",
+        },
+    ],
+    run: matrix,
+};
+
+fn matrix(matches: &Matches) -> CmdResult {
+    let dir = Path::new(matches.positional(0));
+    let metric = matches.value_or("metric", "travel_time");
+    let sources = parse_nodes(matches.value("sources").ok_or("--sources is required")?)?;
+    let targets = parse_nodes(matches.value("targets").ok_or("--targets is required")?)?;
+
+    let graph = load_graph(dir, metric)?;
+    let n = graph.num_nodes() as NodeId;
+    if let Some(node) = sources.iter().chain(targets.iter()).find(|&&node| node >= n) {
+        return Err(format!("Node {} is out of range for a graph with {} nodes", node, n).into());
+    }
+
+    let cch = load_cch(dir, &graph)?;
+    let customized_dir = customized_dir(dir, metric);
+    let customized = if customized_dir.exists() {
+        (&cch).reconstruct_from(&customized_dir)?
+    } else {
+        customize(&cch, &graph)
+    };
+
+    let mut server = DistanceMatrixServer::new(customized.forward_graph(), customized.backward_graph(), cch.node_order().clone());
+    let distances = report_time_with_key("distance matrix", "matrix_running_time_ms", || server.distances(&sources, &targets));
+    let distances: Vec<Vec<Option<Weight>>> = distances
+        .into_iter()
+        .map(|row| row.into_iter().map(|dist| if dist < INFINITY { Some(dist) } else { None }).collect())
+        .collect();
+
+    report!("sources", sources);
+    report!("targets", targets);
+    report!("distances", distances);
+
+    Ok(())
+}
+
+fn parse_nodes(nodes: &str) -> Result<Vec<NodeId>, Box<dyn Error>> {
+    match nodes.split(',').map(|node| node.trim().parse()).collect() {
+        Ok(nodes) => Ok(nodes),
+        Err(_) => Ok(Vec::load_from(nodes)?),
+    }
+}
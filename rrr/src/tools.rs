@@ -0,0 +1,93 @@
+// Running the server and checking graph directories.
+
+use super::*;
+use rust_road_router::cli::CliErr;
+use utils::validate_graph::validate_graph;
+
+pub const SERVE: Command = Command {
+    name: "serve",
+    positional: &["graph_dir"],
+    about: "Start the HTTP routing server on a graph directory",
+    details: "Runs the server binary next to rrr, so both have to be built.
+Arguments after -- are passed on to the server, e.g. rrr serve /data -- --config server.toml --turn-restrictions true",
+    flags: &[
+        Flag {
+            name: "metric",
+            value: Some("file"),
+            help: "Weight file in the graph directory (default: travel_time)",
+        },
+        Flag {
+            name: "address",
+            value: Some("address"),
+            help: "Address to listen on (default: localhost)",
+        },
+        Flag {
+            name: "port",
+            value: Some("port"),
+            help: "Port to listen on (default: 8000)",
+        },
+    ],
+    run: serve,
+};
+
+fn serve(matches: &Matches) -> CmdResult {
+    let server = env::current_exe()?.with_file_name(format!("server{}", env::consts::EXE_SUFFIX));
+    if !server.exists() {
+        return Err(format!("Server binary not found at {}, build it with cargo build -p server", server.display()).into());
+    }
+
+    let mut command = process::Command::new(&server);
+    command.arg("--data-dir").arg(matches.positional(0));
+    for flag in ["metric", "address", "port"] {
+        if let Some(value) = matches.value(flag) {
+            command.arg(format!("--{}", flag)).arg(value);
+        }
+    }
+    command.args(matches.rest());
+
+    let status = command.status()?;
+    if !status.success() {
+        return Err(format!("Server exited with {}", status).into());
+    }
+    Ok(())
+}
+
+pub const VALIDATE: Command = Command {
+    name: "validate",
+    positional: &["graph_dir"],
+    about: "Check a graph directory for consistency",
+    details: "Checks first_out and head, the weight files, latitude and longitude, cch_perm, time-dependent travel time functions
+and forbidden turns. Files which do not exist are skipped.",
+    flags: &[Flag {
+        name: "weights",
+        value: Some("file,..."),
+        help: "Weight files to check (default: travel_time,geo_distance)",
+    }],
+    run: validate,
+};
+
+fn validate(matches: &Matches) -> CmdResult {
+    let dir = Path::new(matches.positional(0));
+    let weight_files: Vec<&str> = matches.value_or("weights", "travel_time,geo_distance").split(',').collect();
+
+    let validation = validate_graph(dir, &weight_files);
+    eprintln!("{}", validation);
+
+    let mut checks_ctxt = push_collection_context("checks");
+    for check in &validation.checks {
+        let _item = checks_ctxt.push_collection_item();
+        report!("name", check.name);
+        report!("num_checked", check.num_checked);
+        report!("num_violations", check.num_violations);
+        report!("examples", check.examples);
+    }
+    drop(checks_ctxt);
+    report!("skipped", validation.skipped);
+    report!("passed", validation.passed());
+
+    if validation.passed() {
+        Ok(())
+    } else {
+        Err(Box::new(CliErr("Graph is inconsistent")))
+    }
+}
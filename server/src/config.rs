@@ -0,0 +1,206 @@
+// Server configuration.
+// Values are read from an optional TOML file and can be overridden by command line flags.
+
+use rust_road_router::datastr::vehicle_restrictions::{hazmat, vehicle_class, VehicleProfile};
+use serde::Deserialize;
+
+use std::{
+    collections::BTreeMap,
+    env,
+    error::Error,
+    fmt,
+    path::{Path, PathBuf},
+    thread,
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Directory containing the graph in the RoutingKit format and a nested disection order
+    pub data_dir: PathBuf,
+    /// Name of the file in `data_dir` containing the initial weights
+    pub metric: String,
+    /// Load the HERE link id mapping and enable HERE specific endpoints
+    pub here_link_ids: bool,
+    /// Load `forbidden_turn_from_arc` and `forbidden_turn_to_arc` from `data_dir`
+    pub turn_restrictions: bool,
+    /// Run the CATCHUp customization on the time-dependent graph in `data_dir` and enable `/profile`
+    pub profiles: bool,
+    /// Directory for snapshots of the CCH and the latest customization, used to warm-start the server
+    pub snapshot_dir: Option<PathBuf>,
+    /// Minimum number of seconds between two snapshots after live updates
+    pub snapshot_interval: u64,
+    pub address: String,
+    pub port: u16,
+    /// Number of threads answering queries
+    pub query_workers: usize,
+    /// Number of threads used for customization
+    pub customization_threads: usize,
+    /// Vehicle profiles by name, each gets its own customization, only from the config file
+    pub vehicle_profiles: BTreeMap<String, VehicleConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let cores = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        Config {
+            data_dir: PathBuf::new(),
+            metric: "travel_time".to_string(),
+            here_link_ids: true,
+            turn_restrictions: false,
+            profiles: false,
+            snapshot_dir: None,
+            snapshot_interval: 60,
+            address: "localhost".to_string(),
+            port: 8000,
+            query_workers: cores,
+            customization_threads: cores,
+            vehicle_profiles: BTreeMap::new(),
+        }
+    }
+}
+
+/// Dimensions and load of a vehicle, converted to a `VehicleProfile`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VehicleConfig {
+    /// Height in meters
+    pub height: f32,
+    /// Total weight in tons
+    pub weight: f32,
+    /// Classes of dangerous goods loaded, any of `water_polluting`, `explosive`, `flammable` and `other`
+    pub hazmat: Vec<String>,
+    /// One of `car`, `truck`, `bus` and `delivery`
+    pub vehicle_class: String,
+}
+
+impl Default for VehicleConfig {
+    fn default() -> Self {
+        VehicleConfig {
+            height: 0.0,
+            weight: 0.0,
+            hazmat: Vec::new(),
+            vehicle_class: "car".to_string(),
+        }
+    }
+}
+
+impl VehicleConfig {
+    pub fn profile(&self) -> Result<VehicleProfile, ConfigErr> {
+        let mut hazmat = 0;
+        for class in &self.hazmat {
+            hazmat |= match &class[..] {
+                "water_polluting" => hazmat::WATER_POLLUTING,
+                "explosive" => hazmat::EXPLOSIVE,
+                "flammable" => hazmat::FLAMMABLE,
+                "other" => hazmat::OTHER,
+                _ => return Err(ConfigErr(format!("Unknown hazmat class {}", class))),
+            };
+        }
+        let vehicle_class = match &self.vehicle_class[..] {
+            "car" => vehicle_class::CAR,
+            "truck" => vehicle_class::TRUCK,
+            "bus" => vehicle_class::BUS,
+            "delivery" => vehicle_class::DELIVERY,
+            _ => return Err(ConfigErr(format!("Unknown vehicle class {}", self.vehicle_class))),
+        };
+        Ok(VehicleProfile {
+            height: (self.height * 100.0).round() as u32,
+            weight: (self.weight * 1000.0).round() as u32,
+            hazmat,
+            vehicle_class,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct ConfigErr(String);
+
+impl fmt::Display for ConfigErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for ConfigErr {}
+
+pub const USAGE: &str = "Usage: server [DATA_DIR [QUERY_WORKERS]] [--config FILE] [--data-dir DIR] [--metric NAME] [--here-link-ids true|false] \
+[--turn-restrictions true|false] [--profiles true|false] [--snapshot-dir DIR] [--snapshot-interval SECS] [--address ADDR] [--port PORT] [--query-workers N] [--customization-threads N]";
+
+impl Config {
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&content)?)
+    }
+
+    /// Build the configuration from the command line.
+    /// A config file given with `--config` is read first, all other flags take precedence over its values.
+    /// For compatibility, the data directory and the number of query workers may also be passed as positional arguments.
+    pub fn from_args(args: env::Args) -> Result<Self, Box<dyn Error>> {
+        let args: Vec<String> = args.skip(1).collect();
+
+        let mut config = match args.iter().position(|arg| arg == "--config") {
+            Some(idx) => Config::from_file(Path::new(args.get(idx + 1).ok_or_else(|| ConfigErr(USAGE.to_string()))?))?,
+            None => Config::default(),
+        };
+
+        let mut num_positional = 0;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                match num_positional {
+                    0 => config.data_dir = PathBuf::from(arg),
+                    1 => config.query_workers = parse_flag("QUERY_WORKERS", &arg)?,
+                    _ => return Err(Box::new(ConfigErr(format!("Unexpected argument {}\n{}", arg, USAGE)))),
+                }
+                num_positional += 1;
+                continue;
+            }
+            let value = args.next().ok_or_else(|| ConfigErr(format!("Missing value for {}\n{}", arg, USAGE)))?;
+            match &arg[..] {
+                "--config" => (),
+                "--data-dir" => config.data_dir = PathBuf::from(value),
+                "--metric" => config.metric = value,
+                "--here-link-ids" => config.here_link_ids = parse_flag(&arg, &value)?,
+                "--turn-restrictions" => config.turn_restrictions = parse_flag(&arg, &value)?,
+                "--profiles" => config.profiles = parse_flag(&arg, &value)?,
+                "--snapshot-dir" => config.snapshot_dir = Some(PathBuf::from(value)),
+                "--snapshot-interval" => config.snapshot_interval = parse_flag(&arg, &value)?,
+                "--address" => config.address = value,
+                "--port" => config.port = parse_flag(&arg, &value)?,
+                "--query-workers" => config.query_workers = parse_flag(&arg, &value)?,
+                "--customization-threads" => config.customization_threads = parse_flag(&arg, &value)?,
+                _ => return Err(Box::new(ConfigErr(format!("Unknown argument {}\n{}", arg, USAGE)))),
+            }
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigErr> {
+        if self.data_dir.as_os_str().is_empty() {
+            return Err(ConfigErr(format!("No data directory given\n{}", USAGE)));
+        }
+        if self.query_workers == 0 {
+            return Err(ConfigErr("Need at least one query worker".to_string()));
+        }
+        if self.snapshot_interval == 0 {
+            return Err(ConfigErr("The snapshot interval has to be at least one second".to_string()));
+        }
+        if self.customization_threads == 0 {
+            return Err(ConfigErr("Need at least one customization thread".to_string()));
+        }
+        if self.turn_restrictions && !self.vehicle_profiles.is_empty() {
+            return Err(ConfigErr("Vehicle profiles are not supported with turn restrictions".to_string()));
+        }
+        for vehicle in self.vehicle_profiles.values() {
+            vehicle.profile()?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_flag<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, ConfigErr> {
+    value.parse().map_err(|_| ConfigErr(format!("Invalid value {} for {}", value, flag)))
+}
@@ -4,6 +4,13 @@
 
 #[macro_use]
 extern crate rocket;
+
+mod config;
+use config::Config;
+mod snapshot;
+mod turns;
+use turns::TurnExpandedGraph;
+
 use serde::{Deserialize, Serialize};
 
 use std::{
@@ -11,60 +18,80 @@ use std::{
     error::Error,
     iter::once,
     path::{Path, PathBuf},
-    sync::mpsc::{self, Sender},
-    sync::{Arc, Mutex},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    sync::{Arc, Mutex, OnceLock, RwLock},
     thread,
+    time::{Duration, Instant},
 };
 
-use rocket::{request::Form, response::NamedFile, State};
+use rocket::{
+    request::Form,
+    response::{status::BadRequest, NamedFile},
+    State,
+};
 use rocket_contrib::json::Json;
 
-use kdtree::kdtree::{Kdtree, KdtreePointTrait};
-
 use conversion::here::link_id_mapper::*;
 use rust_road_router::{
     algo::{
-        customizable_contraction_hierarchy::{customize as cch_customize, query::Server, CCH},
+        catchup::{customize as catchup_customize, profiles::Server as ProfileServer},
+        ch_potentials::{penalty::Penalty, query::Server as TDServer, CCHPotData, SharedCCHPot},
+        customizable_contraction_hierarchy::{
+            customize as cch_customize, customize_incremental,
+            query::{isochrone, Server},
+            Customized, CustomizedBasic, CCH, CCHT,
+        },
+        dijkstra::query::td_dijkstra::TDDijkstraOps,
+        mid_edge::{exits, partial_weight, EdgePoint, MidEdgeQuery, MidEdgeQueryServer},
+        rphast::DistanceMatrixServer,
+        strongly_connected_components::{largest_component, strongly_connected_components},
         *,
     },
     cli::CliErr,
     datastr::{
-        graph::{link_id_to_tail_mapper::*, *},
+        graph::{
+            floating_time_dependent::{shortcut_graph::CustomizedGraph, TDGraph as FloatingTDGraph},
+            link_id_to_tail_mapper::*,
+            time_dependent::{TDGraph, Timestamp},
+            *,
+        },
         node_order::NodeOrder,
         rank_select_map::*,
+        spatial::{EdgeSnap, SpatialIndex},
+        vehicle_restrictions::{LinkAttributes, LinkAttributesReconstructor, VehicleProfile},
     },
     io::*,
     report::report_time,
 };
 
-#[derive(PartialEq, Clone, Copy)]
-struct NodeCoord {
-    coords: [f64; 2],
-    node_id: NodeId,
-}
-
-impl KdtreePointTrait for NodeCoord {
-    #[inline] // the inline on this method is important! Without it there is ~25% speed loss on the tree when cross-crate usage.
-    fn dims(&self) -> &[f64] {
-        &self.coords
-    }
-}
+// Arcs at most this many meters farther away than the closest one are also considered as query endpoints,
+// so both directions of the closest road are tried.
+const SNAP_TOLERANCE: f32 = 1.0;
 
-#[derive(Debug, FromForm, Copy, Clone)]
+#[derive(Debug, FromForm, Clone)]
 struct GeoQuery {
     from_lat: f32,
     from_lng: f32,
     to_lat: f32,
     to_lng: f32,
+    // departure time in ms, only with a time-dependent graph
+    departure: Option<Timestamp>,
+    // name of a vehicle profile from the config
+    vehicle: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
 struct GeoResponse {
     distance: Weight,
     path: Vec<(f32, f32)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    arrival: Option<Timestamp>,
+    // the time at which each node of the path is reached
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamps: Option<Vec<Timestamp>>,
 }
 
-#[derive(Debug, FromForm, Copy, Clone)]
+#[derive(Debug, FromForm, Clone)]
 struct HereQuery {
     from_link_id: u64,
     from_direction: bool,
@@ -72,20 +99,134 @@ struct HereQuery {
     to_link_id: u64,
     to_direction: bool,
     to_link_fraction: f32,
+    // departure time in ms, only with a time-dependent graph
+    departure: Option<Timestamp>,
+    // name of a vehicle profile from the config
+    vehicle: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
 struct HereResponse {
     distance: Weight,
     path: Vec<(u64, bool)>,
+    // the driven part of each link of the path, as fractions from its start in the direction of travel
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fractions: Option<Vec<(f32, f32)>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    arrival: Option<Timestamp>,
+    // the time at which each link of the path is left, for the last one the arrival at the target
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamps: Option<Vec<Timestamp>>,
+}
+
+#[derive(Debug, FromForm, Copy, Clone)]
+struct AlternativesQuery {
+    from_lat: f32,
+    from_lng: f32,
+    to_lat: f32,
+    to_lng: f32,
+    // number of alternatives in addition to the shortest route, defaults to 3
+    k: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AlternativeRouteResponse {
+    distance: Weight,
+    // ratio of the route length and the length of the shortest route
+    detour: f64,
+    // fraction of the route length shared with the shortest route
+    sharing: f64,
+    path: Vec<(f32, f32)>,
+    // only present when the HERE link id mapping was loaded
+    here_links: Option<Vec<(u64, bool)>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AlternativesResponse {
+    // the shortest route comes first
+    routes: Vec<AlternativeRouteResponse>,
+}
+
+#[derive(Debug, FromForm, Copy, Clone)]
+struct ProfileQuery {
+    from_lat: f32,
+    from_lng: f32,
+    to_lat: f32,
+    to_lng: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProfilePathResponse {
+    path: Vec<(f32, f32)>,
+    // departure time intervals in ms in which this path is optimal
+    valid: Vec<(f64, f64)>,
+    // only present when the HERE link id mapping was loaded
+    here_links: Option<Vec<(u64, bool)>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProfileResponse {
+    // departure time and travel time in ms, covering one period
+    breakpoints: Vec<(f64, f64)>,
+    paths: Vec<ProfilePathResponse>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(untagged)]
+enum MatrixLocation {
+    Geo { lat: f32, lng: f32 },
+    Here { link_id: u64, direction: bool },
+}
+
+#[derive(Debug, Deserialize)]
+struct MatrixQuery {
+    sources: Vec<MatrixLocation>,
+    targets: Vec<MatrixLocation>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MatrixResponse {
+    distances: Vec<Vec<Option<Weight>>>,
+}
+
+#[derive(Debug, FromForm, Copy, Clone)]
+struct IsochroneQuery {
+    lat: f32,
+    lng: f32,
+    budget: Weight,
+}
+
+#[derive(Serialize, Deserialize)]
+struct IsochroneNode {
+    coords: (f32, f32),
+    distance: Weight,
+}
+
+#[derive(Serialize, Deserialize)]
+struct IsochroneBoundaryEdge {
+    tail: (f32, f32),
+    head: (f32, f32),
+    // the point on the edge where the budget is used up
+    cut: (f32, f32),
+}
+
+#[derive(Serialize, Deserialize)]
+struct IsochroneResponse {
+    nodes: Vec<IsochroneNode>,
+    boundary: Vec<IsochroneBoundaryEdge>,
 }
 
 enum Request {
-    Geo((GeoQuery, Sender<Option<GeoResponse>>)),
-    Here((HereQuery, Sender<Option<HereResponse>>)),
-    Customize(Vec<(u64, bool, SerializedWeight)>),
+    Geo((GeoQuery, Sender<Result<GeoResponse, String>>)),
+    Isochrone((IsochroneQuery, Sender<IsochroneResponse>)),
+    Here((HereQuery, Sender<Result<HereResponse, String>>)),
+    Matrix((MatrixQuery, Sender<Result<MatrixResponse, String>>)),
+    Alternatives((AlternativesQuery, Sender<Option<AlternativesResponse>>)),
+    Profile((ProfileQuery, Sender<Option<ProfileResponse>>)),
 }
 
+struct CustomizeRequest(Vec<(u64, bool, SerializedWeight)>);
+
 #[get("/")]
 fn index() -> Option<NamedFile> {
     NamedFile::open(Path::new("static/index.html")).ok()
@@ -97,30 +238,46 @@ fn files(file: PathBuf) -> Option<NamedFile> {
 }
 
 #[get("/query?<query_params..>", format = "application/json")]
-fn query(query_params: Form<GeoQuery>, state: State<Mutex<Sender<Request>>>) -> Json<Option<GeoResponse>> {
+fn query(query_params: Form<GeoQuery>, state: State<Mutex<Sender<Request>>>) -> Result<Json<GeoResponse>, BadRequest<String>> {
     let result = report_time("Total Query Request Time", || {
         println!("Received Query: {:?}", query_params);
 
         let tx_query = state.lock().unwrap();
-        let (tx_result, rx_result) = mpsc::channel::<Option<GeoResponse>>();
+        let (tx_result, rx_result) = mpsc::channel::<Result<GeoResponse, String>>();
 
-        tx_query.send(Request::Geo((*query_params, tx_result))).unwrap();
+        tx_query.send(Request::Geo((query_params.into_inner(), tx_result))).unwrap();
         rx_result.recv().expect("routing engine crashed or hung up")
     });
 
     println!();
-    Json(result)
+    result.map(Json).map_err(|err| BadRequest(Some(err)))
 }
 
 #[get("/here_query?<query_params..>", format = "application/json")]
-fn here_query(query_params: Form<HereQuery>, state: State<Mutex<Sender<Request>>>) -> Json<Option<HereResponse>> {
+fn here_query(query_params: Form<HereQuery>, state: State<Mutex<Sender<Request>>>) -> Result<Json<HereResponse>, BadRequest<String>> {
     let result = report_time("Total Query Request Time", || {
         println!("Received Query: {:?}", query_params);
 
         let tx_query = state.lock().unwrap();
-        let (tx_result, rx_result) = mpsc::channel::<Option<HereResponse>>();
+        let (tx_result, rx_result) = mpsc::channel::<Result<HereResponse, String>>();
 
-        tx_query.send(Request::Here((*query_params, tx_result))).unwrap();
+        tx_query.send(Request::Here((query_params.into_inner(), tx_result))).unwrap();
+        rx_result.recv().expect("routing engine crashed or hung up")
+    });
+
+    println!();
+    result.map(Json).map_err(|err| BadRequest(Some(err)))
+}
+
+#[get("/isochrone?<query_params..>", format = "application/json")]
+fn isochrone_query(query_params: Form<IsochroneQuery>, state: State<Mutex<Sender<Request>>>) -> Json<IsochroneResponse> {
+    let result = report_time("Total Isochrone Request Time", || {
+        println!("Received Isochrone Query: {:?}", query_params);
+
+        let tx_query = state.lock().unwrap();
+        let (tx_result, rx_result) = mpsc::channel::<IsochroneResponse>();
+
+        tx_query.send(Request::Isochrone((*query_params, tx_result))).unwrap();
         rx_result.recv().expect("routing engine crashed or hung up")
     });
 
@@ -128,6 +285,54 @@ fn here_query(query_params: Form<HereQuery>, state: State<Mutex<Sender<Request>>
     Json(result)
 }
 
+#[get("/alternatives?<query_params..>", format = "application/json")]
+fn alternatives(query_params: Form<AlternativesQuery>, state: State<Mutex<Sender<Request>>>) -> Json<Option<AlternativesResponse>> {
+    let result = report_time("Total Alternatives Request Time", || {
+        println!("Received Alternatives Query: {:?}", query_params);
+
+        let tx_query = state.lock().unwrap();
+        let (tx_result, rx_result) = mpsc::channel::<Option<AlternativesResponse>>();
+
+        tx_query.send(Request::Alternatives((*query_params, tx_result))).unwrap();
+        rx_result.recv().expect("routing engine crashed or hung up")
+    });
+
+    println!();
+    Json(result)
+}
+
+#[get("/profile?<query_params..>", format = "application/json")]
+fn profile(query_params: Form<ProfileQuery>, state: State<Mutex<Sender<Request>>>) -> Json<Option<ProfileResponse>> {
+    let result = report_time("Total Profile Request Time", || {
+        println!("Received Profile Query: {:?}", query_params);
+
+        let tx_query = state.lock().unwrap();
+        let (tx_result, rx_result) = mpsc::channel::<Option<ProfileResponse>>();
+
+        tx_query.send(Request::Profile((*query_params, tx_result))).unwrap();
+        rx_result.recv().expect("routing engine crashed or hung up")
+    });
+
+    println!();
+    Json(result)
+}
+
+#[post("/matrix", data = "<query>")]
+fn matrix(query: Json<MatrixQuery>, state: State<Mutex<Sender<Request>>>) -> Result<Json<MatrixResponse>, BadRequest<String>> {
+    let result = report_time("Total Matrix Request Time", || {
+        println!("Received Matrix Query: {} sources, {} targets", query.sources.len(), query.targets.len());
+
+        let tx_query = state.lock().unwrap();
+        let (tx_result, rx_result) = mpsc::channel::<Result<MatrixResponse, String>>();
+
+        tx_query.send(Request::Matrix((query.into_inner(), tx_result))).unwrap();
+        rx_result.recv().expect("routing engine crashed or hung up")
+    });
+
+    println!();
+    result.map(Json).map_err(|err| BadRequest(Some(err)))
+}
+
 #[derive(Debug)]
 struct SerializedWeight(Weight);
 
@@ -165,179 +370,991 @@ impl<'de> Deserialize<'de> for SerializedWeight {
 }
 
 #[post("/customize", data = "<updates>")]
-fn customize(updates: Json<Vec<(u64, bool, SerializedWeight)>>, state: State<Mutex<Sender<Request>>>) {
-    let tx_query = state.lock().unwrap();
-    tx_query.send(Request::Customize(updates.0)).expect("routing engine crashed or hung up");
+fn customize(updates: Json<Vec<(u64, bool, SerializedWeight)>>, state: State<Mutex<Sender<CustomizeRequest>>>) {
+    let tx_customize = state.lock().unwrap();
+    tx_customize.send(CustomizeRequest(updates.0)).expect("routing engine crashed or hung up");
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let (tx_query, rx_query) = mpsc::channel::<Request>();
+// A customization together with the weights it was customized with, including all applied updates.
+// The potentials for alternative routes need a perfect customization, so they are only computed
+// by the first worker answering an alternatives query with this metric and then shared by all workers.
+#[derive(Clone)]
+struct Metric<'c> {
+    customized: Arc<CustomizedBasic<'c, CCH>>,
+    travel_time: Arc<[Weight]>,
+    potentials: Arc<OnceLock<(SharedCCHPot<'c>, SharedCCHPot<'c>)>>,
+    // one for each vehicle profile, in the order of their names
+    vehicles: Arc<[VehicleMetric<'c>]>,
+}
+
+// The customization for a vehicle profile, with infinite travel times on the arcs the vehicle may not use.
+struct VehicleMetric<'c> {
+    customized: Arc<CustomizedBasic<'c, CCH>>,
+    travel_time: Arc<[Weight]>,
+}
+
+const NO_TD_VEHICLES: &str = "Departure times are not supported with vehicle profiles";
+
+fn unknown_vehicle(name: &str) -> String {
+    format!("Unknown vehicle profile {}", name)
+}
+
+// The local arc of a HERE link in the given direction, an error for link ids from clients which do not exist.
+fn local_link(id_mapper: &LinkIdMapper, link_id: u64, direction: bool) -> Result<EdgeId, String> {
+    let link_direction = if direction { LinkDirection::FromRef } else { LinkDirection::ToRef };
+    id_mapper
+        .here_to_local_link_id(link_id, link_direction)
+        .ok_or_else(|| format!("unknown link {}", link_id))
+}
+
+type CCHServer<'c> = Server<Arc<CustomizedBasic<'c, CCH>>>;
+
+// The query server and travel times for a query with the given vehicle profile.
+// Queries with a vehicle run on a separate server, so `server` always keeps the default metric.
+fn select_vehicle_server<'s, 'c>(
+    server: &'s mut CCHServer<'c>,
+    vehicle_server: &'s mut Option<CCHServer<'c>>,
+    metric: &Metric<'c>,
+    vehicle_names: &[String],
+    vehicle: Option<&str>,
+) -> Result<(&'s mut CCHServer<'c>, Arc<[Weight]>), String> {
+    let vehicle = match vehicle {
+        Some(vehicle) => vehicle,
+        None => return Ok((server, metric.travel_time.clone())),
+    };
+    let idx = vehicle_names.iter().position(|name| name == vehicle).ok_or_else(|| unknown_vehicle(vehicle))?;
+    let VehicleMetric { customized, travel_time } = &metric.vehicles[idx];
+    let server = vehicle_server.get_or_insert_with(|| Server::new(customized.clone()));
+    if !Arc::ptr_eq(server.customized(), customized) {
+        server.update(customized.clone());
+    }
+    Ok((server, travel_time.clone()))
+}
+
+// Update batches with more changed arcs than this are applied with a full customization.
+const MAX_INCREMENTAL_CUSTOMIZATION_UPDATES: usize = 10_000;
+
+// The most recent customization. Query workers pick up a new one before their next query.
+type SharedCustomization<'c> = RwLock<Metric<'c>>;
+
+// The time-dependent graph together with potentials from a CCH customized with its lower bounds.
+// Not affected by `/customize`.
+struct TimeDependentData<'c> {
+    graph: TDGraph,
+    potentials: CCHPotData<'c>,
+}
+
+const NO_TD_GRAPH: &str = "Departure times are only supported with a time-dependent graph";
+
+// Follow a path found by a time-dependent query and collect the time at which each node is reached
+// and the arcs used, the fastest one at that time for parallel arcs.
+fn td_path_timestamps(graph: &TDGraph, path: &[NodeId], departure: Timestamp) -> (Vec<Timestamp>, Vec<EdgeId>) {
+    let mut t = departure;
+    let mut timestamps = vec![t];
+    let mut edges = Vec::with_capacity(path.len().saturating_sub(1));
+    for nodes in path.windows(2) {
+        let (edge, arrival) = graph
+            .edge_indices(nodes[0], nodes[1])
+            .map(|EdgeIdT(edge)| (edge, t + graph.travel_time_function(edge).eval(t)))
+            .min_by_key(|&(_, arrival)| arrival)
+            .unwrap();
+        t = arrival;
+        timestamps.push(t);
+        edges.push(edge);
+    }
+    (timestamps, edges)
+}
+
+// The points on the closest arcs to some coordinates, among the arcs passing the filter.
+type ClosestArcs<'a> = dyn Fn((f32, f32), &dyn Fn(EdgeId) -> bool) -> Vec<EdgeSnap> + Sync + 'a;
+
+// Each query worker owns its own query server, that is its own distance and parent arrays,
+// but all of them share the customized metric.
+#[allow(clippy::too_many_arguments)]
+fn query_worker<'c>(
+    requests: &Mutex<Receiver<Request>>,
+    customization: &SharedCustomization<'c>,
+    graph: &BorrowedGraph,
+    id_mapper: Option<&LinkIdMapper>,
+    link_id_to_tail_mapper: &LinkIdToTailMapper,
+    td_data: Option<&TimeDependentData<'c>>,
+    catchup: Option<&CustomizedGraph<'c>>,
+    closest_node: &(dyn Fn((f32, f32)) -> NodeId + Sync),
+    nearest_node: &(dyn Fn((f32, f32)) -> NodeId + Sync),
+    closest_arcs: &ClosestArcs<'_>,
+    in_main_component: &(dyn Fn(NodeId) -> bool + Sync),
+    coords: &(dyn Fn(NodeId) -> (f32, f32) + Sync),
+    vehicle_names: &[String],
+) {
+    let mut current = customization.read().unwrap().clone();
+    let mut server = Server::new(current.customized.clone());
+    let mut vehicle_server = None;
+    let mut isochrone_server = isochrone::Server::new(current.customized.clone());
+    let mut td_server = td_data.map(|td_data| TDServer::new(&td_data.graph, td_data.potentials.forward_potential(), TDDijkstraOps::default()));
+    let mut profile_server = catchup.map(|catchup| ProfileServer::new(current.customized.cch, catchup));
+    // The penalty method works on its own copy of the graph, so it is built on the first alternatives query
+    // and only gets new weights when the metric changes.
+    let mut alternatives_server: Option<Penalty<SharedCCHPot<'c>>> = None;
+    let mut alternatives_outdated;
+    let mut pending = None;
+
+    loop {
+        let latest = customization.read().unwrap().clone();
+        if !Arc::ptr_eq(&latest.customized, &current.customized) {
+            server.update(latest.customized.clone());
+            isochrone_server.update(latest.customized.clone());
+            current = latest;
+        }
+        // The weights of the penalty method have to be replaced before the next alternatives query.
+        alternatives_outdated = true;
+        // The matrix server works on the customized graphs of the current metric, so it is built once for each customization.
+        let mut matrix_server = None;
 
-    let mut args = env::args();
-    args.next();
+        loop {
+            // The lock is only held while waiting for the next request, so queries run concurrently.
+            let request = match pending.take() {
+                Some(request) => request,
+                None => match requests.lock().unwrap().recv() {
+                    Ok(request) => request,
+                    Err(_) => return,
+                },
+            };
 
-    let arg = &args.next().ok_or(CliErr("No directory arg given"))?;
-    let path = Path::new(arg);
+            if !Arc::ptr_eq(&customization.read().unwrap().customized, &current.customized) {
+                pending = Some(request);
+                break;
+            }
+
+            match request {
+                Request::Geo((
+                    GeoQuery {
+                        from_lat,
+                        from_lng,
+                        to_lat,
+                        to_lng,
+                        departure,
+                        vehicle,
+                    },
+                    tx_result,
+                )) => {
+                    let result = match (departure, td_server.as_mut()) {
+                        (None, _) => {
+                            select_vehicle_server(&mut server, &mut vehicle_server, &current, vehicle_names, vehicle.as_deref()).map(|(server, travel_time)| {
+                                // arcs closed for the vehicle or by updates can not be used as endpoints
+                                let usable = |arc: EdgeId| travel_time[arc as usize] < INFINITY;
+                                let (sources, targets) = report_time("match arcs", || {
+                                    (closest_arcs((from_lat, from_lng), &usable), closest_arcs((to_lat, to_lng), &usable))
+                                });
+                                let metric = FirstOutGraph::new(graph.first_out(), graph.head(), &travel_time[..]);
+                                report_time("cch query", || mid_arc_query(server, &metric, &sources, &targets, coords))
+                            })
+                        }
+                        (Some(_), _) if vehicle.is_some() => Err(NO_TD_VEHICLES.to_string()),
+                        // time-dependent queries still start and end at the closest nodes
+                        (Some(departure), Some(td_server)) => Ok(report_time("td query", || {
+                            let (from, to) = report_time("match nodes", || (closest_node((from_lat, from_lng)), closest_node((to_lat, to_lng))));
+                            td_server.td_query(TDQuery { from, to, departure }).found().map(|mut result| {
+                                let path = result.node_path();
+                                let (timestamps, _) = td_path_timestamps(&td_data.unwrap().graph, &path, departure);
+                                GeoResponse {
+                                    distance: result.distance(),
+                                    path: path.iter().map(|&node| coords(node)).collect(),
+                                    arrival: timestamps.last().copied(),
+                                    timestamps: Some(timestamps),
+                                }
+                            })
+                        })),
+                        (Some(_), None) => Err(NO_TD_GRAPH.to_string()),
+                    };
+                    let result = result.and_then(|response| {
+                        response.ok_or_else(|| unroutable(in_main_component, nearest_node((from_lat, from_lng)), nearest_node((to_lat, to_lng))))
+                    });
+
+                    tx_result.send(result).unwrap();
+                }
+                Request::Here((
+                    HereQuery {
+                        from_link_id,
+                        from_direction,
+                        from_link_fraction,
+                        to_link_id,
+                        to_direction,
+                        to_link_fraction,
+                        departure,
+                        vehicle,
+                    },
+                    tx_result,
+                )) => {
+                    // the endpoint is only mounted when the HERE link id mapping was loaded
+                    let id_mapper = id_mapper.expect("HERE link id mapping not loaded");
+
+                    let links =
+                        local_link(id_mapper, from_link_id, from_direction).and_then(|from| Ok((from, local_link(id_mapper, to_link_id, to_direction)?)));
+                    let (from_link_local_id, to_link_local_id) = match links {
+                        Ok(links) => links,
+                        Err(err) => {
+                            tx_result.send(Err(err)).unwrap();
+                            continue;
+                        }
+                    };
+                    let from = graph.head()[from_link_local_id as usize];
+                    let to = link_id_to_tail_mapper.link_id_to_tail(to_link_local_id);
+
+                    let here_link = |link_id: EdgeId| {
+                        let (id, dir) = id_mapper.local_to_here_link_id(link_id);
+                        (id, dir == LinkDirection::FromRef)
+                    };
+
+                    let result = match (departure, td_server.as_mut()) {
+                        (None, _) => {
+                            select_vehicle_server(&mut server, &mut vehicle_server, &current, vehicle_names, vehicle.as_deref()).map(|(server, travel_time)| {
+                                let metric = FirstOutGraph::new(graph.first_out(), graph.head(), &travel_time[..]);
+                                // `from_link_fraction` is the part of the source link still ahead, `to_link_fraction` the part of the target link driven
+                                let query = MidEdgeQuery {
+                                    from: EdgePoint {
+                                        edge: from_link_local_id,
+                                        fraction: 1.0 - from_link_fraction,
+                                    },
+                                    to: EdgePoint {
+                                        edge: to_link_local_id,
+                                        fraction: to_link_fraction,
+                                    },
+                                };
+                                report_time("cch query", || {
+                                    server.mid_edge_query(&metric, query).found().map(|mut result| {
+                                        let edges = result.edge_path();
+                                        HereResponse {
+                                            distance: result.distance(),
+                                            path: edges.iter().map(|part| here_link(part.edge)).collect(),
+                                            fractions: Some(edges.iter().map(|part| (part.from_fraction, part.to_fraction)).collect()),
+                                            arrival: None,
+                                            timestamps: None,
+                                        }
+                                    })
+                                })
+                            })
+                        }
+                        (Some(_), _) if vehicle.is_some() => Err(NO_TD_VEHICLES.to_string()),
+                        (Some(departure), Some(td_server)) => Ok(report_time("td query", || {
+                            let td_graph = &td_data.unwrap().graph;
+                            // the query starts at the head of the source link and ends at the tail of the target link
+                            let start =
+                                departure + (from_link_fraction * td_graph.travel_time_function(from_link_local_id).eval(departure) as f32) as Timestamp;
+                            td_server.td_query(TDQuery { from, to, departure: start }).found().map(|mut result| {
+                                let (mut timestamps, edges) = td_path_timestamps(td_graph, &result.node_path(), start);
+                                let end = *timestamps.last().unwrap();
+                                let arrival = end + (to_link_fraction * td_graph.travel_time_function(to_link_local_id).eval(end) as f32) as Timestamp;
+                                timestamps.push(arrival);
+
+                                let path = once((from_link_id, from_direction))
+                                    .chain(edges.into_iter().map(here_link))
+                                    .chain(once((to_link_id, to_direction)))
+                                    .collect();
 
-    let first_out = Vec::load_from(path.join("first_out"))?;
-    let head = Vec::load_from(path.join("head"))?;
-    let travel_time = Vec::load_from(path.join("travel_time"))?;
+                                HereResponse {
+                                    distance: arrival - departure,
+                                    path,
+                                    fractions: None,
+                                    arrival: Some(arrival),
+                                    timestamps: Some(timestamps),
+                                }
+                            })
+                        })),
+                        (Some(_), None) => Err(NO_TD_GRAPH.to_string()),
+                    };
+                    let result = result.and_then(|response| response.ok_or_else(|| unroutable(in_main_component, from, to)));
+
+                    tx_result.send(result).unwrap();
+                }
+                Request::Isochrone((IsochroneQuery { lat, lng, budget }, tx_result)) => {
+                    let travel_time = &current.travel_time;
+                    let usable = |arc: EdgeId| travel_time[arc as usize] < INFINITY;
+                    let snaps = report_time("match arcs", || closest_arcs((lat, lng), &usable));
+                    let metric = FirstOutGraph::new(graph.first_out(), graph.head(), &travel_time[..]);
+
+                    let result = report_time("cch isochrone", || {
+                        // the search starts at the snapped points and leaves their arcs in either direction
+                        let sources: Vec<(NodeId, Weight)> = snaps
+                            .iter()
+                            .flat_map(|snap| {
+                                exits(
+                                    &metric,
+                                    EdgePoint {
+                                        edge: snap.arc,
+                                        fraction: snap.fraction,
+                                    },
+                                )
+                            })
+                            .collect();
+                        let reached = isochrone_server.isochrone_from(&sources, budget);
+                        let boundary = isochrone::boundary_arcs(&metric, &reached, budget)
+                            .into_iter()
+                            .map(|(EdgeIdT(edge), fraction)| {
+                                let tail = coords(link_id_to_tail_mapper.link_id_to_tail(edge));
+                                let head = coords(graph.head()[edge as usize]);
+                                let cut = (tail.0 + (head.0 - tail.0) * fraction as f32, tail.1 + (head.1 - tail.1) * fraction as f32);
+                                IsochroneBoundaryEdge { tail, head, cut }
+                            })
+                            .collect();
+                        let nodes = reached
+                            .into_iter()
+                            .map(|(node, distance)| IsochroneNode {
+                                coords: coords(node),
+                                distance,
+                            })
+                            .collect();
+                        IsochroneResponse { nodes, boundary }
+                    });
 
-    let lat = Vec::load_from(path.join("latitude"))?;
-    let lng = Vec::load_from(path.join("longitude"))?;
+                    tx_result.send(result).unwrap();
+                }
+                Request::Matrix((MatrixQuery { sources, targets }, tx_result)) => {
+                    // source HERE links are left at their head node and target links entered at their tail node
+                    let resolve = |location: &MatrixLocation, is_source: bool| -> Result<NodeId, String> {
+                        match *location {
+                            MatrixLocation::Geo { lat, lng } => Ok(closest_node((lat, lng))),
+                            MatrixLocation::Here { link_id, direction } => {
+                                let id_mapper = id_mapper.ok_or_else(|| "HERE link ids are not enabled".to_string())?;
+                                let link_direction = if direction { LinkDirection::FromRef } else { LinkDirection::ToRef };
+                                let link_local_id = id_mapper
+                                    .here_to_local_link_id(link_id, link_direction)
+                                    .ok_or_else(|| format!("non existing link {}", link_id))?;
+                                if is_source {
+                                    Ok(graph.link(link_local_id).node)
+                                } else {
+                                    Ok(link_id_to_tail_mapper.link_id_to_tail(link_local_id))
+                                }
+                            }
+                        }
+                    };
+
+                    let result = report_time("match nodes", || {
+                        let sources = sources.iter().map(|location| resolve(location, true)).collect::<Result<Vec<_>, _>>()?;
+                        let targets = targets.iter().map(|location| resolve(location, false)).collect::<Result<Vec<_>, _>>()?;
+                        Ok((sources, targets))
+                    })
+                    .map(|(sources, targets)| {
+                        report_time("cch matrix", || {
+                            let customized = &current.customized;
+                            let distances = matrix_server
+                                .get_or_insert_with(|| {
+                                    DistanceMatrixServer::new(customized.forward_graph(), customized.backward_graph(), customized.cch().node_order().clone())
+                                })
+                                .distances(&sources, &targets)
+                                .into_iter()
+                                .map(|row| row.into_iter().map(|dist| if dist < INFINITY { Some(dist) } else { None }).collect())
+                                .collect();
+                            MatrixResponse { distances }
+                        })
+                    });
 
-    let mut coords: Vec<NodeCoord> = lat
+                    tx_result.send(result).unwrap();
+                }
+                Request::Profile((
+                    ProfileQuery {
+                        from_lat,
+                        from_lng,
+                        to_lat,
+                        to_lng,
+                    },
+                    tx_result,
+                )) => {
+                    // the endpoint is only mounted when the CATCHUp customization was run
+                    let profile_server = profile_server.as_mut().expect("CATCHUp customization not available");
+                    let (from, to) = report_time("match nodes", || (closest_node((from_lat, from_lng)), closest_node((to_lat, to_lng))));
+
+                    let result = report_time("catchup profile", || {
+                        profile_server.profile(from, to).map(|profile| {
+                            // floating time-dependent times are in seconds
+                            let to_ms = |t: f64| t * 1000.0;
+                            let breakpoints = profile
+                                .breakpoints()
+                                .iter()
+                                .map(|point| (to_ms(f64::from(point.at)), to_ms(f64::from(point.val))))
+                                .collect();
+                            let paths = profile
+                                .paths
+                                .iter()
+                                .map(|path| ProfilePathResponse {
+                                    path: once(from)
+                                        .chain(path.edges.iter().map(|&edge| graph.head()[edge as usize]))
+                                        .map(coords)
+                                        .collect(),
+                                    valid: path
+                                        .valid
+                                        .iter()
+                                        .map(|valid| (to_ms(f64::from(valid.start)), to_ms(f64::from(valid.end))))
+                                        .collect(),
+                                    here_links: id_mapper.map(|id_mapper| {
+                                        path.edges
+                                            .iter()
+                                            .map(|&edge| {
+                                                let (id, dir) = id_mapper.local_to_here_link_id(edge);
+                                                (id, dir == LinkDirection::FromRef)
+                                            })
+                                            .collect()
+                                    }),
+                                })
+                                .collect();
+                            ProfileResponse { breakpoints, paths }
+                        })
+                    });
+
+                    tx_result.send(result).unwrap();
+                }
+                Request::Alternatives((
+                    AlternativesQuery {
+                        from_lat,
+                        from_lng,
+                        to_lat,
+                        to_lng,
+                        k,
+                    },
+                    tx_result,
+                )) => {
+                    let (from, to) = report_time("match nodes", || (closest_node((from_lat, from_lng)), closest_node((to_lat, to_lng))));
+                    let metric = FirstOutGraph::new(graph.first_out(), graph.head(), &current.travel_time[..]);
+
+                    let (forward_potential, backward_potential) = current
+                        .potentials
+                        .get_or_init(|| {
+                            report_time("alternatives potentials", || {
+                                CCHPotData::new(current.customized.cch, &metric).shared_potentials()
+                            })
+                        })
+                        .clone();
+                    let penalty = match &mut alternatives_server {
+                        Some(penalty) => {
+                            if alternatives_outdated {
+                                report_time("alternatives metric update", || {
+                                    penalty.set_metric(&metric, forward_potential, backward_potential)
+                                });
+                            }
+                            penalty
+                        }
+                        None => alternatives_server.insert(report_time("alternatives preprocessing", || {
+                            Penalty::new(&metric, forward_potential, backward_potential)
+                        })),
+                    };
+                    alternatives_outdated = false;
+
+                    let result = report_time("penalty alternatives", || {
+                        let routes = penalty.alternative_routes(Query { from, to }, k.unwrap_or(3))?;
+                        let routes = routes
+                            .into_iter()
+                            .map(|route| {
+                                let path = route.path;
+                                let here_links = id_mapper.map(|id_mapper| {
+                                    path.windows(2)
+                                        .map(|nodes| {
+                                            let EdgeIdT(link_id) = metric
+                                                .edge_indices(nodes[0], nodes[1])
+                                                .min_by_key(|&EdgeIdT(edge)| metric.weight()[edge as usize])
+                                                .unwrap();
+                                            let (id, dir) = id_mapper.local_to_here_link_id(link_id);
+                                            (id, dir == LinkDirection::FromRef)
+                                        })
+                                        .collect()
+                                });
+
+                                AlternativeRouteResponse {
+                                    distance: route.distance,
+                                    detour: route.detour,
+                                    sharing: route.sharing,
+                                    path: path.iter().map(|&node| coords(node)).collect(),
+                                    here_links,
+                                }
+                            })
+                            .collect();
+
+                        Some(AlternativesResponse { routes })
+                    });
+
+                    tx_result.send(result).unwrap();
+                }
+            }
+        }
+    }
+}
+
+// Why there is no route from `from` to `to`, naming the endpoint responsible if possible.
+fn unroutable(in_main_component: &dyn Fn(NodeId) -> bool, from: NodeId, to: NodeId) -> String {
+    if !in_main_component(from) {
+        "No route found: the source is not connected to the main road network".to_string()
+    } else if !in_main_component(to) {
+        "No route found: the target is not connected to the main road network".to_string()
+    } else {
+        "No route found: source and target are connected to the main road network, but not with the current weights".to_string()
+    }
+}
+
+// Route from a point on any of the source arcs to a point on any of the target arcs, with a single multi query.
+// The route may leave the source arc and enter the target arc in either direction, if the road has arcs in both.
+fn mid_arc_query<C: Customized>(
+    server: &mut Server<C>,
+    metric: &BorrowedGraph,
+    sources: &[EdgeSnap],
+    targets: &[EdgeSnap],
+    coords: &(dyn Fn(NodeId) -> (f32, f32) + Sync),
+) -> Option<GeoResponse> {
+    let points = |snaps: &[EdgeSnap]| -> Vec<EdgePoint> {
+        snaps
+            .iter()
+            .map(|snap| EdgePoint {
+                edge: snap.arc,
+                fraction: snap.fraction,
+            })
+            .collect()
+    };
+    server
+        .mid_edge_multi_query(metric, &points(sources), &points(targets))
+        .found()
+        .map(|mut result| {
+            let (source, target) = result.data().endpoints();
+            let path = once(sources[source].point)
+                .chain(result.node_path().into_iter().map(coords))
+                .chain(once(targets[target].point))
+                .collect();
+            GeoResponse {
+                distance: result.distance(),
+                path,
+                arrival: None,
+                timestamps: None,
+            }
+        })
+}
+
+// Customize the CCH for each vehicle profile.
+// Like for the default metric, small update batches are applied incrementally to the customizations of `previous`.
+fn customize_vehicles<'c>(
+    cch: &'c CCH,
+    first_out: &[EdgeId],
+    head: &[NodeId],
+    vehicles: &[VehicleProfile],
+    link_attributes: Option<&LinkAttributes>,
+    travel_time: &[Weight],
+    previous: Option<(&Metric<'c>, &[(NodeIdT, EdgeIdT)])>,
+) -> Arc<[VehicleMetric<'c>]> {
+    vehicles
         .iter()
-        .zip(lng.iter())
         .enumerate()
-        .map(|(node_id, (&lat, &lng))| NodeCoord {
-            node_id: node_id as NodeId,
-            coords: [f64::from(lat), f64::from(lng)],
+        .map(|(idx, vehicle)| {
+            let travel_time = vehicle.metric(link_attributes.expect("link attributes not loaded"), travel_time);
+            let metric = FirstOutGraph::new(first_out, head, &travel_time[..]);
+            let customized = match previous {
+                Some((previous, changed)) if changed.len() <= MAX_INCREMENTAL_CUSTOMIZATION_UPDATES => {
+                    let mut customized = CustomizedBasic::clone(&previous.vehicles[idx].customized);
+                    customize_incremental(&mut customized, &metric, changed);
+                    customized
+                }
+                _ => cch_customize(cch, &metric),
+            };
+            VehicleMetric {
+                customized: Arc::new(customized),
+                travel_time: travel_time.into(),
+            }
         })
-        .collect();
-    let tree = report_time("build kd tree", || Kdtree::new(&mut coords));
+        .collect()
+}
 
-    let link_id_mapping = BitVec::load_from(path.join("link_id_mapping"))?;
-    let link_id_mapping = InvertableRankSelectMap::new(RankSelectMap::new(link_id_mapping));
-    let here_rank_to_link_id = Vec::load_from(path.join("here_rank_to_link_id"))?;
-    let cch_order = NodeOrder::from_node_order(Vec::load_from(path.join("cch_perm"))?);
+// The graph the CCH is built on and customized with, the turn expanded graph when turns are enabled.
+fn cch_metric<'a>(
+    first_out: &'a [EdgeId],
+    head: &'a [NodeId],
+    travel_time: &'a [Weight],
+    turns: Option<&'a TurnExpandedGraph>,
+    turn_weights: &'a [Weight],
+) -> BorrowedGraph<'a> {
+    match turns {
+        Some(turns) => turns.graph(turn_weights),
+        None => FirstOutGraph::new(first_out, head, travel_time),
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let (tx_query, rx_query) = mpsc::channel::<Request>();
+    let (tx_customize, rx_customize) = mpsc::channel::<CustomizeRequest>();
+
+    let config = Config::from_args(env::args())?;
+    let path = config.data_dir.as_path();
+
+    if config.turn_restrictions && config.profiles {
+        return Err(Box::new(CliErr("Profiles are not supported with turn restrictions")));
+    }
+
+    // The topology and coordinates are never modified, so they are memory mapped and shared with other processes using the same graph.
+    // The weights change with `/customize` and are loaded.
+    let first_out: Mapped<EdgeId> = Mapped::map_from(path.join("first_out"))?;
+    let head: Mapped<NodeId> = Mapped::map_from(path.join("head"))?;
+    let mut travel_time = Vec::load_from(path.join(&config.metric))?;
+
+    let lat: Mapped<f32> = Mapped::map_from(path.join("latitude"))?;
+    let lng: Mapped<f32> = Mapped::map_from(path.join("longitude"))?;
+
+    // link geometry is optional, without it arcs are straight lines between their nodes
+    let spatial_index = if path.join("first_modelling_node").exists() {
+        let first_modelling_node: Vec<u32> = Vec::load_from(path.join("first_modelling_node"))?;
+        let modelling_node_latitude: Vec<f32> = Vec::load_from(path.join("modelling_node_latitude"))?;
+        let modelling_node_longitude: Vec<f32> = Vec::load_from(path.join("modelling_node_longitude"))?;
+        report_time("build spatial index", || {
+            SpatialIndex::with_geometry(
+                &first_out,
+                &head,
+                &lat,
+                &lng,
+                &first_modelling_node,
+                &modelling_node_latitude,
+                &modelling_node_longitude,
+            )
+        })
+    } else {
+        report_time("build spatial index", || SpatialIndex::new(&first_out, &head, &lat, &lng))
+    };
+
+    // snapping only considers the largest strongly connected component, as queries from or to other nodes mostly fail
+    let component: Vec<u32> = if path.join("strongly_connected_component").exists() {
+        Vec::load_from(path.join("strongly_connected_component"))?
+    } else {
+        report_time("strongly connected components", || {
+            strongly_connected_components(&UnweightedFirstOutGraph::new(&first_out[..], &head[..]))
+        })
+    };
+    if component.len() != first_out.len() - 1 {
+        return Err(Box::new(CliErr("Strongly connected components do not match the graph")));
+    }
+    let main_component = largest_component(&component);
+
+    let here_link_id_data = if config.here_link_ids {
+        let link_id_mapping = BitVec::load_from(path.join("link_id_mapping"))?;
+        let link_id_mapping = InvertableRankSelectMap::new(RankSelectMap::new(link_id_mapping));
+        let here_rank_to_link_id = Vec::load_from(path.join("here_rank_to_link_id"))?;
+        Some((link_id_mapping, here_rank_to_link_id))
+    } else {
+        None
+    };
+    // With turn restrictions, the CCH is built on the turn expanded graph and needs an order of its nodes, that is of the original arcs.
+    let forbidden_turns: Option<(Vec<EdgeId>, Vec<EdgeId>)> = if config.turn_restrictions {
+        Some((
+            Vec::load_from(path.join("forbidden_turn_from_arc"))?,
+            Vec::load_from(path.join("forbidden_turn_to_arc"))?,
+        ))
+    } else {
+        None
+    };
+    let (order_file, num_cch_nodes) = if config.turn_restrictions {
+        ("cch_exp_perm", head.len())
+    } else {
+        ("cch_perm", first_out.len() - 1)
+    };
+    let cch_perm: Vec<NodeId> = Vec::load_from(path.join(order_file))?;
+    if cch_perm.len() != num_cch_nodes {
+        return Err(Box::new(CliErr("CCH order does not match the graph")));
+    }
+    // snapshots are only valid for the exact files they were built from
+    let snapshot_dir = config.snapshot_dir.clone();
+    let snapshot_checksum = if snapshot_dir.is_some() {
+        report_time("input checksum", || match &forbidden_turns {
+            Some((from_arc, to_arc)) => snapshot::checksum(&[&first_out, &head, &cch_perm, &travel_time, from_arc, to_arc]),
+            None => snapshot::checksum(&[&first_out, &head, &cch_perm, &travel_time]),
+        })
+    } else {
+        0
+    };
+    let snapshot_interval = Duration::from_secs(config.snapshot_interval);
+    let cch_order = NodeOrder::from_node_order(cch_perm);
+    if config.profiles && !path.join("first_ipp_of_arc").exists() {
+        return Err(Box::new(CliErr("Profiles need a time-dependent graph")));
+    }
+    let floating_td_graph = if config.profiles {
+        Some(FloatingTDGraph::reconstruct_from(&path)?)
+    } else {
+        None
+    };
+    // the time-dependent queries do not know about turns
+    let td_graph = if path.join("first_ipp_of_arc").exists() && !config.turn_restrictions {
+        Some(TDGraph::reconstruct_from(&path)?)
+    } else {
+        None
+    };
+
+    // all vehicle profiles share the CCH, each with its own customization
+    let vehicle_names: Vec<String> = config.vehicle_profiles.keys().cloned().collect();
+    let vehicles: Vec<VehicleProfile> = config.vehicle_profiles.values().map(|vehicle| vehicle.profile()).collect::<Result<_, _>>()?;
+    let link_attributes = if vehicles.is_empty() {
+        None
+    } else {
+        Some(LinkAttributesReconstructor(head.len()).reconstruct_from(&path)?)
+    };
+
+    let customization_pool = rayon::ThreadPoolBuilder::new().num_threads(config.customization_threads).build()?;
+    let num_query_workers = config.query_workers;
 
     // all further preprocessing happening asynchronous
     thread::spawn(move || {
-        let id_mapper = LinkIdMapper::new(link_id_mapping, here_rank_to_link_id, head.len());
+        let id_mapper = here_link_id_data.map(|(link_id_mapping, here_rank_to_link_id)| LinkIdMapper::new(link_id_mapping, here_rank_to_link_id, head.len()));
+
+        let turns = forbidden_turns.map(|(from_arc, to_arc)| report_time("turn expansion", || TurnExpandedGraph::new(&first_out, &head, &from_arc, &to_arc)));
+        let mut turn_weights = turns.as_ref().map_or_else(Vec::new, |turns| turns.turn_weights(&travel_time));
+
+        let snapshot = snapshot_dir.as_deref().and_then(|dir| {
+            let cch_graph = cch_metric(&first_out, &head, &travel_time, turns.as_ref(), &turn_weights);
+            match snapshot::load_cch(dir, snapshot_checksum, &cch_graph, head.len()) {
+                Ok(Some(snapshot)) => Some(snapshot),
+                Ok(None) => {
+                    println!("No snapshot matching the input files in {}, starting from scratch", dir.display());
+                    None
+                }
+                Err(e) => {
+                    eprintln!("Failed to load snapshot from {}: {}", dir.display(), e);
+                    None
+                }
+            }
+        });
+        let warm_start = snapshot.is_some();
+        let cch = match snapshot {
+            Some((cch, snapshot_travel_time)) => {
+                // continue with all updates applied before the snapshot was taken
+                travel_time = snapshot_travel_time;
+                if let Some(turns) = &turns {
+                    turn_weights = turns.turn_weights(&travel_time);
+                }
+                cch
+            }
+            None => {
+                customization_pool.install(|| CCH::fix_order_and_build(&cch_metric(&first_out, &head, &travel_time, turns.as_ref(), &turn_weights), cch_order))
+            }
+        };
 
         let graph = FirstOutGraph::new(&first_out[..], &head[..], travel_time.clone());
         let link_id_to_tail_mapper = LinkIdToTailMapper::new(&graph);
+        let td_data = td_graph.map(|td_graph| {
+            let lower_bound: Vec<Weight> = (0..td_graph.num_arcs() as EdgeId)
+                .map(|edge_id| td_graph.travel_time_function(edge_id).lower_bound())
+                .collect();
+            let potentials = customization_pool.install(|| CCHPotData::new(&cch, &FirstOutGraph::new(&first_out[..], &head[..], &lower_bound[..])));
+            TimeDependentData { graph: td_graph, potentials }
+        });
+        let catchup = floating_td_graph.as_ref().map(|floating_td_graph| {
+            report_time("CATCHUp customization", || {
+                customization_pool.install(|| catchup_customize(&cch, floating_td_graph))
+            })
+        });
+        let snapshot_customization = snapshot_dir.as_deref().filter(|_| warm_start).and_then(|dir| {
+            snapshot::load_customization(dir, &cch)
+                .map_err(|e| eprintln!("Failed to load customization snapshot from {}: {}", dir.display(), e))
+                .ok()
+        });
+        let write_snapshot = |metric: &Metric| {
+            if let Some(dir) = &snapshot_dir {
+                let result = report_time("write snapshot", || {
+                    snapshot::save_customization(dir, snapshot_checksum, &metric.customized, &metric.travel_time)
+                });
+                if let Err(e) = result {
+                    eprintln!("Failed to write snapshot to {}: {}", dir.display(), e);
+                }
+            }
+        };
+        let mut snapshot_outdated = snapshot_dir.is_some() && snapshot_customization.is_none();
+        if let (Some(dir), false) = (&snapshot_dir, warm_start) {
+            if let Err(e) = snapshot::save_cch(dir, &cch) {
+                eprintln!("Failed to write snapshot to {}: {}", dir.display(), e);
+            }
+        }
 
-        let cch = CCH::fix_order_and_build(&graph, cch_order);
-        let server = Arc::new(Mutex::new(Server::new(cch_customize(&cch, &graph))));
+        let customization: SharedCustomization = RwLock::new(Metric {
+            customized: Arc::new(snapshot_customization.unwrap_or_else(|| {
+                customization_pool.install(|| cch_customize(&cch, &cch_metric(&first_out, &head, &travel_time, turns.as_ref(), &turn_weights)))
+            })),
+            travel_time: travel_time.clone().into(),
+            potentials: Arc::new(OnceLock::new()),
+            // vehicle profiles are not part of snapshots
+            vehicles: report_time("vehicle profile customization", || {
+                customization_pool.install(|| customize_vehicles(&cch, &first_out, &head, &vehicles, link_attributes.as_ref(), &travel_time, None))
+            }),
+        });
 
         let coords = |node: NodeId| -> (f32, f32) { (lat[node as usize], lng[node as usize]) };
+        let in_main_component = |node: NodeId| Some(component[node as usize]) == main_component;
+        let in_main_component_arc = |arc: EdgeId| in_main_component(link_id_to_tail_mapper.link_id_to_tail(arc)) && in_main_component(head[arc as usize]);
+        let closer_end = |snap: &EdgeSnap| -> NodeId {
+            if snap.fraction < 0.5 {
+                link_id_to_tail_mapper.link_id_to_tail(snap.arc)
+            } else {
+                head[snap.arc as usize]
+            }
+        };
+        // the closer end of the closest arc
         let closest_node = |(p_lat, p_lng): (f32, f32)| -> NodeId {
-            tree.nearest_search(&NodeCoord {
-                coords: [f64::from(p_lat), f64::from(p_lng)],
-                node_id: 0,
-            })
-            .node_id
+            let snaps = spatial_index.nearest_filtered(p_lat, p_lng, 0.0, in_main_component_arc);
+            closer_end(snaps.first().expect("no arcs in the main component"))
+        };
+        // the closer end of the closest arc in any component, to tell which endpoint of an unroutable query lies off the main road network
+        let nearest_node = |(p_lat, p_lng): (f32, f32)| -> NodeId { closer_end(&spatial_index.nearest(p_lat, p_lng).expect("no arcs in the graph")) };
+        let closest_arcs = |(p_lat, p_lng): (f32, f32), usable: &dyn Fn(EdgeId) -> bool| {
+            spatial_index.nearest_filtered(p_lat, p_lng, SNAP_TOLERANCE, |arc| in_main_component_arc(arc) && usable(arc))
         };
 
-        // Customization should run asynchronous to queries but needs all the data,
-        // Thus we create a scope here, so we can later spawn new threads
-        // without the risk of data going out of scope.
-        crossbeam_utils::thread::scope(|scope| {
-            for query_params in rx_query {
-                match query_params {
-                    Request::Geo((
-                        GeoQuery {
-                            from_lat,
-                            from_lng,
-                            to_lat,
-                            to_lng,
-                        },
-                        tx_result,
-                    )) => {
-                        let (from, to) = report_time("match nodes", || (closest_node((from_lat, from_lng)), closest_node((to_lat, to_lng))));
-
-                        let mut server = server.lock().unwrap();
-                        let result = report_time("cch query", || {
-                            server.query(Query { from, to }).found().map(|mut result| {
-                                let distance = result.distance();
-                                let path = result.node_path().iter().map(|&node| coords(node)).collect();
-                                GeoResponse { distance, path }
-                            })
-                        });
-
-                        tx_result.send(result).unwrap();
-                    }
-                    Request::Here((
-                        HereQuery {
-                            from_link_id,
-                            from_direction,
-                            from_link_fraction,
-                            to_link_id,
-                            to_direction,
-                            to_link_fraction,
-                        },
-                        tx_result,
-                    )) => {
-                        let from_link_direction = if from_direction { LinkDirection::FromRef } else { LinkDirection::ToRef };
-                        let from_link_local_id = id_mapper.here_to_local_link_id(from_link_id, from_link_direction).expect("non existing link");
-                        let from_link = graph.link(from_link_local_id);
-                        let from = from_link.node;
-
-                        let to_link_direction = if to_direction { LinkDirection::FromRef } else { LinkDirection::ToRef };
-                        let to_link_local_id = id_mapper.here_to_local_link_id(to_link_id, to_link_direction).expect("non existing link");
-                        let to_link = graph.link(to_link_local_id);
-                        let to = link_id_to_tail_mapper.link_id_to_tail(to_link_local_id);
-
-                        let mut server = server.lock().unwrap();
-                        let result = report_time("cch query", || {
-                            server.query(Query { from, to }).found().map(|mut result| {
-                                let distance = result.distance()
-                                    + (from_link_fraction * from_link.weight as f32) as u32
-                                    + (to_link_fraction * to_link.weight as f32) as u32;
-
-                                let path = result.node_path();
-                                let path_iter = path.iter();
-                                let mut second_node_iter = path_iter.clone();
-                                second_node_iter.next();
+        let rx_query = Mutex::new(rx_query);
 
-                                let path = once((from_link_id, from_direction))
-                                    .chain(
-                                        path_iter
-                                            .zip(second_node_iter)
-                                            .map(|(first_node, second_node)| {
-                                                graph
-                                                    .edge_indices(*first_node, *second_node)
-                                                    .min_by_key(|&EdgeIdT(edge)| graph.weight()[edge as usize])
-                                                    .unwrap()
-                                            })
-                                            .map(|EdgeIdT(link_id)| {
-                                                let (id, dir) = id_mapper.local_to_here_link_id(link_id);
-                                                (id, dir == LinkDirection::FromRef)
-                                            }),
-                                    )
-                                    .chain(once((to_link_id, to_direction)))
-                                    .collect();
+        // The query workers need all the data, thus we create a scope here,
+        // so we can spawn the worker threads without the risk of data going out of scope.
+        crossbeam_utils::thread::scope(|scope| {
+            for _ in 0..num_query_workers {
+                let rx_query = &rx_query;
+                let customization = &customization;
+                let graph = graph.borrowed();
+                let id_mapper = id_mapper.as_ref();
+                let link_id_to_tail_mapper = &link_id_to_tail_mapper;
+                let td_data = td_data.as_ref();
+                let catchup = catchup.as_ref();
+                let closest_node = &closest_node;
+                let nearest_node = &nearest_node;
+                let closest_arcs = &closest_arcs;
+                let in_main_component = &in_main_component;
+                let coords = &coords;
+                let vehicle_names = &vehicle_names[..];
+                if let Some(turns) = &turns {
+                    scope.spawn(move |_| {
+                        turns::query_worker(
+                            rx_query,
+                            customization,
+                            &graph,
+                            turns,
+                            id_mapper,
+                            closest_node,
+                            nearest_node,
+                            in_main_component,
+                            coords,
+                        )
+                    });
+                    continue;
+                }
+                scope.spawn(move |_| {
+                    query_worker(
+                        rx_query,
+                        customization,
+                        &graph,
+                        id_mapper,
+                        link_id_to_tail_mapper,
+                        td_data,
+                        catchup,
+                        closest_node,
+                        nearest_node,
+                        closest_arcs,
+                        in_main_component,
+                        coords,
+                        vehicle_names,
+                    )
+                });
+            }
 
-                                HereResponse { distance, path }
-                            })
-                        });
+            // Customization runs asynchronous to the queries.
+            // Queries use the previous metric until the new one is ready.
+            // Updates are carried over into future customizations.
+            // Small batches only recompute the affected part of the previous customization.
+            // With a snapshot directory, the latest customization is written to disk at most once per snapshot interval.
+            let mut last_snapshot = None;
+            loop {
+                if snapshot_outdated && last_snapshot.is_none_or(|time: Instant| time.elapsed() >= snapshot_interval) {
+                    let metric = customization.read().unwrap().clone();
+                    write_snapshot(&metric);
+                    last_snapshot = Some(Instant::now());
+                    snapshot_outdated = false;
+                }
 
-                        tx_result.send(result).unwrap();
-                    }
-                    Request::Customize(updates) => {
-                        let server = server.clone();
-                        let mut travel_time = travel_time.clone();
-                        let id_mapper = &id_mapper;
-                        let cch = &cch;
-                        let first_out = &first_out;
-                        let head = &head;
-
-                        // asynchronous customization
-                        scope.spawn(move |_| {
-                            for (here_link_id, is_from_ref, weight) in updates.into_iter() {
-                                if is_from_ref {
-                                    if let Some(link_idx) = id_mapper.here_to_local_link_id(here_link_id, LinkDirection::FromRef) {
-                                        travel_time[link_idx as usize] = weight.0
-                                    }
-                                } else if let Some(link_idx) = id_mapper.here_to_local_link_id(here_link_id, LinkDirection::ToRef) {
-                                    travel_time[link_idx as usize] = weight.0
-                                }
-                            }
-                            let customized = cch_customize(&cch, &FirstOutGraph::new(&first_out[..], &head[..], travel_time));
-                            server.lock().unwrap().update(customized);
-                        });
+                // Only an outdated snapshot needs a wake up, at the end of the current interval.
+                let received = if snapshot_outdated {
+                    let since_snapshot = last_snapshot.map_or(snapshot_interval, |time| time.elapsed());
+                    rx_customize.recv_timeout(snapshot_interval.saturating_sub(since_snapshot))
+                } else {
+                    rx_customize.recv().map_err(|_| RecvTimeoutError::Disconnected)
+                };
+                let updates = match received {
+                    Ok(CustomizeRequest(updates)) => updates,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+                let mut changed = Vec::new();
+                for (link_id, is_from_ref, weight) in updates.into_iter() {
+                    let link_idx = match &id_mapper {
+                        Some(id_mapper) => {
+                            let direction = if is_from_ref { LinkDirection::FromRef } else { LinkDirection::ToRef };
+                            id_mapper.here_to_local_link_id(link_id, direction)
+                        }
+                        // without HERE data, updates refer directly to arc ids in the graph
+                        None => EdgeId::try_from(link_id).ok().filter(|&link_idx| (link_idx as usize) < travel_time.len()),
+                    };
+                    if let Some(link_idx) = link_idx {
+                        if travel_time[link_idx as usize] != weight.0 {
+                            travel_time[link_idx as usize] = weight.0;
+                            changed.push((NodeIdT(link_id_to_tail_mapper.link_id_to_tail(link_idx)), EdgeIdT(link_idx)));
+                        }
                     }
                 }
+                if changed.is_empty() {
+                    continue;
+                }
+
+                if let Some(turns) = &turns {
+                    changed = turns.update_turn_weights(&travel_time, &mut turn_weights, &changed);
+                }
+                let metric = cch_metric(&first_out, &head, &travel_time, turns.as_ref(), &turn_weights);
+                let vehicles = {
+                    let previous = customization.read().unwrap().clone();
+                    customization_pool.install(|| {
+                        customize_vehicles(
+                            &cch,
+                            &first_out,
+                            &head,
+                            &vehicles,
+                            link_attributes.as_ref(),
+                            &travel_time,
+                            Some((&previous, &changed)),
+                        )
+                    })
+                };
+                let customized = if changed.len() <= MAX_INCREMENTAL_CUSTOMIZATION_UPDATES {
+                    let mut customized = CustomizedBasic::clone(&customization.read().unwrap().customized);
+                    customize_incremental(&mut customized, &metric, &changed);
+                    customized
+                } else {
+                    customization_pool.install(|| cch_customize(&cch, &metric))
+                };
+                *customization.write().unwrap() = Metric {
+                    customized: Arc::new(customized),
+                    travel_time: travel_time.clone().into(),
+                    potentials: Arc::new(OnceLock::new()),
+                    vehicles,
+                };
+                snapshot_outdated = snapshot_dir.is_some();
             }
         })
         .unwrap();
     });
 
-    rocket::ignite()
-        .mount("/", routes![index, files, query, here_query, customize])
+    let rocket_config = rocket::config::Config::build(rocket::config::Environment::active()?)
+        .address(config.address.clone())
+        .port(config.port)
+        .finalize()?;
+
+    // the other endpoints work on nodes of the original graph and do not know about turns
+    let mut routes = if config.turn_restrictions && config.here_link_ids {
+        routes![index, files, query, here_query, customize]
+    } else if config.turn_restrictions {
+        routes![index, files, query, customize]
+    } else if config.here_link_ids {
+        routes![index, files, query, here_query, isochrone_query, alternatives, matrix, customize]
+    } else {
+        routes![index, files, query, isochrone_query, alternatives, matrix, customize]
+    };
+    if config.profiles {
+        routes.extend(routes![profile]);
+    }
+
+    rocket::custom(rocket_config)
+        .mount("/", routes)
         .manage(Mutex::new(tx_query))
+        .manage(Mutex::new(tx_customize))
         .launch();
 
     Ok(())
@@ -0,0 +1,89 @@
+// Snapshots of the CCH and the latest customization, so the server can warm-start without redoing the preprocessing.
+//
+// Layout of the snapshot directory:
+// - `cch/`: the CCH and its node order
+// - `customized/`: the latest customization
+// - `travel_time`: the metric of this customization, including all applied updates
+// - `snapshot_info`: format version and a checksum of the input files the snapshot was built from
+//
+// `snapshot_info` is removed before and written after everything else,
+// so an interrupted write leaves no snapshot rather than a broken one.
+
+use std::{
+    fs,
+    io::{ErrorKind, Result},
+    path::Path,
+};
+
+use rust_road_router::{
+    algo::customizable_contraction_hierarchy::{CCHReconstrctor, CustomizedBasic, CCH, CCHT},
+    datastr::graph::*,
+    io::*,
+};
+
+// Increment whenever the layout or the contents of a snapshot change.
+const SNAPSHOT_VERSION: u64 = 1;
+
+// 64 bit FNV-1a over the contents of all given files.
+// Stable across builds, unlike the hashers of the standard library.
+pub fn checksum(data: &[&dyn DataBytes]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for bytes in data {
+        for &byte in bytes.data_bytes() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+// Load CCH and metric if there is a snapshot matching `checksum`.
+// `graph` is the graph the CCH was built on, `num_arcs` the number of arcs of the metric.
+// They differ when the CCH was built on the turn expanded graph.
+// Returns `Ok(None)` if there is no snapshot or it belongs to different input files.
+pub fn load_cch(dir: &Path, checksum: u64, graph: &impl EdgeIdGraph, num_arcs: usize) -> Result<Option<(CCH, Vec<Weight>)>> {
+    let info: Vec<u64> = match Vec::load_from(dir.join("snapshot_info")) {
+        Ok(info) => info,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if info[..] != [SNAPSHOT_VERSION, checksum] {
+        return Ok(None);
+    }
+
+    let cch = CCHReconstrctor(graph).reconstruct_from(&dir.join("cch"))?;
+    let travel_time: Vec<Weight> = Vec::load_from(dir.join("travel_time"))?;
+    if travel_time.len() != num_arcs {
+        return Err(std::io::Error::new(ErrorKind::InvalidData, "snapshot metric does not match the graph"));
+    }
+    Ok(Some((cch, travel_time)))
+}
+
+pub fn load_customization<'c>(dir: &Path, cch: &'c CCH) -> Result<CustomizedBasic<'c, CCH>> {
+    cch.reconstruct_from(&dir.join("customized"))
+}
+
+// Only needed once, the CCH does not change with the metric.
+pub fn save_cch(dir: &Path, cch: &CCH) -> Result<()> {
+    invalidate(dir)?;
+    let cch_dir = dir.join("cch");
+    fs::create_dir_all(&cch_dir)?;
+    cch.deconstruct_to(&cch_dir)?;
+    // the CCH only stores its graph, the order has to be stored separately
+    cch.node_order().deconstruct_to(&cch_dir)
+}
+
+pub fn save_customization(dir: &Path, checksum: u64, customized: &CustomizedBasic<CCH>, travel_time: &[Weight]) -> Result<()> {
+    invalidate(dir)?;
+    customized.deconstruct_to(&dir.join("customized"))?;
+    travel_time.write_to(&dir.join("travel_time"))?;
+    vec![SNAPSHOT_VERSION, checksum].write_to(&dir.join("snapshot_info"))
+}
+
+fn invalidate(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    match fs::remove_file(dir.join("snapshot_info")) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
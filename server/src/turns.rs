@@ -0,0 +1,279 @@
+// Turn-aware routing on the turn expanded graph, that is the line graph of the road network.
+// Each node of the line graph is an arc of the original graph and each arc of the line graph is an allowed turn.
+// Forbidden turns and U-turns, except at dead ends, are left out, all other turns cost nothing,
+// so the weight of a turn is the travel time of the arc it starts at.
+// Queries between original nodes become queries between the arcs leaving the source and the arcs entering the target.
+
+use super::*;
+
+pub struct TurnExpandedGraph {
+    first_out: Vec<EdgeId>,
+    head: Vec<EdgeId>,
+    // original arcs ending at each node
+    first_in: Vec<EdgeId>,
+    incoming: Vec<EdgeId>,
+}
+
+impl TurnExpandedGraph {
+    // `forbidden_turn_from_arc` and `forbidden_turn_to_arc` have to be sorted lexicographically, as in the RoutingKit format.
+    pub fn new(first_out: &[EdgeId], head: &[NodeId], forbidden_turn_from_arc: &[EdgeId], forbidden_turn_to_arc: &[EdgeId]) -> Self {
+        let graph = FirstOutGraph::new(first_out, head, vec![0; head.len()]);
+        let (exp_first_out, exp_head, _) = turn_restricted_line_graph(&graph, forbidden_turn_from_arc, forbidden_turn_to_arc).decompose();
+
+        let mut first_in = vec![0; graph.num_nodes() + 1];
+        for &node in head {
+            first_in[node as usize + 1] += 1;
+        }
+        for node in 0..graph.num_nodes() {
+            first_in[node + 1] += first_in[node];
+        }
+        let mut next_in = first_in.clone();
+        let mut incoming = vec![0; head.len()];
+        for (arc, &node) in head.iter().enumerate() {
+            incoming[next_in[node as usize] as usize] = arc as EdgeId;
+            next_in[node as usize] += 1;
+        }
+
+        TurnExpandedGraph {
+            first_out: exp_first_out,
+            head: exp_head,
+            first_in,
+            incoming,
+        }
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.first_out.len() - 1
+    }
+
+    // The line graph with the given turn weights.
+    pub fn graph<'a>(&'a self, turn_weights: &'a [Weight]) -> BorrowedGraph<'a> {
+        FirstOutGraph::new(&self.first_out[..], &self.head[..], turn_weights)
+    }
+
+    pub fn turn_weights(&self, travel_time: &[Weight]) -> Vec<Weight> {
+        (0..self.num_nodes())
+            .flat_map(|arc| (self.first_out[arc]..self.first_out[arc + 1]).map(move |_| travel_time[arc]))
+            .collect()
+    }
+
+    // Apply weight changes of original arcs to the turn weights.
+    // Returns the changed turns with their tails in the line graph, ready for an incremental customization.
+    pub fn update_turn_weights(&self, travel_time: &[Weight], turn_weights: &mut [Weight], changed_arcs: &[(NodeIdT, EdgeIdT)]) -> Vec<(NodeIdT, EdgeIdT)> {
+        let mut changed_turns = Vec::new();
+        for &(_, EdgeIdT(arc)) in changed_arcs {
+            for turn in self.first_out[arc as usize]..self.first_out[arc as usize + 1] {
+                turn_weights[turn as usize] = travel_time[arc as usize];
+                changed_turns.push((NodeIdT(arc), EdgeIdT(turn)));
+            }
+        }
+        changed_turns
+    }
+
+    // The arcs which may be entered after `arc`.
+    fn turns_from(&self, arc: EdgeId) -> &[EdgeId] {
+        &self.head[self.first_out[arc as usize] as usize..self.first_out[arc as usize + 1] as usize]
+    }
+
+    fn incoming_arcs(&self, node: NodeId) -> &[EdgeId] {
+        &self.incoming[self.first_in[node as usize] as usize..self.first_in[node as usize + 1] as usize]
+    }
+}
+
+const NO_TD_TURNS: &str = "Departure times are not supported with turn restrictions";
+
+// Shortest path in the line graph from any of the `sources` to any of the `targets`, both with offsets.
+// The line graph distance does not include the target arc itself, so the offsets of the targets have to.
+fn line_graph_query<S: MultiQueryServer>(
+    server: &mut S,
+    sources: impl IntoIterator<Item = (EdgeId, Weight)>,
+    targets: impl IntoIterator<Item = (EdgeId, Weight)>,
+) -> Option<(Weight, Vec<EdgeId>)> {
+    let mut result = server.multi_query(&MultiQuery::new(sources, targets)).found()?;
+    Some((result.distance(), result.node_path()))
+}
+
+// Shortest path from a point on `from_link` to a point on `to_link`, like a mid-edge query on the original graph.
+// `from_fraction` is the part of the source link still ahead, `to_fraction` the part of the target link driven, as in the plain mode.
+// The source link is left through any allowed turn, so a target behind the source on the same link is reached by leaving the link and coming back.
+// Returns the distance and the original arcs of the path, starting with the source and ending with the target link.
+fn mid_link_query<S: MultiQueryServer>(
+    server: &mut S,
+    turns: &TurnExpandedGraph,
+    travel_time: &[Weight],
+    (from_link, from_fraction): (EdgeId, f32),
+    (to_link, to_fraction): (EdgeId, f32),
+) -> Option<(Weight, Vec<EdgeId>)> {
+    let ahead = partial_weight(travel_time[from_link as usize], from_fraction);
+    let routed = line_graph_query(
+        server,
+        turns.turns_from(from_link).iter().map(|&arc| (arc, ahead)),
+        once((to_link, partial_weight(travel_time[to_link as usize], to_fraction))),
+    )
+    .map(|(distance, arcs)| (distance, once(from_link).chain(arcs).collect()));
+
+    // on the same link, a target ahead of the source can be reached without leaving the link
+    let direct = Some((
+        partial_weight(travel_time[from_link as usize], to_fraction - (1.0 - from_fraction)),
+        vec![from_link],
+    ))
+    .filter(|&(distance, _)| from_link == to_link && 1.0 - from_fraction <= to_fraction && distance < INFINITY);
+
+    routed.into_iter().chain(direct).min_by_key(|&(distance, _)| distance)
+}
+
+// Like `query_worker`, but all queries run on a CCH of the turn expanded graph.
+// Only `/query` and `/here_query` are available in this mode.
+#[allow(clippy::too_many_arguments)]
+pub fn query_worker<'c>(
+    requests: &Mutex<Receiver<Request>>,
+    customization: &SharedCustomization<'c>,
+    graph: &BorrowedGraph,
+    turns: &TurnExpandedGraph,
+    id_mapper: Option<&LinkIdMapper>,
+    closest_node: &(dyn Fn((f32, f32)) -> NodeId + Sync),
+    nearest_node: &(dyn Fn((f32, f32)) -> NodeId + Sync),
+    in_main_component: &(dyn Fn(NodeId) -> bool + Sync),
+    coords: &(dyn Fn(NodeId) -> (f32, f32) + Sync),
+) {
+    let mut current = customization.read().unwrap().clone();
+    let mut server = Server::new(current.customized.clone());
+
+    loop {
+        // The lock is only held while waiting for the next request, so queries run concurrently.
+        let request = match requests.lock().unwrap().recv() {
+            Ok(request) => request,
+            Err(_) => return,
+        };
+
+        let latest = customization.read().unwrap().clone();
+        if !Arc::ptr_eq(&latest.customized, &current.customized) {
+            server.update(latest.customized.clone());
+            current = latest;
+        }
+        let travel_time = &current.travel_time;
+
+        match request {
+            Request::Geo((
+                GeoQuery {
+                    from_lat,
+                    from_lng,
+                    to_lat,
+                    to_lng,
+                    departure,
+                    vehicle,
+                },
+                tx_result,
+            )) => {
+                let (from, to) = report_time("match nodes", || (closest_node((from_lat, from_lng)), closest_node((to_lat, to_lng))));
+
+                // vehicle profiles are not available with turn restrictions
+                let result = match (departure, vehicle) {
+                    (Some(_), _) => Err(NO_TD_TURNS.to_string()),
+                    (None, Some(vehicle)) => Err(unknown_vehicle(&vehicle)),
+                    (None, None) if from == to => Ok(GeoResponse {
+                        distance: 0,
+                        path: vec![coords(from)],
+                        arrival: None,
+                        timestamps: None,
+                    }),
+                    (None, None) => report_time("turn expanded cch query", || {
+                        let sources = graph.neighbor_edge_indices(from).map(|arc| (arc, 0));
+                        let targets = turns.incoming_arcs(to).iter().map(|&arc| (arc, travel_time[arc as usize]));
+                        line_graph_query(&mut server, sources, targets).map(|(distance, arcs)| {
+                            let path = once(from).chain(arcs.iter().map(|&arc| graph.head()[arc as usize])).map(coords).collect();
+                            GeoResponse {
+                                distance,
+                                path,
+                                arrival: None,
+                                timestamps: None,
+                            }
+                        })
+                    })
+                    .ok_or_else(|| unroutable(in_main_component, nearest_node((from_lat, from_lng)), nearest_node((to_lat, to_lng)))),
+                };
+
+                tx_result.send(result).unwrap();
+            }
+            Request::Here((
+                HereQuery {
+                    from_link_id,
+                    from_direction,
+                    from_link_fraction,
+                    to_link_id,
+                    to_direction,
+                    to_link_fraction,
+                    departure,
+                    vehicle,
+                },
+                tx_result,
+            )) => {
+                // the endpoint is only mounted when the HERE link id mapping was loaded
+                let id_mapper = id_mapper.expect("HERE link id mapping not loaded");
+
+                let links = local_link(id_mapper, from_link_id, from_direction).and_then(|from| Ok((from, local_link(id_mapper, to_link_id, to_direction)?)));
+                let (from_link, to_link) = match links {
+                    Ok(links) => links,
+                    Err(err) => {
+                        tx_result.send(Err(err)).unwrap();
+                        continue;
+                    }
+                };
+
+                let result = match (departure, vehicle) {
+                    (Some(_), _) => Err(NO_TD_TURNS.to_string()),
+                    (None, Some(vehicle)) => Err(unknown_vehicle(&vehicle)),
+                    (None, None) => report_time("turn expanded cch query", || {
+                        mid_link_query(&mut server, turns, travel_time, (from_link, from_link_fraction), (to_link, to_link_fraction)).map(|(distance, arcs)| {
+                            let path = arcs
+                                .into_iter()
+                                .map(|arc| {
+                                    let (id, dir) = id_mapper.local_to_here_link_id(arc);
+                                    (id, dir == LinkDirection::FromRef)
+                                })
+                                .collect();
+
+                            HereResponse {
+                                distance,
+                                path,
+                                fractions: None,
+                                arrival: None,
+                                timestamps: None,
+                            }
+                        })
+                    })
+                    .ok_or_else(|| unroutable(in_main_component, graph.head()[from_link as usize], link_id_to_tail(graph.first_out(), to_link))),
+                };
+
+                tx_result.send(result).unwrap();
+            }
+            _ => unreachable!("only /query and /here_query are mounted with turn restrictions"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_road_router::algo::dijkstra::{DefaultOps, Server as DijkServer};
+
+    #[test]
+    fn mid_link_query_leaves_the_link_for_targets_behind_the_source() {
+        // one way triangle 0 -> 1 -> 2 -> 0, each link takes 20
+        let first_out = [0, 1, 2, 3];
+        let head = [1, 2, 0];
+        let travel_time = [20, 20, 20];
+        let turns = TurnExpandedGraph::new(&first_out, &head, &[], &[]);
+        let turn_weights = turns.turn_weights(&travel_time);
+        let mut server = DijkServer::<_, DefaultOps>::new(turns.graph(&turn_weights));
+
+        // the source is in the middle of link 0, a target ahead of it on the same link is reached directly
+        assert_eq!(mid_link_query(&mut server, &turns, &travel_time, (0, 0.5), (0, 0.75)), Some((5, vec![0])));
+        // a target behind it can only be reached by going around the triangle
+        assert_eq!(
+            mid_link_query(&mut server, &turns, &travel_time, (0, 0.5), (0, 0.25)),
+            Some((55, vec![0, 1, 2, 0]))
+        );
+        assert_eq!(mid_link_query(&mut server, &turns, &travel_time, (0, 0.5), (2, 0.5)), Some((40, vec![0, 1, 2])));
+    }
+}
@@ -1,10 +1,18 @@
-use rust_road_router::{cli::CliErr, io::*};
-use std::{env, error::Error};
+use rust_road_router::{
+    cli::CliErr,
+    io::{header::Header, *},
+};
+use std::{env, error::Error, fs::File};
 
 fn main() -> Result<(), Box<dyn Error>> {
     let mut args = env::args().skip(1);
-    match (args.next(), args.next()) {
-        (Some(data_type), Some(ref input)) => match data_type.as_ref() {
+    let args = match (args.next(), args.next()) {
+        (Some(input), None) => Some((header_data_type(&input)?, input)),
+        (Some(data_type), Some(input)) => Some((data_type, input)),
+        _ => None,
+    };
+    match args {
+        Some((data_type, ref input)) => match data_type.as_ref() {
             "i8" | "int8" => print_values(Vec::<i8>::load_from(input)?),
             "u8" | "uint8" => print_values(Vec::<u8>::load_from(input)?),
             "i16" | "int16" => print_values(Vec::<i16>::load_from(input)?),
@@ -30,9 +38,11 @@ fn main() -> Result<(), Box<dyn Error>> {
 
 fn print_usage() {
     eprintln!(
-        "Usage: decode_vector data_type input_vector_file
+        "Usage: decode_vector [data_type] input_vector_file
 
-Reads binary data from input_vector_file and writes the data to the standard output. data_type can be one of
+Reads binary data from input_vector_file and writes the data to the standard output.
+data_type may be omitted if the file has a header, the type from the header is used then.
+For files with a header, the data is validated against it. data_type can be one of
 * i8
 * u8
 * i16
@@ -48,6 +58,18 @@ Reads binary data from input_vector_file and writes the data to the standard out
     );
 }
 
+// The element type stored in the header of the file
+fn header_data_type(input: &str) -> Result<String, Box<dyn Error>> {
+    match Header::read(&mut File::open(input)?)? {
+        Some(header) if header.layout.element_type != ElementType::Other => Ok(header.layout.element_type.name().to_string()),
+        Some(_) => Err(Box::new(CliErr("File contains elements of a compound type, can't decode them"))),
+        None => {
+            print_usage();
+            Err(Box::new(CliErr("File has no header, data_type required")))
+        }
+    }
+}
+
 use std::fmt::Display;
 
 fn print_values<T>(values: Vec<T>)
@@ -2,20 +2,21 @@ use rust_road_router::{cli::CliErr, io::*};
 use std::{env, error::Error};
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let mut args = env::args().skip(1);
+    let mut args = env::args().skip(1).peekable();
+    let with_header = args.next_if(|arg| arg == "--header").is_some();
     match (args.next(), args.next()) {
         (Some(data_type), Some(ref output)) => {
             match data_type.as_ref() {
-                "i8" | "int8" => parse_input::<i8>()?.write_to(output)?,
-                "u8" | "uint8" => parse_input::<u8>()?.write_to(output)?,
-                "i16" | "int16" => parse_input::<i16>()?.write_to(output)?,
-                "u16" | "uint16" => parse_input::<u16>()?.write_to(output)?,
-                "i32" | "int32" => parse_input::<i32>()?.write_to(output)?,
-                "u32" | "uint32" => parse_input::<u32>()?.write_to(output)?,
-                "i64" | "int64" => parse_input::<i64>()?.write_to(output)?,
-                "u64" | "uint64" => parse_input::<u64>()?.write_to(output)?,
-                "f32" | "float32" => parse_input::<f32>()?.write_to(output)?,
-                "f64" | "float64" => parse_input::<f64>()?.write_to(output)?,
+                "i8" | "int8" => write(parse_input::<i8>()?, output, with_header)?,
+                "u8" | "uint8" => write(parse_input::<u8>()?, output, with_header)?,
+                "i16" | "int16" => write(parse_input::<i16>()?, output, with_header)?,
+                "u16" | "uint16" => write(parse_input::<u16>()?, output, with_header)?,
+                "i32" | "int32" => write(parse_input::<i32>()?, output, with_header)?,
+                "u32" | "uint32" => write(parse_input::<u32>()?, output, with_header)?,
+                "i64" | "int64" => write(parse_input::<i64>()?, output, with_header)?,
+                "u64" | "uint64" => write(parse_input::<u64>()?, output, with_header)?,
+                "f32" | "float32" => write(parse_input::<f32>()?, output, with_header)?,
+                "f64" | "float64" => write(parse_input::<f64>()?, output, with_header)?,
                 _ => {
                     print_usage();
                     return Err(Box::new(CliErr("Invalid data type")));
@@ -31,9 +32,9 @@ fn main() -> Result<(), Box<dyn Error>> {
 }
 
 fn print_usage() {
-    eprintln!("Usage: encode_vector data_type output_vector_file
+    eprintln!("Usage: encode_vector [--header] data_type output_vector_file
 
-Reads textual data from the standard input and writes it in a binary format to output_vector_file. The input data should be one data element per line. The data is only written once an end of file is encountered on the input. data_type can be one of
+Reads textual data from the standard input and writes it in a binary format to output_vector_file. The input data should be one data element per line. The data is only written once an end of file is encountered on the input. With --header, the file is prefixed with a header recording the type, length and checksum of the data. data_type can be one of
 * i8
 * u8
 * i16
@@ -48,6 +49,14 @@ Reads textual data from the standard input and writes it in a binary format to o
 ");
 }
 
+fn write<T: Copy>(values: Vec<T>, output: &str, with_header: bool) -> std::io::Result<()> {
+    if with_header {
+        values.write_with_header_to(&output)
+    } else {
+        values.write_to(&output)
+    }
+}
+
 use std::str::FromStr;
 
 fn parse_input<T>() -> Result<Vec<T>, Box<dyn Error>>
@@ -0,0 +1,60 @@
+use rust_road_router::{
+    cli::CliErr,
+    io::{manifest::MANIFEST_FILE, *},
+};
+use std::{env, error::Error, path::Path};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args().skip(1);
+    match (args.next(), args.next()) {
+        (Some(command), Some(ref dir)) => {
+            let dir = Path::new(dir);
+            match command.as_ref() {
+                "create" => {
+                    let mut files: Vec<String> = args.collect();
+                    if files.is_empty() {
+                        for entry in std::fs::read_dir(dir)? {
+                            let entry = entry?;
+                            if entry.file_type()?.is_file() && entry.file_name() != MANIFEST_FILE {
+                                files.push(entry.file_name().into_string().map_err(|_| CliErr("Non UTF-8 file name"))?);
+                            }
+                        }
+                    }
+
+                    let mut manifest = Manifest::read_from_dir(dir)?.unwrap_or_default();
+                    for file in &files {
+                        manifest.add_file(dir, file)?;
+                    }
+                    manifest.write_to_dir(dir)?;
+                    Ok(())
+                }
+                "verify" => {
+                    let manifest = Manifest::read_from_dir(dir)?.ok_or(CliErr("No manifest in directory"))?;
+                    manifest.verify(dir)?;
+                    println!("{} files ok", manifest.files.len());
+                    Ok(())
+                }
+                _ => {
+                    print_usage();
+                    Err(Box::new(CliErr("Invalid command")))
+                }
+            }
+        }
+        _ => {
+            print_usage();
+            Err(Box::new(CliErr("Invalid arguments")))
+        }
+    }
+}
+
+fn print_usage() {
+    eprintln!(
+        "Usage: manifest create directory [file...]
+       manifest verify directory
+
+create records the length and checksum of the given files (default: all files in the directory) in the manifest of the directory.
+Once a directory has a manifest, the listed files are checked against it whenever they are loaded.
+verify checks all files listed in the manifest of the directory.
+"
+    );
+}
@@ -0,0 +1,26 @@
+use rust_road_router::{algo::strongly_connected_components::*, cli::CliErr, datastr::graph::*, io::*};
+
+use std::{env, error::Error, path::Path};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let arg = &env::args().skip(1).next().ok_or(CliErr("No graph directory arg given"))?;
+    let path = Path::new(arg);
+
+    let graph = UnweightedOwnedGraph::reconstruct_from(&path)?;
+
+    let component = strongly_connected_components(&graph);
+    let num_components = component.iter().max().map_or(0, |&max| max as usize + 1);
+    if let Some(largest) = largest_component(&component) {
+        let largest_size = component.iter().filter(|&&c| c == largest).count();
+        println!(
+            "{} strongly connected components, the largest one contains {} of {} nodes",
+            num_components,
+            largest_size,
+            graph.num_nodes()
+        );
+    }
+
+    component.write_to(&path.join("strongly_connected_component"))?;
+
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use rust_road_router::cli::CliErr;
+use std::{env, error::Error, path::Path};
+use utils::validate_graph::validate_graph;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args().skip(1);
+    let dir = match args.next() {
+        Some(dir) => dir,
+        None => {
+            print_usage();
+            return Err(Box::new(CliErr("No directory arg given")));
+        }
+    };
+    let weight_files: Vec<String> = args.collect();
+    let weight_files: Vec<&str> = if weight_files.is_empty() {
+        vec!["travel_time", "geo_distance"]
+    } else {
+        weight_files.iter().map(String::as_str).collect()
+    };
+
+    let report = validate_graph(Path::new(&dir), &weight_files);
+    println!("{}", report);
+    if report.passed() {
+        Ok(())
+    } else {
+        Err(Box::new(CliErr("Graph is inconsistent")))
+    }
+}
+
+fn print_usage() {
+    eprintln!(
+        "Usage: validate_graph graph_directory [weight_file...]
+
+Checks a graph in the RoutingKit format for consistency: first_out and head, the given weight files (default: travel_time and geo_distance),
+latitude and longitude, cch_perm, the time-dependent travel time functions and forbidden turns. Files which do not exist are skipped.
+Prints the number of violations of each check with a few example offenders.
+"
+    );
+}
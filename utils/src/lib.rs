@@ -1,3 +1,5 @@
+pub mod validate_graph;
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -0,0 +1,457 @@
+//! Consistency checks for graph directories in the RoutingKit format.
+//!
+//! `validate_graph` loads whichever of the known files exist in a directory and checks their invariants.
+//! Every check records the number of violations and a few example offenders, so broken data can be found without a debugger.
+
+use rust_road_router::{
+    datastr::graph::{
+        time_dependent::{period, Timestamp},
+        *,
+    },
+    io::*,
+};
+use std::{fmt, path::Path};
+
+/// Maximum number of example offenders recorded per check
+const MAX_EXAMPLES: usize = 5;
+
+/// Result of a single check
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: String,
+    pub num_checked: usize,
+    pub num_violations: usize,
+    pub examples: Vec<String>,
+}
+
+impl Check {
+    fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            num_checked: 0,
+            num_violations: 0,
+            examples: Vec::new(),
+        }
+    }
+
+    // Count one checked item and record it as a violation if `ok` is false.
+    // The description is only built for the first few violations.
+    fn expect(&mut self, ok: bool, describe: impl FnOnce() -> String) {
+        self.num_checked += 1;
+        if !ok {
+            self.num_violations += 1;
+            if self.examples.len() < MAX_EXAMPLES {
+                self.examples.push(describe());
+            }
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.num_violations == 0
+    }
+}
+
+/// All checks run on a directory
+#[derive(Debug, Clone, Default)]
+pub struct ValidationReport {
+    pub checks: Vec<Check>,
+    /// Known files which do not exist in the directory
+    pub skipped: Vec<String>,
+}
+
+impl ValidationReport {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(Check::passed)
+    }
+
+    pub fn num_violations(&self) -> usize {
+        self.checks.iter().map(|check| check.num_violations).sum()
+    }
+
+    fn check(&mut self, check: Check) {
+        self.checks.push(check);
+    }
+
+    // Load the file `name` if it exists, recording missing files and load errors.
+    fn load<T: Load>(&mut self, dir: &Path, name: &str) -> Option<T> {
+        let path = dir.join(name);
+        if !path.exists() {
+            self.skipped.push(name.to_string());
+            return None;
+        }
+        let mut check = Check::new(format!("{} loads", name));
+        let result = T::load_from(&path);
+        if let Err(e) = &result {
+            check.expect(false, || e.to_string());
+        } else {
+            check.expect(true, String::new);
+        }
+        self.check(check);
+        result.ok()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name_width = self.checks.iter().map(|check| check.name.len()).max().unwrap_or(0);
+        for check in &self.checks {
+            if check.passed() {
+                writeln!(f, "{:width$}  ok ({} checked)", check.name, check.num_checked, width = name_width)?;
+            } else {
+                writeln!(
+                    f,
+                    "{:width$}  FAILED ({} of {} violated)",
+                    check.name,
+                    check.num_violations,
+                    check.num_checked,
+                    width = name_width
+                )?;
+                for example in &check.examples {
+                    writeln!(f, "{:width$}    {}", "", example, width = name_width)?;
+                }
+            }
+        }
+        if !self.skipped.is_empty() {
+            writeln!(f, "skipped (not present): {}", self.skipped.join(", "))?;
+        }
+        write!(
+            f,
+            "{} checks, {} failed, {} violations",
+            self.checks.len(),
+            self.checks.iter().filter(|check| !check.passed()).count(),
+            self.num_violations()
+        )
+    }
+}
+
+/// Run all checks on the graph in `dir`.
+/// `first_out` and `head` are required, all other files are checked if they exist.
+/// `weight_files` are the names of the weight vectors to check, for example `travel_time` and `geo_distance`.
+pub fn validate_graph(dir: &Path, weight_files: &[&str]) -> ValidationReport {
+    let mut report = ValidationReport::default();
+
+    let first_out: Option<Vec<EdgeId>> = report.load(dir, "first_out");
+    let head: Option<Vec<NodeId>> = report.load(dir, "head");
+    let (first_out, head) = match (first_out, head) {
+        (Some(first_out), Some(head)) => (first_out, head),
+        _ => return report,
+    };
+
+    check_adjacency_array(&mut report, "first_out", &first_out, head.len(), "head");
+    let n = first_out.len().saturating_sub(1);
+    let m = head.len();
+
+    let mut check = Check::new("head in range");
+    for (arc, &node) in head.iter().enumerate() {
+        check.expect((node as usize) < n, || format!("arc {}: head {} >= num_nodes {}", arc, node, n));
+    }
+    report.check(check);
+
+    for &name in weight_files {
+        if let Some(weights) = report.load::<Vec<Weight>>(dir, name) {
+            check_weights(&mut report, name, &weights, m);
+        }
+    }
+
+    let latitude: Option<Vec<f32>> = report.load(dir, "latitude");
+    let longitude: Option<Vec<f32>> = report.load(dir, "longitude");
+    if let Some(latitude) = latitude {
+        check_coordinates(&mut report, "latitude", &latitude, n, 90.0);
+    }
+    if let Some(longitude) = longitude {
+        check_coordinates(&mut report, "longitude", &longitude, n, 180.0);
+    }
+
+    if let Some(cch_perm) = report.load::<Vec<NodeId>>(dir, "cch_perm") {
+        check_permutation(&mut report, "cch_perm", &cch_perm, n);
+    }
+
+    let first_ipp_of_arc: Option<Vec<u32>> = report.load(dir, "first_ipp_of_arc");
+    let ipp_departure_time: Option<Vec<Timestamp>> = report.load(dir, "ipp_departure_time");
+    let ipp_travel_time: Option<Vec<Weight>> = report.load(dir, "ipp_travel_time");
+    if let (Some(first_ipp_of_arc), Some(ipp_departure_time), Some(ipp_travel_time)) = (first_ipp_of_arc, ipp_departure_time, ipp_travel_time) {
+        check_travel_time_functions(&mut report, &first_ipp_of_arc, &ipp_departure_time, &ipp_travel_time, m);
+    }
+
+    let forbidden_turn_from_arc: Option<Vec<EdgeId>> = report.load(dir, "forbidden_turn_from_arc");
+    let forbidden_turn_to_arc: Option<Vec<EdgeId>> = report.load(dir, "forbidden_turn_to_arc");
+    if let (Some(from_arc), Some(to_arc)) = (forbidden_turn_from_arc, forbidden_turn_to_arc) {
+        check_forbidden_turns(&mut report, &from_arc, &to_arc, &head, &first_out);
+    }
+
+    report
+}
+
+// Invariants of a `first_out` style index array into a vector with `target_len` elements.
+fn check_adjacency_array(report: &mut ValidationReport, name: &str, first: &[u32], target_len: usize, target_name: &str) {
+    let mut check = Check::new(format!("{} bounds", name));
+    check.expect(!first.is_empty(), || format!("{} is empty", name));
+    if let Some(&start) = first.first() {
+        check.expect(start == 0, || format!("{}[0] is {} instead of 0", name, start));
+    }
+    if let Some(&end) = first.last() {
+        check.expect(end as usize == target_len, || {
+            format!("last entry of {} is {} but {} has {} elements", name, end, target_name, target_len)
+        });
+    }
+    report.check(check);
+
+    let mut check = Check::new(format!("{} monotone", name));
+    for (i, window) in first.windows(2).enumerate() {
+        check.expect(window[0] <= window[1], || {
+            format!("{}[{}] = {} > {}[{}] = {}", name, i, window[0], name, i + 1, window[1])
+        });
+    }
+    report.check(check);
+}
+
+fn check_weights(report: &mut ValidationReport, name: &str, weights: &[Weight], m: usize) {
+    let mut check = Check::new(format!("{} length", name));
+    check.expect(weights.len() == m, || format!("{} elements but {} arcs", weights.len(), m));
+    report.check(check);
+
+    let mut check = Check::new(format!("{} positive", name));
+    for (arc, &weight) in weights.iter().enumerate() {
+        check.expect(weight > 0, || format!("arc {}: weight 0", arc));
+    }
+    report.check(check);
+
+    let mut check = Check::new(format!("{} below infinity", name));
+    for (arc, &weight) in weights.iter().enumerate() {
+        check.expect(weight < INFINITY, || format!("arc {}: weight {} >= {}", arc, weight, INFINITY));
+    }
+    report.check(check);
+}
+
+fn check_coordinates(report: &mut ValidationReport, name: &str, values: &[f32], n: usize, max_abs: f32) {
+    let mut check = Check::new(format!("{} length", name));
+    check.expect(values.len() == n, || format!("{} elements but {} nodes", values.len(), n));
+    report.check(check);
+
+    let mut check = Check::new(format!("{} range", name));
+    for (node, &value) in values.iter().enumerate() {
+        check.expect(value.is_finite() && value.abs() <= max_abs, || {
+            format!("node {}: {} outside of [-{}, {}]", node, value, max_abs, max_abs)
+        });
+    }
+    report.check(check);
+}
+
+fn check_permutation(report: &mut ValidationReport, name: &str, perm: &[NodeId], n: usize) {
+    let mut check = Check::new(format!("{} length", name));
+    check.expect(perm.len() == n, || format!("{} elements but {} nodes", perm.len(), n));
+    report.check(check);
+
+    let mut check = Check::new(format!("{} permutation", name));
+    let mut seen = vec![false; perm.len()];
+    for (rank, &node) in perm.iter().enumerate() {
+        let in_range = (node as usize) < seen.len();
+        let valid = in_range && !seen[node as usize];
+        check.expect(valid, || {
+            if in_range {
+                format!("rank {}: node {} occurs twice", rank, node)
+            } else {
+                format!("rank {}: node {} out of range", rank, node)
+            }
+        });
+        if valid {
+            seen[node as usize] = true;
+        }
+    }
+    report.check(check);
+}
+
+fn check_travel_time_functions(report: &mut ValidationReport, first_ipp_of_arc: &[u32], departure_time: &[Timestamp], travel_time: &[Weight], m: usize) {
+    let mut check = Check::new("first_ipp_of_arc length");
+    check.expect(first_ipp_of_arc.len() == m + 1, || {
+        format!("{} elements but {} arcs", first_ipp_of_arc.len(), m)
+    });
+    report.check(check);
+    check_adjacency_array(report, "first_ipp_of_arc", first_ipp_of_arc, departure_time.len(), "ipp_departure_time");
+
+    let mut check = Check::new("ipp_travel_time length");
+    check.expect(travel_time.len() == departure_time.len(), || {
+        format!("{} travel times but {} departure times", travel_time.len(), departure_time.len())
+    });
+    report.check(check);
+
+    let mut non_empty = Check::new("travel time functions non empty");
+    let mut sorted = Check::new("ipp_departure_time sorted within period");
+    let mut fifo = Check::new("travel time functions FIFO");
+    let num_ipps = std::cmp::min(departure_time.len(), travel_time.len());
+    for (arc, range) in first_ipp_of_arc.windows(2).enumerate() {
+        // broken index ranges are already reported above
+        if range[0] > range[1] || range[1] as usize > num_ipps {
+            continue;
+        }
+        let range = range[0] as usize..range[1] as usize;
+        non_empty.expect(!range.is_empty(), || format!("arc {}: no interpolation points", arc));
+        if range.is_empty() {
+            continue;
+        }
+        let dts = &departure_time[range.clone()];
+        let tts = &travel_time[range];
+
+        let in_period = dts.iter().all(|&dt| dt < period());
+        let increasing = dts.windows(2).all(|dts| dts[0] < dts[1]);
+        sorted.expect(in_period && increasing, || format!("arc {}: departure times {:?}", arc, dts));
+        if !(in_period && increasing) {
+            continue;
+        }
+
+        // arrival times may not decrease, including the wrap around from the last point to the first one in the next period
+        let arrival = |i: usize| dts[i] as u64 + tts[i] as u64;
+        let violation = (0..dts.len() - 1)
+            .find(|&i| arrival(i) > arrival(i + 1))
+            .or_else(|| Some(dts.len() - 1).filter(|&last| arrival(last) > arrival(0) + period() as u64));
+        fifo.expect(violation.is_none(), || {
+            let i = violation.unwrap();
+            let next = (i + 1) % dts.len();
+            format!(
+                "arc {}: departing at {} arrives at {}, departing at {} arrives at {}",
+                arc,
+                dts[i],
+                arrival(i),
+                dts[next],
+                arrival(next)
+            )
+        });
+    }
+    report.check(non_empty);
+    report.check(sorted);
+    report.check(fifo);
+}
+
+fn check_forbidden_turns(report: &mut ValidationReport, from_arc: &[EdgeId], to_arc: &[EdgeId], head: &[NodeId], first_out: &[EdgeId]) {
+    let mut check = Check::new("forbidden turns length");
+    check.expect(from_arc.len() == to_arc.len(), || {
+        format!("{} from arcs but {} to arcs", from_arc.len(), to_arc.len())
+    });
+    report.check(check);
+
+    let turns: Vec<_> = from_arc.iter().copied().zip(to_arc.iter().copied()).collect();
+    let m = head.len();
+
+    let mut check = Check::new("forbidden turns in range");
+    for (i, &(from, to)) in turns.iter().enumerate() {
+        check.expect((from as usize) < m && (to as usize) < m, || {
+            format!("turn {}: {} -> {} with {} arcs", i, from, to, m)
+        });
+    }
+    report.check(check);
+
+    let mut check = Check::new("forbidden turns sorted");
+    for (i, window) in turns.windows(2).enumerate() {
+        check.expect(window[0] <= window[1], || format!("turn {}: {:?} before {:?}", i, window[0], window[1]));
+    }
+    report.check(check);
+
+    // a turn is only possible if the second arc starts where the first one ends
+    let mut check = Check::new("forbidden turns connected");
+    for (i, &(from, to)) in turns.iter().enumerate() {
+        if (from as usize) < m && (to as usize) < m {
+            let via = head[from as usize] as usize;
+            let connected = via + 1 < first_out.len() && (first_out[via]..first_out[via + 1]).contains(&to);
+            check.expect(connected, || {
+                format!("turn {}: arc {} ends at node {} but arc {} does not start there", i, from, via, to)
+            });
+        }
+    }
+    report.check(check);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // A graph directory in the temp dir which is removed again when the test ends
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        // A consistent graph with three nodes and four arcs: 0 -> 1, 1 -> 2, 1 -> 0 and 2 -> 0
+        fn consistent(name: &str) -> Self {
+            let dir = TestDir(std::env::temp_dir().join(format!("rrr_validate_{}_{}", name, std::process::id())));
+            std::fs::create_dir_all(&dir.0).unwrap();
+            dir.write("first_out", &vec![0u32, 1, 3, 4]);
+            dir.write("head", &vec![1u32, 2, 0, 0]);
+            dir.write("travel_time", &vec![1u32, 2, 3, 4]);
+            dir.write("latitude", &vec![49.0f32, 49.1, 49.2]);
+            dir.write("longitude", &vec![8.4f32, 8.5, 8.6]);
+            dir.write("cch_perm", &vec![2u32, 0, 1]);
+            dir.write("first_ipp_of_arc", &vec![0u32, 1, 3, 4, 5]);
+            dir.write("ipp_departure_time", &vec![0u32, 0, 100, 0, 0]);
+            dir.write("ipp_travel_time", &vec![5u32, 10, 20, 3, 4]);
+            dir.write("forbidden_turn_from_arc", &vec![0u32]);
+            dir.write("forbidden_turn_to_arc", &vec![2u32]);
+            dir
+        }
+
+        fn write<T: Store>(&self, name: &str, data: &T) {
+            data.write_to(&self.0.join(name)).unwrap();
+        }
+
+        fn failed_checks(&self) -> Vec<String> {
+            let report = validate_graph(&self.0, &["travel_time"]);
+            report.checks.into_iter().filter(|check| !check.passed()).map(|check| check.name).collect()
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn consistent_graph_passes() {
+        let dir = TestDir::consistent("ok");
+        let report = validate_graph(&dir.0, &["travel_time"]);
+        assert!(report.passed(), "{}", report);
+        assert!(report.skipped.is_empty());
+    }
+
+    #[test]
+    fn head_out_of_range() {
+        let dir = TestDir::consistent("head");
+        dir.write("head", &vec![1u32, 2, 0, 3]);
+        assert_eq!(dir.failed_checks(), vec!["head in range"]);
+    }
+
+    #[test]
+    fn first_out_not_monotone() {
+        let dir = TestDir::consistent("first_out");
+        dir.write("first_out", &vec![0u32, 3, 1, 4]);
+        assert!(dir.failed_checks().contains(&"first_out monotone".to_string()));
+    }
+
+    #[test]
+    fn weights_of_wrong_length() {
+        let dir = TestDir::consistent("weights");
+        dir.write("travel_time", &vec![1u32, 2, 3]);
+        assert_eq!(dir.failed_checks(), vec!["travel_time length"]);
+    }
+
+    #[test]
+    fn travel_time_function_not_fifo() {
+        let dir = TestDir::consistent("fifo");
+        // departing at 0 arrives at 200, departing later at 100 arrives earlier at 110
+        dir.write("ipp_travel_time", &vec![5u32, 200, 10, 3, 4]);
+        assert_eq!(dir.failed_checks(), vec!["travel time functions FIFO"]);
+    }
+
+    #[test]
+    fn cch_perm_not_a_permutation() {
+        let dir = TestDir::consistent("perm");
+        dir.write("cch_perm", &vec![2u32, 0, 0]);
+        assert_eq!(dir.failed_checks(), vec!["cch_perm permutation"]);
+    }
+
+    #[test]
+    fn forbidden_turn_between_unconnected_arcs() {
+        let dir = TestDir::consistent("turns");
+        // arc 0 ends at node 1, arc 3 starts at node 2
+        dir.write("forbidden_turn_to_arc", &vec![3u32]);
+        assert_eq!(dir.failed_checks(), vec!["forbidden turns connected"]);
+    }
+}